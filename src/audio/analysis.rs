@@ -0,0 +1,186 @@
+use std::f32::consts::{PI, TAU};
+
+/// Loudness of the low, middle and high frequencies, each from 0 to 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BandLevels {
+    pub bass: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+impl BandLevels {
+    fn lerp(self, other: BandLevels, t: f32) -> BandLevels {
+        BandLevels {
+            bass: self.bass + (other.bass - self.bass) * t,
+            mid: self.mid + (other.mid - self.mid) * t,
+            high: self.high + (other.high - self.high) * t,
+        }
+    }
+}
+
+/// Band levels of a whole track, one every `HOP` samples, computed once
+/// when the track is loaded.
+///
+/// Each band is scaled so its loudest frame is 1, then follows the music
+/// with an instant rise and a smooth fall, which is what looks good when it
+/// drives the visuals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandEnvelopes {
+    sample_rate: u32,
+    levels: Vec<BandLevels>,
+}
+
+impl BandEnvelopes {
+    /// FFT size, about 46 ms at 44.1 kHz.
+    pub const WINDOW: usize = 2048;
+    pub const HOP: usize = 512;
+    /// Upper edges of the bass and mid bands in Hz, the highs are everything above.
+    pub const BASS_MAX: f32 = 250.0;
+    pub const MID_MAX: f32 = 2000.0;
+    /// Seconds a level takes to fall to about a third after a peak.
+    pub const RELEASE: f32 = 0.15;
+
+    pub fn analyze(samples: &[f32], sample_rate: u32) -> Self {
+        let window: Vec<f32> = (0..Self::WINDOW)
+            .map(|i| 0.5 - 0.5 * (TAU * i as f32 / Self::WINDOW as f32).cos())
+            .collect();
+        let bin_hz = sample_rate as f32 / Self::WINDOW as f32;
+        let mut re = vec![0.0; Self::WINDOW];
+        let mut im = vec![0.0; Self::WINDOW];
+
+        let frames = samples.len().div_ceil(Self::HOP);
+        let mut energies: Vec<[f32; 3]> = Vec::with_capacity(frames);
+        for frame in 0..frames {
+            // centered on the frame time, zeros past the ends
+            let start = (frame * Self::HOP) as isize - Self::WINDOW as isize / 2;
+            for (i, w) in window.iter().enumerate() {
+                let index = start + i as isize;
+                re[i] = match usize::try_from(index).ok().and_then(|index| samples.get(index)) {
+                    Some(sample) => sample * w,
+                    None => 0.0,
+                };
+                im[i] = 0.0;
+            }
+            fft(&mut re, &mut im);
+
+            let mut energy = [0.0; 3];
+            for bin in 1..Self::WINDOW / 2 {
+                let hz = bin as f32 * bin_hz;
+                let band = match hz {
+                    hz if hz < Self::BASS_MAX => 0,
+                    hz if hz < Self::MID_MAX => 1,
+                    _ => 2,
+                };
+                energy[band] += re[bin] * re[bin] + im[bin] * im[bin];
+            }
+            energies.push(energy.map(f32::sqrt));
+        }
+
+        let mut peaks = [0.0f32; 3];
+        for energy in &energies {
+            for band in 0..3 {
+                peaks[band] = peaks[band].max(energy[band]);
+            }
+        }
+        let fall = (-(Self::HOP as f32) / (sample_rate as f32 * Self::RELEASE)).exp();
+        let mut level = [0.0f32; 3];
+        let levels = energies
+            .iter()
+            .map(|energy| {
+                for band in 0..3 {
+                    let target = if peaks[band] > 0.0 { energy[band] / peaks[band] } else { 0.0 };
+                    level[band] = target.max(level[band] * fall);
+                }
+                BandLevels { bass: level[0], mid: level[1], high: level[2] }
+            })
+            .collect();
+        Self { sample_rate, levels }
+    }
+
+    /// Levels `time` seconds into the track, between the two nearest frames.
+    pub fn at(&self, time: f32) -> BandLevels {
+        let Some(last) = self.levels.len().checked_sub(1) else {
+            return BandLevels::default();
+        };
+        let frame = (time.max(0.0) * self.sample_rate as f32 / Self::HOP as f32).min(last as f32);
+        let index = frame.floor() as usize;
+        let next = (index + 1).min(last);
+        self.levels[index].lerp(self.levels[next], frame - index as f32)
+    }
+}
+
+/// In-place radix-2 FFT, the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+    // bit reversed order
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fft_finds_the_frequency() {
+        let n = 64;
+        let mut re: Vec<f32> = (0..n).map(|i| (TAU * 5.0 * i as f32 / n as f32).cos()).collect();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+        let magnitude = |bin: usize| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt();
+        assert!((magnitude(5) - n as f32 / 2.0).abs() < 1e-3);
+        assert!((magnitude(n - 5) - n as f32 / 2.0).abs() < 1e-3);
+        assert!((0..n).filter(|&bin| bin != 5 && bin != n - 5).all(|bin| magnitude(bin) < 1e-3));
+    }
+
+    #[test]
+    fn bands_follow_the_music() {
+        // one second of a 60 Hz hum, then one second of a 5 kHz whine
+        let rate = 44100;
+        let samples: Vec<f32> = (0..2 * rate)
+            .map(|i| {
+                let hz = if i < rate { 60.0 } else { 5000.0 };
+                (TAU * hz * i as f32 / rate as f32).sin() * 0.5
+            })
+            .collect();
+        let envelopes = BandEnvelopes::analyze(&samples, rate as u32);
+        let hum = envelopes.at(0.5);
+        let whine = envelopes.at(1.5);
+        assert!(hum.bass > 0.9 && hum.high < 0.1, "{:?}", hum);
+        assert!(whine.high > 0.9 && whine.bass < 0.1, "{:?}", whine);
+        // the hum fades out instead of stopping
+        let after = envelopes.at(1.0 + BandEnvelopes::RELEASE * 0.5);
+        assert!(after.bass > 0.3 && after.bass < 0.9, "{:?}", after);
+        assert_eq!(envelopes.at(100.0), envelopes.at(2.0));
+        assert_eq!(BandEnvelopes::analyze(&[], rate as u32).at(0.0), BandLevels::default());
+    }
+}
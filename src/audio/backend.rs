@@ -0,0 +1,152 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Where mixed samples go. Samples are mono, in `[-1, 1]`.
+pub trait AudioBackend {
+    fn sample_rate(&self) -> u32;
+    fn write(&mut self, samples: &[f32]) -> io::Result<()>;
+    /// Flushes everything written so far, nothing is written after this.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Drops all samples, for when there is nowhere to play them.
+pub struct NullBackend {
+    pub sample_rate: u32,
+}
+
+impl AudioBackend for NullBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, _samples: &[f32]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A sample as 16-bit PCM, clipped to `[-1, 1]`.
+pub(super) fn pcm16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+const WAV_HEADER_LEN: u32 = 44;
+
+fn wav_header(sample_rate: u32, sample_count: u32) -> [u8; WAV_HEADER_LEN as usize] {
+    let data_len = sample_count * 2;
+    let mut header = [0u8; WAV_HEADER_LEN as usize];
+    let mut offset = 0;
+    let mut put = |bytes: &[u8]| {
+        header[offset..offset + bytes.len()].copy_from_slice(bytes);
+        offset += bytes.len();
+    };
+    put(b"RIFF");
+    put(&(WAV_HEADER_LEN - 8 + data_len).to_le_bytes());
+    put(b"WAVE");
+    put(b"fmt ");
+    put(&16u32.to_le_bytes());
+    put(&1u16.to_le_bytes()); // PCM
+    put(&1u16.to_le_bytes()); // mono
+    put(&sample_rate.to_le_bytes());
+    put(&(sample_rate * 2).to_le_bytes()); // bytes per second
+    put(&2u16.to_le_bytes()); // bytes per frame
+    put(&16u16.to_le_bytes()); // bits per sample
+    put(b"data");
+    put(&data_len.to_le_bytes());
+    header
+}
+
+/// Writes 16-bit mono PCM to a WAV file. The header is rewritten with the
+/// final length in `finish`, until then the file claims to be empty.
+pub struct WavFileBackend {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    sample_rate: u32,
+    sample_count: u32,
+}
+
+impl WavFileBackend {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&wav_header(sample_rate, 0))?;
+        Ok(Self { path: path.to_path_buf(), writer: Some(writer), sample_rate, sample_count: 0 })
+    }
+}
+
+impl AudioBackend for WavFileBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Err(io::Error::other("WAV file is already finished"));
+        };
+        for sample in samples {
+            writer.write_all(&pcm16(*sample).to_le_bytes())?;
+        }
+        self.sample_count += samples.len() as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(());
+        };
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&wav_header(self.sample_rate, self.sample_count))?;
+        writer.flush()
+    }
+}
+
+impl Drop for WavFileBackend {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Failed to finish {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// Reads a file written by `WavFileBackend`: the sample rate and the samples.
+#[cfg(test)]
+pub(crate) fn read_wav(path: &Path) -> io::Result<(u32, Vec<i16>)> {
+    let bytes = std::fs::read(path)?;
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    if bytes.len() < WAV_HEADER_LEN as usize || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+    let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let data_len = u32_at(40) as usize;
+    if u32_at(4) as usize != bytes.len() - 8 || data_len != bytes.len() - WAV_HEADER_LEN as usize {
+        return Err(invalid("WAV lengths don't match the file"));
+    }
+    let samples = bytes[WAV_HEADER_LEN as usize..]
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    Ok((u32_at(24), samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("wav_round_trip_{}.wav", std::process::id()));
+        let mut backend = WavFileBackend::create(&path, 22050).unwrap();
+        backend.write(&[0.0, 0.5, -0.5]).unwrap();
+        backend.write(&[1.0, -1.0, 2.0]).unwrap();
+        backend.finish().unwrap();
+        assert!(backend.write(&[0.0]).is_err());
+
+        let (rate, samples) = read_wav(&path).unwrap();
+        assert_eq!(rate, 22050);
+        assert_eq!(samples, vec![0, 16384, -16384, i16::MAX, -i16::MAX, i16::MAX]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
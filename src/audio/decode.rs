@@ -0,0 +1,248 @@
+use std::{
+    fmt,
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+};
+
+use lewton::inside_ogg::OggStreamReader;
+
+/// Decoded sound, mixed down to mono.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+impl DecodedAudio {
+    /// Converts to `sample_rate` with linear interpolation.
+    pub fn resample(self, sample_rate: u32) -> DecodedAudio {
+        if sample_rate == self.sample_rate || self.samples.is_empty() {
+            return DecodedAudio { sample_rate, ..self };
+        }
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let count = (self.samples.len() as f64 / ratio).floor() as usize;
+        let last = self.samples.len() - 1;
+        let samples = (0..count)
+            .map(|i| {
+                let position = i as f64 * ratio;
+                let index = (position.floor() as usize).min(last);
+                let t = (position - index as f64) as f32;
+                let next = self.samples[(index + 1).min(last)];
+                self.samples[index] + (next - self.samples[index]) * t
+            })
+            .collect();
+        DecodedAudio { sample_rate, samples }
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Io(PathBuf, io::Error),
+    Wav(String),
+    Ogg(String),
+    /// Neither `.wav` nor `.ogg`.
+    UnknownFormat(PathBuf),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            DecodeError::Wav(message) => write!(f, "invalid WAV file: {}", message),
+            DecodeError::Ogg(message) => write!(f, "invalid OGG file: {}", message),
+            DecodeError::UnknownFormat(path) => write!(f, "{}: expected a .wav or .ogg file", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn mix_down(interleaved: impl Iterator<Item = f32>, channels: usize) -> Vec<f32> {
+    let interleaved: Vec<f32> = interleaved.collect();
+    interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Decodes a RIFF WAV file with 8, 16, 24 or 32-bit integer or 32-bit float samples.
+pub fn decode_wav(bytes: &[u8]) -> Result<DecodedAudio, DecodeError> {
+    let error = |message: &str| DecodeError::Wav(message.to_string());
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(error("missing RIFF/WAVE header"));
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+
+    // (format, channels, sample rate, bits per sample)
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let len = u32_at(offset + 4) as usize;
+        let body = offset + 8;
+        let end = body.checked_add(len).filter(|&end| end <= bytes.len()).ok_or_else(|| error("chunk runs past the end of the file"))?;
+        match id {
+            b"fmt " if len >= 16 => {
+                let mut tag = u16_at(body);
+                if tag == 0xFFFE && len >= 26 {
+                    // WAVE_FORMAT_EXTENSIBLE, the real tag starts the sub-format GUID
+                    tag = u16_at(body + 24);
+                }
+                format = Some((tag, u16_at(body + 2) as usize, u32_at(body + 4), u16_at(body + 14)));
+            }
+            b"data" => data = Some(&bytes[body..end]),
+            _ => {}
+        }
+        // chunks are padded to an even length
+        offset = end + (len & 1);
+    }
+    let (tag, channels, sample_rate, bits) = format.ok_or_else(|| error("missing fmt chunk"))?;
+    let data = data.ok_or_else(|| error("missing data chunk"))?;
+    if channels == 0 || sample_rate == 0 {
+        return Err(error("no channels or a sample rate of zero"));
+    }
+
+    let samples = match (tag, bits) {
+        (1, 8) => mix_down(data.iter().map(|&b| (b as f32 - 128.0) / 128.0), channels),
+        (1, 16) => mix_down(
+            data.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0),
+            channels,
+        ),
+        (1, 24) => mix_down(
+            data.chunks_exact(3).map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0),
+            channels,
+        ),
+        (1, 32) => mix_down(
+            data.chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap()) as f32 / 2_147_483_648.0),
+            channels,
+        ),
+        (3, 32) => mix_down(data.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())), channels),
+        _ => return Err(DecodeError::Wav(format!("unsupported format {} with {} bits per sample", tag, bits))),
+    };
+    Ok(DecodedAudio { sample_rate, samples })
+}
+
+/// Decodes an Ogg Vorbis file.
+pub fn decode_ogg(bytes: &[u8]) -> Result<DecodedAudio, DecodeError> {
+    let error = |err: lewton::VorbisError| DecodeError::Ogg(err.to_string());
+    let mut reader = OggStreamReader::new(Cursor::new(bytes)).map_err(error)?;
+    let channels = reader.ident_hdr.audio_channels as usize;
+    let sample_rate = reader.ident_hdr.audio_sample_rate;
+    let mut interleaved = Vec::new();
+    while let Some(packet) = reader.read_dec_packet_itl().map_err(error)? {
+        interleaved.extend(packet.into_iter().map(|s| s as f32 / 32768.0));
+    }
+    if channels == 0 {
+        return Err(DecodeError::Ogg("no channels".to_string()));
+    }
+    Ok(DecodedAudio { sample_rate, samples: mix_down(interleaved.into_iter(), channels) })
+}
+
+/// Decodes a `.wav` or `.ogg` file, picked by extension.
+pub fn decode_file(path: &Path) -> Result<DecodedAudio, DecodeError> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+    let decode = match extension.as_deref() {
+        Some("wav") => decode_wav,
+        Some("ogg") => decode_ogg,
+        _ => return Err(DecodeError::UnknownFormat(path.to_path_buf())),
+    };
+    let bytes = fs::read(path).map_err(|err| DecodeError::Io(path.to_path_buf(), err))?;
+    decode(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A WAV file with the given format chunk fields and raw sample data.
+    fn wav(tag: u16, channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(b"RIFF");
+        out.extend((4 + 24 + 10 + 8 + data.len() as u32).to_le_bytes());
+        out.extend(b"WAVE");
+        out.extend(b"fmt ");
+        out.extend(16u32.to_le_bytes());
+        out.extend(tag.to_le_bytes());
+        out.extend(channels.to_le_bytes());
+        out.extend(rate.to_le_bytes());
+        out.extend((rate * channels as u32 * bits as u32 / 8).to_le_bytes());
+        out.extend((channels * bits / 8).to_le_bytes());
+        out.extend(bits.to_le_bytes());
+        // an unknown chunk with odd length, skipped with its padding byte
+        out.extend(b"LIST");
+        out.extend(1u32.to_le_bytes());
+        out.extend([7, 0]);
+        out.extend(b"data");
+        out.extend((data.len() as u32).to_le_bytes());
+        out.extend(data);
+        out
+    }
+
+    #[test]
+    fn decodes_wav_formats() {
+        let stereo_16: Vec<u8> = [16384i16, -16384, 32767, 32767].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let decoded = decode_wav(&wav(1, 2, 8000, 16, &stereo_16)).unwrap();
+        assert_eq!(decoded.sample_rate, 8000);
+        assert_eq!(decoded.samples.len(), 2);
+        assert!(decoded.samples[0].abs() < 1e-6 && (decoded.samples[1] - 1.0).abs() < 1e-3);
+
+        let decoded = decode_wav(&wav(1, 1, 8000, 8, &[128, 255, 0])).unwrap();
+        assert_eq!(decoded.samples, vec![0.0, 127.0 / 128.0, -1.0]);
+
+        let decoded = decode_wav(&wav(1, 1, 8000, 24, &[0, 0, 0x40, 0, 0, 0xC0])).unwrap();
+        assert_eq!(decoded.samples, vec![0.5, -0.5]);
+
+        let floats: Vec<u8> = [0.25f32, -0.75].iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(decode_wav(&wav(3, 1, 8000, 32, &floats)).unwrap().samples, vec![0.25, -0.75]);
+
+        assert!(decode_wav(&wav(2, 1, 8000, 4, &[0])).is_err());
+        assert!(decode_wav(b"RIFF\0\0\0\0WAVE").is_err());
+        assert!(decode_wav(b"OggS").is_err());
+        assert!(decode_ogg(b"RIFF\0\0\0\0WAVE").is_err());
+    }
+
+    /// 1024 stereo frames at 8000 Hz: a tone of about 490 Hz peaking at
+    /// 0.39 on the left channel, silence on the right.
+    const TONE_LEFT_OGG: &[u8] = include_bytes!("testdata/tone_left.ogg");
+
+    #[test]
+    fn decodes_ogg() {
+        let mut reader = OggStreamReader::new(Cursor::new(TONE_LEFT_OGG)).unwrap();
+        assert_eq!(reader.ident_hdr.audio_channels, 2);
+        let mut left = vec![];
+        while let Some(packet) = reader.read_dec_packet_itl().unwrap() {
+            assert!(packet.chunks(2).all(|frame| frame[1] == 0), "right channel isn't silent");
+            left.extend(packet.chunks(2).map(|frame| frame[0] as f32 / 32768.0));
+        }
+
+        let decoded = decode_ogg(TONE_LEFT_OGG).unwrap();
+        assert_eq!(decoded.sample_rate, 8000);
+        assert_eq!(decoded.samples.len(), 1024);
+        // the average of the channels, the silent right one halves the tone
+        assert!(decoded.samples.iter().zip(&left).all(|(mixed, left)| (mixed - left / 2.0).abs() < 1e-6));
+        let expected = [-0.109, -0.0413, 0.0323, 0.1015, 0.1560, 0.1881, 0.1929, 0.1697];
+        for (sample, expected) in decoded.samples.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-3, "{} vs {}", sample, expected);
+        }
+        let peak = decoded.samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.194).abs() < 0.002, "peak {}", peak);
+        let crossings = decoded.samples.windows(2).filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0)).count();
+        assert!((120..=130).contains(&crossings), "{} zero crossings", crossings);
+
+        assert!(decode_ogg(&TONE_LEFT_OGG[..40]).is_err());
+    }
+
+    #[test]
+    fn resampling_keeps_duration_and_shape() {
+        let ramp = DecodedAudio { sample_rate: 100, samples: (0..100).map(|i| i as f32 / 100.0).collect() };
+        let up = ramp.clone().resample(300);
+        assert_eq!(up.samples.len(), 300);
+        assert!((up.samples[150] - 0.5).abs() < 1e-4);
+        let down = ramp.resample(50);
+        assert_eq!(down.samples.len(), 50);
+        assert!((down.samples[25] - 0.5).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,212 @@
+// Plays the mixed samples on the sound card through ALSA.
+//
+// libasound is loaded when the device is opened instead of linked, so the
+// game builds without its headers and runs silent where it is missing.
+// Writing to the device blocks until there is room, so that happens on a
+// thread of its own which the game hands the samples of each update to.
+use std::{
+    ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void, CStr},
+    io,
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+};
+
+use libloading::Library;
+
+use super::backend::{pcm16, AudioBackend};
+
+const SND_PCM_STREAM_PLAYBACK: c_int = 0;
+const SND_PCM_FORMAT_S16_LE: c_int = 2;
+const SND_PCM_ACCESS_RW_INTERLEAVED: c_int = 3;
+
+/// How far the sound card plays behind the mixer, in microseconds.
+const LATENCY_US: c_uint = 60_000;
+/// Updates of samples waiting for the device, more are dropped.
+const QUEUED_UPDATES: usize = 8;
+
+type SndPcm = *mut c_void;
+
+/// The functions of libasound the backend calls.
+struct Alsa {
+    pcm_open: unsafe extern "C" fn(*mut SndPcm, *const c_char, c_int, c_int) -> c_int,
+    pcm_set_params: unsafe extern "C" fn(SndPcm, c_int, c_int, c_uint, c_uint, c_int, c_uint) -> c_int,
+    pcm_writei: unsafe extern "C" fn(SndPcm, *const c_void, c_ulong) -> c_long,
+    pcm_recover: unsafe extern "C" fn(SndPcm, c_int, c_int) -> c_int,
+    pcm_drain: unsafe extern "C" fn(SndPcm) -> c_int,
+    pcm_close: unsafe extern "C" fn(SndPcm) -> c_int,
+    strerror: unsafe extern "C" fn(c_int) -> *const c_char,
+    /// Keeps the functions above loaded.
+    _library: Library,
+}
+
+impl Alsa {
+    fn load() -> io::Result<Self> {
+        // SAFETY: loading libasound runs no initialisation that could
+        // interfere with the game.
+        let library = unsafe { Library::new("libasound.so.2") }.map_err(io::Error::other)?;
+        Ok(Self {
+            pcm_open: symbol(&library, b"snd_pcm_open\0")?,
+            pcm_set_params: symbol(&library, b"snd_pcm_set_params\0")?,
+            pcm_writei: symbol(&library, b"snd_pcm_writei\0")?,
+            pcm_recover: symbol(&library, b"snd_pcm_recover\0")?,
+            pcm_drain: symbol(&library, b"snd_pcm_drain\0")?,
+            pcm_close: symbol(&library, b"snd_pcm_close\0")?,
+            strerror: symbol(&library, b"snd_strerror\0")?,
+            _library: library,
+        })
+    }
+
+    /// `code` if it isn't an error, otherwise the error ALSA describes it as.
+    fn check(&self, call: &str, code: c_int) -> io::Result<c_int> {
+        if code >= 0 {
+            return Ok(code);
+        }
+        // SAFETY: snd_strerror returns a static string for any code.
+        let message = unsafe { CStr::from_ptr((self.strerror)(code)) };
+        Err(io::Error::other(format!("{} failed: {}", call, message.to_string_lossy())))
+    }
+}
+
+/// Looks up the function `name`, whose signature must be `T`.
+fn symbol<T: Copy>(library: &Library, name: &[u8]) -> io::Result<T> {
+    // SAFETY: the types in `Alsa` are the signatures from alsa/pcm.h.
+    unsafe { library.get::<T>(name) }.map(|symbol| *symbol).map_err(io::Error::other)
+}
+
+/// The default playback device, open for mono 16-bit samples.
+struct Pcm {
+    alsa: Alsa,
+    handle: SndPcm,
+}
+
+// SAFETY: an ALSA handle may be used from any thread, one at a time, which
+// owning it makes sure of.
+unsafe impl Send for Pcm {}
+
+impl Pcm {
+    fn open(sample_rate: u32) -> io::Result<Self> {
+        let alsa = Alsa::load()?;
+        let mut handle = std::ptr::null_mut();
+        // SAFETY: `handle` is only used once the device opened.
+        let code = unsafe { (alsa.pcm_open)(&mut handle, c"default".as_ptr(), SND_PCM_STREAM_PLAYBACK, 0) };
+        alsa.check("snd_pcm_open", code)?;
+        let pcm = Self { alsa, handle };
+        // SAFETY: `handle` is open, it's closed when `pcm` is dropped.
+        let code = unsafe {
+            (pcm.alsa.pcm_set_params)(
+                pcm.handle,
+                SND_PCM_FORMAT_S16_LE,
+                SND_PCM_ACCESS_RW_INTERLEAVED,
+                1,
+                sample_rate,
+                1,
+                LATENCY_US,
+            )
+        };
+        pcm.alsa.check("snd_pcm_set_params", code)?;
+        Ok(pcm)
+    }
+
+    /// Plays `samples`, waiting for room on the device. Underruns, which
+    /// happen whenever the game stalls, are recovered from.
+    fn write(&mut self, mut samples: &[i16]) -> io::Result<()> {
+        while !samples.is_empty() {
+            // SAFETY: a frame is one sample, all of `samples` can be read.
+            let written = unsafe { (self.alsa.pcm_writei)(self.handle, samples.as_ptr().cast(), samples.len() as c_ulong) };
+            if written < 0 {
+                // SAFETY: `handle` is open.
+                let code = unsafe { (self.alsa.pcm_recover)(self.handle, written as c_int, 1) };
+                self.alsa.check("snd_pcm_writei", code)?;
+                continue;
+            }
+            samples = &samples[written as usize..];
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Pcm {
+    fn drop(&mut self) {
+        // SAFETY: `handle` is open and isn't used after this.
+        unsafe {
+            (self.alsa.pcm_drain)(self.handle);
+            (self.alsa.pcm_close)(self.handle);
+        }
+    }
+}
+
+/// Plays samples on the default ALSA device. If the device falls behind by
+/// more than `QUEUED_UPDATES` updates the samples are dropped, the game never
+/// waits for it.
+pub struct DeviceBackend {
+    sample_rate: u32,
+    sender: Option<SyncSender<Vec<i16>>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl DeviceBackend {
+    pub fn open(sample_rate: u32) -> io::Result<Self> {
+        let mut pcm = Pcm::open(sample_rate)?;
+        let (sender, receiver) = mpsc::sync_channel::<Vec<i16>>(QUEUED_UPDATES);
+        let thread = thread::Builder::new().name("audio".into()).spawn(move || {
+            for samples in receiver {
+                pcm.write(&samples)?;
+            }
+            Ok(())
+        })?;
+        Ok(Self { sample_rate, sender: Some(sender), thread: Some(thread) })
+    }
+}
+
+impl AudioBackend for DeviceBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let Some(sender) = &self.sender else {
+            return Err(io::Error::other("audio device is already closed"));
+        };
+        match sender.try_send(samples.iter().map(|sample| pcm16(*sample)).collect()) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            // The thread stopped on an error, which `finish` returns.
+            Err(TrySendError::Disconnected(_)) => self.finish(),
+        }
+    }
+
+    /// Plays what is queued and closes the device.
+    fn finish(&mut self) -> io::Result<()> {
+        self.sender = None;
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or_else(|_| Err(io::Error::other("audio thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for DeviceBackend {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Failed to play audio: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_the_device_or_says_why_not() {
+        // Whether there is a sound card depends on the machine, either way
+        // the backend has to take a short silence or report an error.
+        match DeviceBackend::open(super::super::SAMPLE_RATE) {
+            Ok(mut backend) => {
+                backend.write(&[0.0; 441]).unwrap();
+                backend.finish().unwrap();
+                assert!(backend.write(&[0.0]).is_err());
+            }
+            Err(err) => assert!(!err.to_string().is_empty()),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+/// A sound that is playing.
+#[derive(Debug, Clone)]
+struct Voice {
+    samples: Arc<[f32]>,
+    position: usize,
+    gain: f32,
+}
+
+/// Adds up the playing sounds into one mono stream.
+///
+/// Next to the sound effects there is one music voice, which loops and
+/// counts the samples it played for `Mixer::music_position`.
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    voices: Vec<Voice>,
+    music: Option<Voice>,
+    /// Samples of the music played since it started, counting loops.
+    music_played: u64,
+    pub music_paused: bool,
+    pub master_volume: f32,
+}
+
+impl Mixer {
+    /// Sounds playing at once, starting one more stops the oldest.
+    pub const MAX_VOICES: usize = 16;
+
+    pub fn new() -> Self {
+        Self { voices: vec![], music: None, music_played: 0, music_paused: false, master_volume: 0.8 }
+    }
+
+    pub fn play(&mut self, samples: Arc<[f32]>, gain: f32) {
+        if self.voices.len() == Self::MAX_VOICES {
+            self.voices.remove(0);
+        }
+        self.voices.push(Voice { samples, position: 0, gain });
+    }
+
+    /// Starts looping `samples` from the beginning, replacing the music that was playing.
+    pub fn play_music(&mut self, samples: Arc<[f32]>, gain: f32) {
+        self.music = (!samples.is_empty()).then_some(Voice { samples, position: 0, gain });
+        self.music_played = 0;
+    }
+
+    pub fn stop_music(&mut self) {
+        self.music = None;
+    }
+
+    /// Samples of the music played so far, counting loops, if there is music.
+    pub fn music_position(&self) -> Option<u64> {
+        self.music.as_ref().map(|_| self.music_played)
+    }
+
+    /// Fills `out` with the next samples and drops the sounds that ended.
+    /// Loud mixes are soft-clipped instead of wrapping or cutting off hard.
+    pub fn mix(&mut self, out: &mut [f32]) {
+        out.fill(0.0);
+        if let Some(music) = self.music.as_mut().filter(|_| !self.music_paused) {
+            for out in out.iter_mut() {
+                *out += music.samples[music.position] * music.gain;
+                music.position = (music.position + 1) % music.samples.len();
+            }
+            self.music_played += out.len() as u64;
+        }
+        for voice in &mut self.voices {
+            let remaining = &voice.samples[voice.position..];
+            for (out, sample) in out.iter_mut().zip(remaining) {
+                *out += sample * voice.gain;
+            }
+            voice.position += remaining.len().min(out.len());
+        }
+        self.voices.retain(|voice| voice.position < voice.samples.len());
+        for sample in out.iter_mut() {
+            *sample = (*sample * self.master_volume).tanh();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixes_overlapping_sounds() {
+        let mut mixer = Mixer::new();
+        mixer.master_volume = 1.0;
+        mixer.play(Arc::from(vec![0.1; 10]), 1.0);
+        let mut out = vec![0.0; 4];
+        mixer.mix(&mut out);
+        mixer.play(Arc::from(vec![0.2; 3]), 0.5);
+
+        mixer.mix(&mut out);
+        assert!(out[..3].iter().all(|s| (s - 0.2f32.tanh()).abs() < 1e-6), "{:?}", out);
+        assert!((out[3] - 0.1f32.tanh()).abs() < 1e-6);
+        assert_eq!(mixer.voices.len(), 1);
+
+        mixer.mix(&mut out);
+        assert!((out[1] - 0.1f32.tanh()).abs() < 1e-6);
+        assert_eq!(out[2], 0.0);
+        assert_eq!(mixer.voices.len(), 0);
+    }
+
+    #[test]
+    fn stays_in_range_and_drops_the_oldest_voice() {
+        let mut mixer = Mixer::new();
+        for _ in 0..Mixer::MAX_VOICES + 4 {
+            mixer.play(Arc::from(vec![1.0; 100]), 1.0);
+        }
+        assert_eq!(mixer.voices.len(), Mixer::MAX_VOICES);
+        let mut out = vec![0.0; 50];
+        mixer.mix(&mut out);
+        assert!(out.iter().all(|s| s.abs() <= 1.0 && *s > 0.9));
+    }
+
+    #[test]
+    fn music_loops_and_counts_its_samples() {
+        let mut mixer = Mixer::new();
+        mixer.master_volume = 1.0;
+        assert_eq!(mixer.music_position(), None);
+        mixer.play_music(Arc::from(vec![0.1, 0.2, 0.3]), 1.0);
+        mixer.play(Arc::from(vec![0.1; 2]), 1.0);
+        let mut out = vec![0.0; 4];
+        mixer.mix(&mut out);
+        let expected = [0.2f32, 0.3, 0.3, 0.1].map(f32::tanh);
+        assert!(out.iter().zip(expected).all(|(s, e)| (s - e).abs() < 1e-6), "{:?}", out);
+        assert_eq!(mixer.music_position(), Some(4));
+
+        mixer.music_paused = true;
+        mixer.mix(&mut out);
+        assert_eq!(out, vec![0.0; 4]);
+        assert_eq!(mixer.music_position(), Some(4));
+
+        mixer.play_music(Arc::from(vec![0.5]), 1.0);
+        assert_eq!(mixer.music_position(), Some(0));
+        mixer.stop_music();
+        assert_eq!(mixer.music_position(), None);
+    }
+}
@@ -0,0 +1,383 @@
+// Procedural sound effects, mixed and sent to an output backend.
+//
+// Sounds are synthesized once from small parameter sets when `Audio` is
+// created and played back from memory. Rendering is driven by game time
+// through `Audio::update`, so a headless run produces the same audio as a
+// live one and the WAV backend works without any audio device.
+//
+// Every stage has a music track, see `Stage::music`. The samples of it that
+// were mixed are the clock of the game's timeline.
+use std::{io, path::Path, sync::Arc};
+
+mod analysis;
+mod backend;
+mod decode;
+#[cfg(target_os = "linux")]
+mod device;
+mod mixer;
+mod music;
+mod synth;
+
+pub use analysis::BandLevels;
+pub use backend::{AudioBackend, NullBackend, WavFileBackend};
+#[cfg(target_os = "linux")]
+pub use device::DeviceBackend;
+pub use mixer::Mixer;
+pub use music::{MusicLibrary, MusicTrack, MUSIC_DIR};
+pub use synth::{synthesize, SoundParams, Waveform};
+
+use crate::game::{Game, GameEvent, GameState, MusicClock};
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEffect {
+    DeathCrash,
+    PatternBlip,
+    NearMissWhoosh,
+    MenuSelect,
+    PowerUpChime,
+    ShieldBreak,
+}
+
+impl SoundEffect {
+    pub const ALL: [SoundEffect; 6] = [
+        SoundEffect::DeathCrash,
+        SoundEffect::PatternBlip,
+        SoundEffect::NearMissWhoosh,
+        SoundEffect::MenuSelect,
+        SoundEffect::PowerUpChime,
+        SoundEffect::ShieldBreak,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SoundEffect::DeathCrash => "death_crash",
+            SoundEffect::PatternBlip => "pattern_blip",
+            SoundEffect::NearMissWhoosh => "near_miss_whoosh",
+            SoundEffect::MenuSelect => "menu_select",
+            SoundEffect::PowerUpChime => "power_up_chime",
+            SoundEffect::ShieldBreak => "shield_break",
+        }
+    }
+
+    pub fn params(self) -> SoundParams {
+        match self {
+            // low rumble drowned in noise that closes down
+            SoundEffect::DeathCrash => SoundParams {
+                waveform: Waveform::Square,
+                start_frequency: 110.0,
+                end_frequency: 40.0,
+                noise: 0.8,
+                cutoff_start: 4000.0,
+                cutoff_end: 200.0,
+                duration: 0.9,
+                attack: 0.003,
+                decay: 2.5,
+                volume: 0.9,
+            },
+            // short rising beep
+            SoundEffect::PatternBlip => SoundParams {
+                waveform: Waveform::Square,
+                start_frequency: 880.0,
+                end_frequency: 1320.0,
+                noise: 0.0,
+                cutoff_start: 6000.0,
+                cutoff_end: 6000.0,
+                duration: 0.08,
+                attack: 0.002,
+                decay: 1.0,
+                volume: 0.3,
+            },
+            // filtered noise opening up
+            SoundEffect::NearMissWhoosh => SoundParams {
+                waveform: Waveform::Sine,
+                start_frequency: 200.0,
+                end_frequency: 120.0,
+                noise: 0.9,
+                cutoff_start: 400.0,
+                cutoff_end: 3000.0,
+                duration: 0.35,
+                attack: 0.12,
+                decay: 1.5,
+                volume: 0.6,
+            },
+            SoundEffect::MenuSelect => SoundParams {
+                waveform: Waveform::Triangle,
+                start_frequency: 660.0,
+                end_frequency: 990.0,
+                noise: 0.0,
+                cutoff_start: 8000.0,
+                cutoff_end: 8000.0,
+                duration: 0.12,
+                attack: 0.005,
+                decay: 1.5,
+                volume: 0.5,
+            },
+            // bright sweep up over an octave and a half
+            SoundEffect::PowerUpChime => SoundParams {
+                waveform: Waveform::Triangle,
+                start_frequency: 523.0,
+                end_frequency: 1568.0,
+                noise: 0.0,
+                cutoff_start: 8000.0,
+                cutoff_end: 8000.0,
+                duration: 0.3,
+                attack: 0.01,
+                decay: 1.2,
+                volume: 0.5,
+            },
+            // glassy noise burst, shorter and higher than the crash
+            SoundEffect::ShieldBreak => SoundParams {
+                waveform: Waveform::Square,
+                start_frequency: 1200.0,
+                end_frequency: 300.0,
+                noise: 0.6,
+                cutoff_start: 9000.0,
+                cutoff_end: 1500.0,
+                duration: 0.4,
+                attack: 0.002,
+                decay: 2.0,
+                volume: 0.6,
+            },
+        }
+    }
+
+    /// Sound played for a game event, if any.
+    pub fn for_event(event: GameEvent) -> Option<SoundEffect> {
+        match event {
+            GameEvent::Collision => Some(SoundEffect::DeathCrash),
+            GameEvent::PatternStarted => Some(SoundEffect::PatternBlip),
+            GameEvent::NearMiss => Some(SoundEffect::NearMissWhoosh),
+            GameEvent::MenuSelect => Some(SoundEffect::MenuSelect),
+            GameEvent::PowerUpCollected(_) => Some(SoundEffect::PowerUpChime),
+            GameEvent::ShieldBroken => Some(SoundEffect::ShieldBreak),
+        }
+    }
+}
+
+/// The sound effects, the music, a mixer and the backend they are played on.
+pub struct Audio {
+    backend: Box<dyn AudioBackend>,
+    mixer: Mixer,
+    sounds: Vec<Arc<[f32]>>,
+    library: MusicLibrary,
+    /// The track the mixer is looping, if any.
+    music: Option<MusicTrack>,
+    /// Fraction of a sample left over from the last update.
+    pending: f64,
+    buffer: Vec<f32>,
+}
+
+impl Audio {
+    /// Music is quieter than the sound effects on top of it.
+    const MUSIC_GAIN: f32 = 0.5;
+
+    pub fn new(backend: Box<dyn AudioBackend>) -> Self {
+        let sample_rate = backend.sample_rate();
+        let sounds = SoundEffect::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, effect)| Arc::from(synthesize(&effect.params(), sample_rate, i as u64)))
+            .collect();
+        Self {
+            backend,
+            mixer: Mixer::new(),
+            sounds,
+            library: MusicLibrary::new(Path::new(MUSIC_DIR), sample_rate),
+            music: None,
+            pending: 0.0,
+            buffer: vec![],
+        }
+    }
+
+    /// Audio that goes nowhere.
+    pub fn silent() -> Self {
+        Self::new(Box::new(NullBackend { sample_rate: SAMPLE_RATE }))
+    }
+
+    /// Audio recorded to the WAV file at `path`, or played on the sound card
+    /// if there is no path. Silent if neither can be opened.
+    pub fn open(record_to: Option<&Path>) -> Self {
+        let Some(path) = record_to else {
+            return Self::device();
+        };
+        match WavFileBackend::create(path, SAMPLE_RATE) {
+            Ok(backend) => {
+                println!("Recording audio to {}", path.display());
+                Self::new(Box::new(backend))
+            }
+            Err(err) => {
+                eprintln!("Failed to create {}: {}", path.display(), err);
+                Self::silent()
+            }
+        }
+    }
+
+    /// Audio played on the sound card, or silent audio if there is none.
+    #[cfg(target_os = "linux")]
+    fn device() -> Self {
+        match DeviceBackend::open(SAMPLE_RATE) {
+            Ok(backend) => Self::new(Box::new(backend)),
+            Err(err) => {
+                eprintln!("No audio device: {}", err);
+                Self::silent()
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn device() -> Self {
+        Self::silent()
+    }
+
+    pub fn play(&mut self, effect: SoundEffect) {
+        let index = SoundEffect::ALL.iter().position(|e| *e == effect).unwrap();
+        self.mixer.play(self.sounds[index].clone(), 1.0);
+    }
+
+    /// Starts the sounds for the events of the last game update.
+    pub fn play_events(&mut self, events: &[GameEvent]) {
+        for effect in events.iter().filter_map(|event| SoundEffect::for_event(*event)) {
+            self.play(effect);
+        }
+    }
+
+    /// Loops `track` from its start.
+    pub fn play_music(&mut self, track: &MusicTrack) {
+        self.mixer.play_music(track.samples.clone(), Self::MUSIC_GAIN);
+        self.music = Some(track.clone());
+    }
+
+    fn stop_music(&mut self) {
+        self.mixer.stop_music();
+        self.music = None;
+    }
+
+    /// Where the music is, from the samples mixed so far.
+    pub fn music_clock(&self) -> Option<MusicClock> {
+        let played = self.mixer.music_position()?;
+        let len = self.music.as_ref()?.samples.len() as u64;
+        let rate = self.backend.sample_rate() as f64;
+        Some(MusicClock {
+            elapsed: played as f64 / rate,
+            position: ((played % len) as f64 / rate) as f32,
+        })
+    }
+
+    /// Band levels of the music at its current position, all 0 without music.
+    pub fn music_levels(&self) -> BandLevels {
+        match (&self.music, self.music_clock()) {
+            (Some(track), Some(clock)) => track.envelopes.at(clock.position),
+            _ => BandLevels::default(),
+        }
+    }
+
+    /// Everything the audio does for a game update: starts the sounds of its
+    /// events and the music of a new run, mixes `dt` seconds and hands the
+    /// music position back to the game. The music holds still while the game
+    /// is paused and stops on the title screen. A missing track keeps the
+    /// music that is playing.
+    pub fn update_game(&mut self, game: &mut Game, dt: f32) -> io::Result<()> {
+        self.play_events(game.events());
+        if let Some(name) = game.take_music_change() {
+            if let Some(track) = self.library.track(name).cloned() {
+                self.play_music(&track);
+                game.set_beat_map(track.beat_map);
+            }
+        }
+        match game.state() {
+            GameState::Title => self.stop_music(),
+            state => self.mixer.music_paused = state == GameState::Paused,
+        }
+        let result = self.update(dt);
+        game.sync_music(self.music_clock());
+        result
+    }
+
+    /// Mixes `dt` seconds of audio and sends them to the backend.
+    pub fn update(&mut self, dt: f32) -> io::Result<()> {
+        self.pending += dt as f64 * self.backend.sample_rate() as f64;
+        let count = self.pending.floor() as usize;
+        self.pending -= count as f64;
+        self.buffer.resize(count, 0.0);
+        self.mixer.mix(&mut self.buffer);
+        self.backend.write(&self.buffer)
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.backend.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Stage;
+    use crate::headless::{HeadlessRunner, InputScript};
+    use crate::input::VirtualInput;
+
+    #[test]
+    fn effects_are_audible_and_in_range() {
+        for effect in SoundEffect::ALL {
+            let samples = synthesize(&effect.params(), SAMPLE_RATE, 0);
+            let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            assert!(peak > 0.05 && peak <= 1.0, "{}: peak {}", effect.name(), peak);
+            assert!(samples.len() < SAMPLE_RATE as usize, "{} is longer than a second", effect.name());
+        }
+    }
+
+    #[test]
+    fn decoded_music_is_the_clock_of_the_timeline() {
+        let path = std::env::temp_dir().join(format!("timeline_clock_{}.ogg", std::process::id()));
+        std::fs::write(&path, include_bytes!("testdata/tone_left.ogg")).unwrap();
+        let track = MusicTrack::load(&path, SAMPLE_RATE).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut audio = Audio::silent();
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        let input = VirtualInput::new();
+        game.set_timeline_source("bpm 60\n2 palette 1\n").unwrap();
+        game.start();
+        game.take_music_change();
+        audio.play_music(&track);
+
+        // the game always gets 1/60 s, the frames play very different numbers
+        // of samples of the looping clip: the music gets past beat 2 on the
+        // fifth update and the timeline follows it on the next one
+        let frames = [11025, 11025, 44100, 17640, 26460, 735, 735];
+        let mut played = 0;
+        for (update, frame) in (1..).zip(frames) {
+            game.update(1.0 / 60.0, &input);
+            assert_eq!(game.palette().1 == 1, update >= 6, "update {}", update);
+            audio.update_game(&mut game, frame as f32 / SAMPLE_RATE as f32).unwrap();
+            played += frame;
+            let clock = audio.music_clock().unwrap();
+            assert!((clock.elapsed * SAMPLE_RATE as f64 - played as f64).abs() <= 1.0, "{} samples", played);
+            let position = (played % track.samples.len()) as f32 / SAMPLE_RATE as f32;
+            assert!((clock.position - position).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn headless_run_renders_to_wav() {
+        let path = std::env::temp_dir().join(format!("headless_audio_{}.wav", std::process::id()));
+        let backend = WavFileBackend::create(&path, SAMPLE_RATE).unwrap();
+        let mut runner = HeadlessRunner::new(1);
+        runner.audio = Audio::new(Box::new(backend));
+        let result = runner.run(120.0, &InputScript::new());
+        assert!(result.game_over);
+        runner.audio.finish().unwrap();
+
+        let (rate, samples) = backend::read_wav(&path).unwrap();
+        assert_eq!(rate, SAMPLE_RATE);
+        // as long as the run, up to rounding of the summed up step times
+        let expected = result.time as f64 * SAMPLE_RATE as f64;
+        assert!((samples.len() as f64 - expected).abs() <= 50.0, "{} vs {}", samples.len(), expected);
+        // pattern blips during the run, the crash starts on the last step
+        let last_step = samples.len() - (runner.dt * SAMPLE_RATE as f32) as usize;
+        assert!(samples[..last_step].iter().any(|s| *s != 0));
+        assert!(samples[last_step..].iter().any(|s| s.abs() > 1000));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
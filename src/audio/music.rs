@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::analysis::BandEnvelopes;
+use super::decode::{decode_file, DecodeError};
+use crate::game::{load_beat_map, BeatMap, BeatMapLoadError, BEAT_MAP_EXTENSION};
+
+pub const MUSIC_DIR: &str = "assets/music";
+/// Music file types, in the order they are looked for.
+const MUSIC_EXTENSIONS: [&str; 2] = ["ogg", "wav"];
+
+/// A decoded music track, its band levels and the beat map next to it, if
+/// there is one.
+#[derive(Debug, Clone)]
+pub struct MusicTrack {
+    pub samples: Arc<[f32]>,
+    pub sample_rate: u32,
+    pub envelopes: BandEnvelopes,
+    pub beat_map: Option<BeatMap>,
+}
+
+#[derive(Debug)]
+pub enum MusicLoadError {
+    Decode(DecodeError),
+    BeatMap(BeatMapLoadError),
+}
+
+impl fmt::Display for MusicLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MusicLoadError::Decode(err) => err.fmt(f),
+            MusicLoadError::BeatMap(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MusicLoadError {}
+
+impl MusicTrack {
+    /// Decodes the WAV or OGG file at `path` to `sample_rate` and reads the
+    /// `.beats` file with the same name, if it exists.
+    pub fn load(path: &Path, sample_rate: u32) -> Result<Self, MusicLoadError> {
+        let audio = decode_file(path).map_err(MusicLoadError::Decode)?.resample(sample_rate);
+        let beats_path = path.with_extension(BEAT_MAP_EXTENSION);
+        let beat_map = match beats_path.exists() {
+            true => Some(load_beat_map(&beats_path).map_err(MusicLoadError::BeatMap)?),
+            false => None,
+        };
+        let envelopes = BandEnvelopes::analyze(&audio.samples, sample_rate);
+        Ok(Self { samples: Arc::from(audio.samples), sample_rate, envelopes, beat_map })
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+}
+
+/// Music file `<name>.ogg` or `<name>.wav` in `dir`.
+pub fn track_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    MUSIC_EXTENSIONS
+        .iter()
+        .map(|extension| dir.join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+}
+
+/// The music tracks by name, loaded when they are first needed. A track
+/// that fails to load is reported once and treated as missing.
+pub struct MusicLibrary {
+    dir: PathBuf,
+    sample_rate: u32,
+    tracks: HashMap<String, Option<MusicTrack>>,
+}
+
+impl MusicLibrary {
+    pub fn new(dir: &Path, sample_rate: u32) -> Self {
+        Self { dir: dir.to_path_buf(), sample_rate, tracks: HashMap::new() }
+    }
+
+    pub fn track(&mut self, name: &str) -> Option<&MusicTrack> {
+        let (dir, sample_rate) = (&self.dir, self.sample_rate);
+        self.tracks
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let path = track_path(dir, name)?;
+                MusicTrack::load(&path, sample_rate)
+                    .inspect_err(|err| eprintln!("Failed to load music {}: {}", path.display(), err))
+                    .ok()
+            })
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::SAMPLE_RATE;
+    use crate::game::Stage;
+
+    #[test]
+    fn bundled_clip_matches_its_beat_map() {
+        let mut library = MusicLibrary::new(Path::new(MUSIC_DIR), SAMPLE_RATE);
+        assert!(library.track("silence").is_none());
+        for stage in Stage::all() {
+            assert!(library.track(stage.music).is_some(), "no music for {}", stage.id);
+        }
+        let track = library.track("hexagon").expect("hexagon has music").clone();
+        assert!((track.duration() - 4.0).abs() < 1e-3, "{}", track.duration());
+        let beat_map = track.beat_map.as_ref().expect("hexagon has a beat map");
+        assert_eq!(beat_map.beats().len(), 8);
+        assert_eq!(beat_map.bars(), &[0.0, 2.0]);
+
+        // every beat is a drum hit: loud right after it, quiet right before
+        let energy = |start: f32, end: f32| {
+            let range = (start * SAMPLE_RATE as f32) as usize..(end * SAMPLE_RATE as f32) as usize;
+            track.samples[range].iter().map(|s| s * s).sum::<f32>()
+        };
+        for &beat in beat_map.beats() {
+            let before = energy((beat - 0.03).max(0.0), beat);
+            let after = energy(beat, beat + 0.03);
+            assert!(after > 10.0 * before && after > 1.0, "beat at {}: {} -> {}", beat, before, after);
+            // the kick drum makes the bass jump on the beat and fade in between
+            let on_beat = track.envelopes.at(beat + 0.02).bass;
+            let between = track.envelopes.at(beat + 0.4).bass;
+            assert!(on_beat > 0.6 && between < 0.5 * on_beat, "beat at {}: {} -> {}", beat, on_beat, between);
+        }
+    }
+}
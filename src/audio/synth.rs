@@ -0,0 +1,136 @@
+use std::f32::consts::TAU;
+
+use crate::common::rng::Rng;
+
+/// Oscillator shape of a sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Saw,
+    Triangle,
+}
+
+/// Everything needed to synthesize one sound effect.
+///
+/// An oscillator sweeps from `start_frequency` to `end_frequency`, white
+/// noise is mixed in by `noise`, and the sum goes through a low-pass filter
+/// sweeping from `cutoff_start` to `cutoff_end`. The envelope rises linearly
+/// over `attack` seconds and then falls to zero at `duration`, with `decay`
+/// as the exponent of the fall (1 is linear, higher drops faster).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundParams {
+    pub waveform: Waveform,
+    pub start_frequency: f32,
+    pub end_frequency: f32,
+    /// 0 is only the oscillator, 1 only noise.
+    pub noise: f32,
+    pub cutoff_start: f32,
+    pub cutoff_end: f32,
+    pub duration: f32,
+    pub attack: f32,
+    pub decay: f32,
+    pub volume: f32,
+}
+
+/// Exponential sweep, so that equal times give equal musical intervals.
+fn sweep(start: f32, end: f32, t: f32) -> f32 {
+    start * (end / start).powf(t)
+}
+
+fn oscillator(waveform: Waveform, phase: f32) -> f32 {
+    match waveform {
+        Waveform::Sine => (phase * TAU).sin(),
+        Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+        Waveform::Saw => 2.0 * phase - 1.0,
+        Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+    }
+}
+
+fn envelope(params: &SoundParams, time: f32) -> f32 {
+    if time < params.attack {
+        return time / params.attack;
+    }
+    let release = (params.duration - params.attack).max(1e-6);
+    (1.0 - (time - params.attack) / release).max(0.0).powf(params.decay)
+}
+
+/// Renders `params` to mono samples in `[-1, 1]`. The noise is seeded, so
+/// the same parameters always give the same samples.
+pub fn synthesize(params: &SoundParams, sample_rate: u32, seed: u64) -> Vec<f32> {
+    let mut rng = Rng::new(seed);
+    let count = (params.duration * sample_rate as f32).ceil() as usize;
+    let dt = 1.0 / sample_rate as f32;
+    let mut samples = Vec::with_capacity(count);
+    let mut phase = 0.0;
+    let mut filtered = 0.0;
+    for i in 0..count {
+        let time = i as f32 * dt;
+        let t = time / params.duration;
+        phase = (phase + sweep(params.start_frequency, params.end_frequency, t) * dt).fract();
+        let noise = rng.range_f32(-1.0, 1.0);
+        let raw = oscillator(params.waveform, phase) * (1.0 - params.noise) + noise * params.noise;
+
+        // one-pole low-pass
+        let cutoff = sweep(params.cutoff_start, params.cutoff_end, t);
+        let alpha = 1.0 - (-TAU * cutoff * dt).exp();
+        filtered += alpha * (raw - filtered);
+
+        samples.push((filtered * envelope(params, time) * params.volume).clamp(-1.0, 1.0));
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(waveform: Waveform) -> SoundParams {
+        SoundParams {
+            waveform,
+            start_frequency: 440.0,
+            end_frequency: 440.0,
+            noise: 0.0,
+            cutoff_start: 20000.0,
+            cutoff_end: 20000.0,
+            duration: 0.5,
+            attack: 0.01,
+            decay: 1.0,
+            volume: 1.0,
+        }
+    }
+
+    /// Zero crossings from negative to positive, one per period.
+    fn periods(samples: &[f32]) -> usize {
+        samples.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn tone_has_its_frequency_and_envelope() {
+        for waveform in [Waveform::Sine, Waveform::Square, Waveform::Saw, Waveform::Triangle] {
+            let samples = synthesize(&tone(waveform), 44100, 1);
+            assert_eq!(samples.len(), 22050);
+            // 440 Hz for half a second
+            assert!((periods(&samples) as i32 - 220).abs() <= 2, "{:?}: {}", waveform, periods(&samples));
+            assert!(samples.iter().all(|s| s.abs() <= 1.0));
+            // silent at both ends, loud after the attack
+            assert!(samples[0].abs() < 0.01 && samples.last().unwrap().abs() < 0.01);
+            assert!(samples[441..2000].iter().any(|s| s.abs() > 0.5));
+        }
+    }
+
+    #[test]
+    fn sweep_and_filter() {
+        let rising = SoundParams { end_frequency: 880.0, ..tone(Waveform::Sine) };
+        let samples = synthesize(&rising, 44100, 1);
+        let half = samples.len() / 2;
+        assert!(periods(&samples[half..]) > periods(&samples[..half]));
+
+        // the same noise is quieter through a low cutoff
+        let noise = SoundParams { noise: 1.0, ..tone(Waveform::Sine) };
+        let muffled = SoundParams { cutoff_start: 200.0, cutoff_end: 200.0, ..noise };
+        let energy = |samples: &[f32]| samples.iter().map(|s| s * s).sum::<f32>();
+        assert!(energy(&synthesize(&muffled, 44100, 3)) < 0.5 * energy(&synthesize(&noise, 44100, 3)));
+        assert_eq!(synthesize(&noise, 44100, 3), synthesize(&noise, 44100, 3));
+    }
+}
@@ -23,8 +23,6 @@ pub struct Camera {
 
 impl Camera {
     pub fn new(eye: cgmath::Point3<f32>, target: cgmath::Point3<f32>, up: cgmath::Vector3<f32>, aspect: f32, fovy: f32, znear: f32, zfar: f32) -> Self {
-        let u_dir = (target - eye).cross(up).normalize();
-        let v_dir = u_dir.cross(target - eye).normalize();
         Self {
             eye,
             target,
@@ -64,6 +62,6 @@ impl Camera {
     pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
     }
 }
@@ -1,27 +1,87 @@
 use winit::{
     dpi::PhysicalSize,
     event::*,
-    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    event_loop::EventLoopWindowTarget,
     keyboard::{KeyCode, PhysicalKey},
-    window::WindowBuilder,
     window::Window,
 };
 
+use std::path::PathBuf;
+
 use crate::{
-    game::Game, 
+    audio::Audio,
+    game::{ActivePowerUps, AsciiView, Autoplay, Game, GameState, Player, RunResult, Stage}, 
+    highscore::{format_date, HighScoreEntry, HighScores},
     input::{
         InputDriveInterface, 
-        InputHandler
+        InputGetInterface,
+        InputHandler,
+        InputID,
+        VirtualInput,
     }, 
-    time::get_time_since_start,
+    time::{get_time_since_start, time_seed, unix_time},
+    time_scale::TimeScale,
     renderer::Renderer,
+    replay::{Replay, ReplayPlayer, ReplayRecorder},
 };
 
 
 
-pub struct EngineContext<'window> {
-    pub input: &'window InputHandler<'window>,
-    pub renderer: &'window Renderer<'window>,
+/// Seconds on the title screen without a key press before the demo starts.
+const ATTRACT_DELAY: f32 = 20.0;
+/// Seconds between the frames of `--ascii`.
+const ASCII_INTERVAL: f32 = 0.1;
+
+/// Stages the player may pick, see `Stage::unlocked`.
+fn unlocked_stages(high_scores: &HighScores) -> Vec<&'static Stage> {
+    Stage::unlocked(|id| high_scores.personal_best(id).map(|entry| entry.time))
+}
+
+/// Options taken from the command line.
+#[derive(Debug, Default)]
+pub struct EngineOptions {
+    /// `--record <file>`: record the run and save it to the file on exit.
+    pub record_replay: Option<PathBuf>,
+    /// `--replay <file>`: play back a recorded run.
+    pub play_replay: Option<PathBuf>,
+    /// `--headless <seconds>`: run without a window, see `crate::headless`.
+    pub headless: Option<f32>,
+    /// `--record-audio <file>`: write the sound of the game to a WAV file.
+    pub record_audio: Option<PathBuf>,
+    /// `--stage <id>`: start on this stage, if it is unlocked.
+    pub stage: Option<String>,
+    /// `--autoplay`: let `Autoplay` play the headless run.
+    pub autoplay: bool,
+    /// `--ascii`: also draw the game as text on the terminal, see `AsciiView`.
+    pub ascii: bool,
+    /// `--view-pattern <name>`: page through a pattern as text and exit, see `crate::pattern_view`.
+    pub view_pattern: Option<String>,
+    /// `--export <file>`: with `--view-pattern`, write the whole pattern to the file instead.
+    pub export: Option<PathBuf>,
+    /// `--check-patterns`: analyze the pattern files and exit, see `crate::pattern_check`.
+    pub check_patterns: bool,
+}
+
+impl EngineOptions {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut options = EngineOptions::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record" => options.record_replay = args.next().map(PathBuf::from),
+                "--replay" => options.play_replay = args.next().map(PathBuf::from),
+                "--headless" => options.headless = args.next().and_then(|s| s.parse().ok()),
+                "--record-audio" => options.record_audio = args.next().map(PathBuf::from),
+                "--stage" => options.stage = args.next(),
+                "--autoplay" => options.autoplay = true,
+                "--check-patterns" => options.check_patterns = true,
+                "--ascii" => options.ascii = true,
+                "--view-pattern" => options.view_pattern = args.next(),
+                "--export" => options.export = args.next().map(PathBuf::from),
+                _ => eprintln!("Ignoring unknown argument {:?}", arg),
+            }
+        }
+        options
+    }
 }
 
 pub struct Engine<'window> {
@@ -36,12 +96,30 @@ pub struct Engine<'window> {
     pub time_accumulator: f64,
     pub fps_counter: crate::time::FpsCounter,
 
+    // Replay recording / playback
+    pub replay_recorder: Option<(ReplayRecorder, PathBuf)>,
+    pub replay_player: Option<ReplayPlayer>,
+
+    pub high_scores: HighScores,
+
+    pub audio: Audio,
+
+    // Attract mode, the bot plays a demo run after a while on the title screen
+    pub attract: Option<(Autoplay, VirtualInput)>,
+    pub idle_time: f32,
+
+    // `--ascii`: the game drawn as text on the terminal, and the time since the last frame
+    pub ascii_view: Option<(AsciiView, f32)>,
+
+    // Slow motion and hit-stop between the wall clock and the game
+    pub time_scale: TimeScale,
+
     #[cfg(target_arch = "wasm32")]
     pub wait_until: f64,
 }
 
 impl<'window> Engine<'window> {
-    pub async fn new(window: &'window Window) -> Engine<'window> {
+    pub async fn new(window: &'window Window, options: EngineOptions) -> Engine<'window> {
         // Create our Renderer
         let size = window.inner_size();
         let renderer = Renderer::new(window, size).await;
@@ -50,15 +128,38 @@ impl<'window> Engine<'window> {
         let fps_counter = crate::time::FpsCounter::new();
         let now = get_time_since_start();
 
-        let mut game = Game::new(size.width as f32 / size.height as f32);
+        let replay_player = options.play_replay.and_then(|path| match Replay::load(&path) {
+            Ok(replay) => {
+                println!("Playing replay {}", path.display());
+                Some(ReplayPlayer::new(replay))
+            }
+            Err(err) => {
+                eprintln!("Failed to load replay {}: {}", path.display(), err);
+                None
+            }
+        });
+        let seed = match &replay_player {
+            Some(player) => player.replay.seed,
+            None => time_seed(),
+        };
+        let high_scores = HighScores::load_default();
+        let stage = match (&replay_player, &options.stage) {
+            (Some(player), _) => Stage::find_or_first(&player.replay.stage),
+            (None, Some(id)) => match unlocked_stages(&high_scores).into_iter().find(|stage| stage.id == id) {
+                Some(stage) => stage,
+                None => {
+                    eprintln!("Stage {} is unknown or still locked", id);
+                    Stage::first()
+                }
+            },
+            (None, None) => Stage::first(),
+        };
+        let replay_recorder = options.record_replay.map(|path| (ReplayRecorder::new(seed, stage.id), path));
+
+        let game = Game::new(size.width as f32 / size.height as f32, seed, stage.clone());
         let mut input = InputHandler::new(window);
         input.activate();
 
-        let context = EngineContext {
-            input: &input,
-            renderer: &renderer,
-        };
-
         Engine {
             window,
             renderer,
@@ -69,13 +170,21 @@ impl<'window> Engine<'window> {
             frame_duration: 1.0 / 60.0,
             time_accumulator: 0.0,
             fps_counter,
+            replay_recorder,
+            replay_player,
+            high_scores,
+            audio: Audio::open(options.record_audio.as_deref()),
+            attract: None,
+            idle_time: 0.0,
+            time_scale: TimeScale::new(),
+            ascii_view: options.ascii.then(|| (AsciiView { rows: 24, color: true, ..AsciiView::default() }, 0.0)),
             #[cfg(target_arch = "wasm32")]
             wait_until: 0.0,
         }
     }
 
     /// Handle input (keyboard, mouse, etc.)
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
+    pub fn input(&mut self, _event: &WindowEvent) -> bool {
         // Return `true` if event has been handled to prevent further processing
         false
     }
@@ -83,21 +192,265 @@ impl<'window> Engine<'window> {
     /// Update logic (no rendering) each discrete timestep
     pub fn update(&mut self, dt: f32) {
         self.input.update(dt);
-        let engine_context = EngineContext {
-            input: &mut self.input,
-            renderer: &mut self.renderer,
+        self.print_ascii(dt);
+        let game_dt = self.time_scale.update(dt);
+        self.update_game(dt, game_dt);
+        self.time_scale.handle_events(self.game.events());
+    }
+
+    /// One update of the game by `game_dt` seconds of game time, during `dt`
+    /// seconds on the wall clock. Replays record and play back game time and
+    /// the music position.
+    fn update_game(&mut self, dt: f32, game_dt: f32) {
+        if let Some(player) = &mut self.replay_player {
+            match player.next_frame() {
+                Some(replay_dt) => {
+                    self.game.update(replay_dt, &player.input);
+                    if let Err(desync) = player.verify(&self.game) {
+                        eprintln!("{}", desync);
+                        self.replay_player = None;
+                    }
+                    self.update_audio(dt);
+                    if let Some(player) = &self.replay_player {
+                        player.sync_music(&mut self.game);
+                    }
+                    return;
+                }
+                None => {
+                    println!("Replay finished");
+                    self.replay_player = None;
+                }
+            }
+        }
+
+        if self.update_attract(dt, game_dt) {
+            return;
+        }
+        if self.game.state() == GameState::Title {
+            self.select_stage();
+        }
+        if let Some((recorder, _)) = &mut self.replay_recorder {
+            recorder.record_input(game_dt, &self.input);
+        }
+        self.game.update(game_dt, &self.input);
+        self.update_audio(dt);
+        if let Some((recorder, _)) = &mut self.replay_recorder {
+            recorder.record_state(&self.game);
+        }
+        if let Some(run) = self.game.take_finished_run() {
+            self.submit_run(run);
+        }
+    }
+
+    /// Redraws the game on the terminal every `ASCII_INTERVAL` with `--ascii`.
+    fn print_ascii(&mut self, dt: f32) {
+        let Some((view, since_frame)) = &mut self.ascii_view else {
+            return;
         };
-        self.game.update(dt, &engine_context);
-        // self.game.update(dt, &self.input);
+        *since_frame += dt;
+        if *since_frame >= ASCII_INTERVAL {
+            *since_frame = 0.0;
+            // clear the screen and draw from the top left
+            print!("\x1b[2J\x1b[H{}", view.render_game(&self.game));
+        }
+    }
+
+    /// Plays the sounds and music of the last game update and renders `dt` seconds of audio.
+    fn update_audio(&mut self, dt: f32) {
+        if let Err(err) = self.audio.update_game(&mut self.game, dt) {
+            eprintln!("Failed to write audio: {}", err);
+            self.audio = Audio::silent();
+        }
+    }
+
+    /// Starts the demo after `ATTRACT_DELAY` seconds on the title screen,
+    /// and plays it with the bot until it dies or any key is pressed, which
+    /// goes back to the title screen. Returns whether the demo had the game
+    /// this update.
+    fn update_attract(&mut self, dt: f32, game_dt: f32) -> bool {
+        let key_pressed = InputID::ALL.iter().any(|&key| self.input.get_key_state(key).just_pressed);
+        if self.attract.is_none() {
+            self.idle_time = match self.game.state() {
+                GameState::Title if !key_pressed => self.idle_time + dt,
+                _ => 0.0,
+            };
+            if self.idle_time < ATTRACT_DELAY {
+                return false;
+            }
+            println!("Demo");
+            self.attract = Some((Autoplay::new(), VirtualInput::new()));
+        }
+        if key_pressed || self.game.is_game_over() {
+            self.attract = None;
+            self.idle_time = 0.0;
+            self.new_game(self.game.stage().clone());
+            return true;
+        }
+        if let Some((bot, input)) = &mut self.attract {
+            bot.drive(&self.game, input);
+            self.game.update(game_dt, input);
+        }
+        self.update_audio(dt);
+        // demo runs don't count
+        self.game.take_finished_run();
+        true
+    }
+
+    /// Replaces the game with a new one on the title screen, with as many
+    /// players as before, and starts the replay being recorded over with it.
+    fn new_game(&mut self, stage: Stage) {
+        let seed = time_seed();
+        if let Some((recorder, _)) = &mut self.replay_recorder {
+            *recorder = ReplayRecorder::new(seed, stage.id);
+        }
+        let player_count = self.game.players.len();
+        self.game = Game::new(self.game.camera.aspect, seed, stage);
+        // a replay starts with one player, picking more is in its input
+        if player_count > 1 && self.replay_recorder.is_none() {
+            self.game.set_player_count(player_count);
+        }
+        self.time_scale.reset();
+    }
+
+    /// Left/Right on the title screen switch between the unlocked stages. The
+    /// game is built anew for the stage.
+    fn select_stage(&mut self) {
+        let left = self.input.get_key_state(InputID::Left).just_pressed;
+        let right = self.input.get_key_state(InputID::Right).just_pressed;
+        let step = match (left, right) {
+            (true, false) => -1,
+            (false, true) => 1,
+            _ => return,
+        };
+        let stages = unlocked_stages(&self.high_scores);
+        let current = stages.iter().position(|stage| stage.id == self.game.stage().id).unwrap_or(0);
+        let stage = stages[(current as isize + step).rem_euclid(stages.len() as isize) as usize];
+        if stage.id == self.game.stage().id {
+            return;
+        }
+        println!("Stage: {}", stage.name);
+        self.new_game(stage.clone());
+    }
+
+    /// Enters a finished run into the high-score table and prints the table.
+    /// Replays don't get here, so they can't add scores.
+    fn submit_run(&mut self, run: RunResult) {
+        let entry = HighScoreEntry {
+            time: run.time,
+            date: unix_time(),
+            seed: run.seed,
+            pattern: run.pattern,
+        };
+        let unlocked = unlocked_stages(&self.high_scores).len();
+        match self.high_scores.submit(run.mode, entry) {
+            Some(rank) => {
+                println!("New high score: {:.2} s (rank {})", run.time, rank + 1);
+                if let Err(err) = self.high_scores.save() {
+                    eprintln!("Failed to save high scores: {}", err);
+                }
+            }
+            None => println!("Survived {:.2} s", run.time),
+        }
+        let stages = unlocked_stages(&self.high_scores);
+        if stages.len() > unlocked {
+            println!("Unlocked {}", stages[stages.len() - 1].name);
+        }
+        println!("High scores ({}):", run.mode);
+        for (i, entry) in self.high_scores.top(run.mode, 5).iter().enumerate() {
+            println!(
+                "{:>2}. {:>7.2} s  {}  seed {}  {}",
+                i + 1, entry.time, format_date(entry.date), entry.seed, entry.pattern
+            );
+        }
+    }
+
+    /// Called before the event loop exits.
+    pub fn shutdown(&mut self) {
+        if let Err(err) = self.audio.finish() {
+            eprintln!("Failed to finish audio: {}", err);
+        }
+        if let Some((recorder, path)) = &self.replay_recorder {
+            match recorder.replay.save(path) {
+                Ok(()) => println!("Saved replay to {}", path.display()),
+                Err(err) => eprintln!("Failed to save replay {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    /// Power-ups in effect for the window title, empty if there are none.
+    /// In a versus run each player is listed with theirs, or as out.
+    fn players_status(players: &[Player]) -> String {
+        match players {
+            [player] => Self::power_up_status(player.power_ups),
+            players => players
+                .iter()
+                .enumerate()
+                .map(|(i, player)| match player.alive {
+                    true => format!(", P{}{}", i + 1, Self::power_up_status(player.power_ups)),
+                    false => format!(", P{} out", i + 1),
+                })
+                .collect(),
+        }
+    }
+
+    fn power_up_status(active: ActivePowerUps) -> String {
+        let mut status = String::new();
+        if active.shield {
+            status += ", Shield";
+        }
+        if active.slow_time > 0.0 {
+            status += &format!(", Slow {:.1}", active.slow_time);
+        }
+        if active.wide_gap > 0.0 {
+            status += &format!(", Wide {:.1}", active.wide_gap);
+        }
+        status
     }
 
     /// then let the `Renderer` do the actual GPU updates + rendering.
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let res = self.renderer.render(&self.game, &self.input);
+        let res = self.renderer.render(&self.game, &self.input, self.audio.music_levels(), &self.time_scale);
 
         if (self.renderer.render_frame % 10) == 0 {
+            let best = self.high_scores
+                .personal_best(self.game.stage().id)
+                .map_or(0.0, |entry| entry.time);
+            let status = match self.game.state() {
+                _ if self.attract.is_some() => "Demo, press any key".to_string(),
+                GameState::Title => format!(
+                    "{}, {} player{}, Left/Right to change stage, Up/Down to change players, Enter to start, Escape to quit",
+                    self.game.stage().name,
+                    self.game.players.len(),
+                    if self.game.is_versus() { "s" } else { "" }
+                ),
+                GameState::Playing => format!(
+                    "Time: {:.2}, Step {}, {:.0} lanes, Bonus: {:.0} (x{:.1}){}",
+                    self.game.survival_time,
+                    self.game.difficulty_step() + 1,
+                    self.game.lane_shape().sides,
+                    self.game.bonus(),
+                    self.game.combo(),
+                    Self::players_status(&self.game.players)
+                ),
+                GameState::Paused => format!("Paused at {:.2}, press Escape to resume", self.game.survival_time),
+                GameState::GameOver if self.game.is_versus() => format!(
+                    "{} after {:.2}, press Enter for a rematch or Escape for the title screen",
+                    match self.game.winner() {
+                        Some(index) => format!("Player {} wins", index + 1),
+                        None => "Draw".to_string(),
+                    },
+                    self.game.survival_time
+                ),
+                GameState::GameOver => format!(
+                    "Game over after {:.2} with {:.0} bonus, press Enter to restart or Escape for the title screen",
+                    self.game.survival_time,
+                    self.game.bonus()
+                ),
+            };
             let title = format!(
-                "Frame {}, FPS: {:.2}, UPS: {:.2}",
+                "{}, Best: {:.2}, Frame {}, FPS: {:.2}, UPS: {:.2}",
+                status,
+                best,
                 self.renderer.render_frame,
                 self.fps_counter.fps(),
                 self.fps_counter.ups()
@@ -124,14 +477,20 @@ impl<'window> Engine<'window> {
         event_loop_window_target: &EventLoopWindowTarget<()>,
     ) {
         match event {
-            WindowEvent::CloseRequested => event_loop_window_target.exit(),
+            WindowEvent::CloseRequested => {
+                self.shutdown();
+                event_loop_window_target.exit();
+            }
             WindowEvent::KeyboardInput { event, .. } => {
                 match event {
+                    // Escape is also Cancel, so it only quits from the title screen
                     KeyEvent {
                         state: ElementState::Pressed, 
                         physical_key: PhysicalKey::Code(KeyCode::Escape),
+                        repeat: false,
                         ..
-                    } => {
+                    } if self.game.state() == GameState::Title => {
+                        self.shutdown();
                         event_loop_window_target.exit();
                         return;
                     }
@@ -188,6 +547,8 @@ impl<'window> Engine<'window> {
                     Err(wgpu::SurfaceError::Timeout) => {
                         log::warn!("Surface timeout");
                     }
+                    // the web build's wgpu has more kinds of errors
+                    #[allow(unreachable_patterns)]
                     Err(e) => {
                         log::error!("Failed to render: {:?}", e);
                     }
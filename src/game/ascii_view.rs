@@ -0,0 +1,305 @@
+// Text diagrams of patterns and running games, for reviewing layouts without
+// the renderer.
+use std::{f32::consts::TAU, fs, io, path::Path};
+
+use super::pattern::Pattern;
+use super::{Game, Obstacle, ObstacleHeight, PowerUp, PowerUpKind};
+
+const WALL_COLOR: &str = "\x1b[31m";
+const MOVING_WALL_COLOR: &str = "\x1b[33m";
+const PLAYER_COLOR: &str = "\x1b[36m";
+const POWER_UP_COLOR: &str = "\x1b[32m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Draws what passes the player circle over time: one row per `row_time`
+/// seconds, the lanes side by side, `X` where a wall crosses the circle
+/// during the row (`L` for a low and `H` for a high one) and `-` where
+/// nothing does. Power-ups are drawn as `S`
+/// (shield), `T` (slow time) or `W` (wide gaps) in the row they reach the
+/// player circle, filling their lane where no wall is. Sliding and spinning walls
+/// move across the columns. Each row is labeled with its time and the
+/// distance of its walls, in pattern coordinates for a pattern and from the
+/// center for a game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AsciiView {
+    /// Rows of one page, see `render_pattern`.
+    pub rows: usize,
+    pub row_time: f32,
+    /// Characters per lane.
+    pub lane_columns: usize,
+    /// Color walls and the player with ANSI escape codes, moving walls in
+    /// their own color.
+    pub color: bool,
+}
+
+impl Default for AsciiView {
+    fn default() -> Self {
+        Self { rows: 32, row_time: 0.125, lane_columns: 4, color: false }
+    }
+}
+
+/// What to draw: walls at the start of the view, moving at `obstacle_speed`.
+struct Scene {
+    obstacles: Vec<Obstacle>,
+    power_ups: Vec<PowerUp>,
+    lanes: usize,
+    obstacle_speed: f32,
+    /// Seconds from the start of the view until the first row.
+    first_time: f32,
+    /// Distance labeled on the first row.
+    first_distance: f32,
+    /// Radius of the circle the rows are drawn at.
+    radius: f32,
+    /// Player position and width in lanes, drawn on the first row.
+    player: Option<(f32, f32)>,
+}
+
+impl AsciiView {
+    /// Number of rows until the last wall of `pattern` has passed the player circle.
+    pub fn pattern_rows(&self, pattern: &Pattern) -> usize {
+        let scene = Self::pattern_scene(pattern);
+        let end = scene
+            .obstacles
+            .iter()
+            .map(|obstacle| {
+                let end = obstacle.end + obstacle.motion.pulse_amplitude.abs() - Game::PLAYER_RADIUS;
+                end / (obstacle.speed * scene.obstacle_speed)
+            })
+            .fold(scene.first_time, f32::max);
+        ((end - scene.first_time) / self.row_time).ceil() as usize + 1
+    }
+
+    /// A page of `rows` rows of `pattern`, starting at row `first_row`. Row 0
+    /// is when the pattern's distance 0 reaches the player, at the starting
+    /// obstacle speed.
+    pub fn render_pattern(&self, pattern: &Pattern, first_row: usize) -> String {
+        let scene = Self::pattern_scene(pattern);
+        let mut out = format!("{} ({} lanes, difficulty {})\n", pattern.name, pattern.lanes, pattern.difficulty);
+        out += &self.render(&scene, first_row, self.rows);
+        out
+    }
+
+    /// The next `rows` rows of what will pass the player in `game`, if
+    /// nothing new spawns and the speed stays, with the player on the first
+    /// row. In a versus run it follows the first player.
+    pub fn render_game(&self, game: &Game) -> String {
+        let shape = game.lane_shape();
+        let first_lane_start = shape.lane_center(0) - shape.lane_width() / 2.0;
+        let first = &game.players[0];
+        let player = (first.angle - first.width / 2.0 - first_lane_start).rem_euclid(TAU) / shape.lane_width();
+        let scene = Scene {
+            obstacles: game.get_obstacles_all(),
+            power_ups: game.get_power_ups_all(),
+            lanes: game.lanes.len(),
+            obstacle_speed: game.obstacle_speed,
+            first_time: 0.0,
+            first_distance: first.radius(),
+            radius: first.radius(),
+            player: Some((player, first.width / shape.lane_width())),
+        };
+        let mut out = format!("{:.2} s, {}\n", game.survival_time, game.spawner.pattern_name().unwrap_or("-"));
+        out += &self.render(&scene, 0, self.rows);
+        out
+    }
+
+    /// Writes every row of `pattern` to `path`, without colors.
+    pub fn export_pattern(&self, pattern: &Pattern, path: &Path) -> io::Result<()> {
+        let view = Self { rows: self.pattern_rows(pattern), color: false, ..*self };
+        fs::write(path, view.render_pattern(pattern, 0))
+    }
+
+    /// The pattern as the spawner places it, the first row is when its
+    /// distance 0 reaches the player circle.
+    fn pattern_scene(pattern: &Pattern) -> Scene {
+        let obstacles = pattern
+            .obstacles
+            .iter()
+            .map(|obstacle| Obstacle {
+                start: Game::OBSTACLE_SPAWN_DISTANCE + obstacle.start,
+                end: Game::OBSTACLE_SPAWN_DISTANCE + obstacle.end,
+                lane: obstacle.lane % pattern.lanes,
+                age: 0.0,
+                ..*obstacle
+            })
+            .collect();
+        let power_ups = pattern
+            .power_ups
+            .iter()
+            .map(|power_up| PowerUp {
+                distance: Game::OBSTACLE_SPAWN_DISTANCE + power_up.distance,
+                lane: power_up.lane % pattern.lanes,
+                ..*power_up
+            })
+            .collect();
+        Scene {
+            obstacles,
+            power_ups,
+            lanes: pattern.lanes,
+            obstacle_speed: Game::OBSTACLE_SPEED,
+            first_time: (Game::OBSTACLE_SPAWN_DISTANCE - Game::PLAYER_RADIUS) / Game::OBSTACLE_SPEED,
+            first_distance: 0.0,
+            radius: Game::PLAYER_RADIUS,
+            player: None,
+        }
+    }
+
+    fn render(&self, scene: &Scene, first_row: usize, rows: usize) -> String {
+        let mut out = format!("{:>7} {:>6} ", "time", "dist");
+        for lane in 0..scene.lanes {
+            out += &format!("|{:^width$}", lane, width = self.lane_columns);
+        }
+        out += "|\n";
+        for row in first_row..first_row + rows {
+            let time = row as f32 * self.row_time;
+            let distance = scene.first_distance + time * scene.obstacle_speed;
+            out += &format!("{:>7.3} {:>6.2} ", time, distance);
+            let cells = self.row_cells(scene, scene.first_time + time, row == 0);
+            for (i, cell) in cells.into_iter().enumerate() {
+                if i % self.lane_columns == 0 {
+                    out.push('|');
+                }
+                out += &self.cell(cell);
+            }
+            out += "|\n";
+        }
+        out
+    }
+
+    /// What covers each column of the player circle between `time` and one
+    /// row later.
+    fn row_cells(&self, scene: &Scene, time: f32, with_player: bool) -> Vec<Cell> {
+        let columns = scene.lanes * self.lane_columns;
+        let column_lanes = |column: usize| (column as f32 + 0.5) / self.lane_columns as f32;
+        // lane units `position` lies in the span `[start, start + width]`, across the wrap
+        let covers = |start: f32, width: f32, position: f32| (position - start).rem_euclid(scene.lanes as f32) < width;
+        let mut cells = vec![Cell::Empty; columns];
+        for obstacle in &scene.obstacles {
+            let at = |time: f32| {
+                let mut obstacle = *obstacle;
+                obstacle.update(time, scene.obstacle_speed);
+                obstacle
+            };
+            let (now, later) = (at(time), at(time + self.row_time));
+            if later.current_start() >= scene.radius || now.current_end() <= scene.radius {
+                continue;
+            }
+            let start = obstacle.lane as f32 + now.lane_offset();
+            let wall = if obstacle.motion.is_static() { Cell::Wall(obstacle.height) } else { Cell::MovingWall(obstacle.height) };
+            for (column, cell) in cells.iter_mut().enumerate() {
+                if covers(start, 1.0, column_lanes(column)) {
+                    *cell = wall;
+                }
+            }
+        }
+        for power_up in &scene.power_ups {
+            let distance = |time: f32| power_up.distance - time * scene.obstacle_speed;
+            if distance(time) <= scene.radius || distance(time + self.row_time) > scene.radius {
+                continue;
+            }
+            for (column, cell) in cells.iter_mut().enumerate() {
+                if *cell == Cell::Empty && column / self.lane_columns == power_up.lane {
+                    *cell = Cell::PowerUp(power_up.kind);
+                }
+            }
+        }
+        if let Some((player, width)) = scene.player.filter(|_| with_player) {
+            // every column the player touches, so it shows however narrow it is
+            let column_width = 1.0 / self.lane_columns as f32;
+            for (column, cell) in cells.iter_mut().enumerate() {
+                let column_start = column as f32 * column_width;
+                if covers(player, width, column_start) || covers(column_start, column_width, player) {
+                    *cell = Cell::Player;
+                }
+            }
+        }
+        cells
+    }
+
+    fn wall_symbol(height: ObstacleHeight) -> &'static str {
+        match height {
+            ObstacleHeight::Full => "X",
+            ObstacleHeight::Low => "L",
+            ObstacleHeight::High => "H",
+        }
+    }
+
+    fn cell(&self, cell: Cell) -> String {
+        let (symbol, color) = match cell {
+            Cell::Empty => return "-".to_string(),
+            Cell::Wall(height) => (Self::wall_symbol(height), WALL_COLOR),
+            Cell::MovingWall(height) => (Self::wall_symbol(height), MOVING_WALL_COLOR),
+            Cell::Player => ("P", PLAYER_COLOR),
+            Cell::PowerUp(PowerUpKind::Shield) => ("S", POWER_UP_COLOR),
+            Cell::PowerUp(PowerUpKind::SlowTime) => ("T", POWER_UP_COLOR),
+            Cell::PowerUp(PowerUpKind::WideGap) => ("W", POWER_UP_COLOR),
+        };
+        match self.color {
+            true => format!("{}{}{}", color, symbol, RESET_COLOR),
+            false => symbol.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    Empty,
+    Wall(ObstacleHeight),
+    MovingWall(ObstacleHeight),
+    Player,
+    PowerUp(PowerUpKind),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Stage;
+
+    #[test]
+    fn draws_walls_by_lane_and_time() {
+        let mut pattern = Pattern::new("steps");
+        pattern.obstacles.push(Obstacle::new(0.0, 0.9, 0));
+        pattern.obstacles.push(Obstacle::new(2.1, 2.6, 3));
+        pattern.obstacles.push(Obstacle { height: ObstacleHeight::Low, ..Obstacle::new(2.1, 2.6, 5) });
+        pattern.add_power_up(PowerUp::new(PowerUpKind::Shield, 1.2, 1));
+        let view = AsciiView { rows: 8, row_time: 0.125, lane_columns: 2, color: false };
+        let text = view.render_pattern(&pattern, 0);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "steps (6 lanes, difficulty 1)");
+        assert_eq!(lines[1], "   time   dist |0 |1 |2 |3 |4 |5 |");
+        // at 4 units per second a row is half a unit of distance
+        assert_eq!(lines[2], "  0.000   0.00 |XX|--|--|--|--|--|");
+        assert_eq!(lines[3], "  0.125   0.50 |XX|--|--|--|--|--|");
+        assert_eq!(lines[4], "  0.250   1.00 |--|SS|--|--|--|--|");
+        assert_eq!(lines[5], "  0.375   1.50 |--|--|--|--|--|--|");
+        assert_eq!(lines[6], "  0.500   2.00 |--|--|--|XX|--|LL|");
+        assert_eq!(lines.len(), 10);
+        assert_eq!(view.pattern_rows(&pattern), 7);
+
+        // scrolling starts at a later row, colors mark the walls
+        let view = AsciiView { color: true, ..view };
+        let page = view.render_pattern(&pattern, 4);
+        assert!(page.lines().nth(2).unwrap().starts_with("  0.500   2.00 |--|--|--|\x1b[31mX\x1b[0m"));
+
+        let path = std::env::temp_dir().join(format!("ascii-view-test-{}.txt", std::process::id()));
+        view.export_pattern(&pattern, &path).unwrap();
+        let exported = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(exported.lines().count(), 2 + 7);
+        assert!(!exported.contains('\x1b'));
+    }
+
+    #[test]
+    fn draws_the_player_of_a_running_game() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        game.start();
+        game.players[0].angle = game.lane_shape().lane_center(0);
+        game.lanes[1].obstacles.push(Obstacle::new(Game::PLAYER_RADIUS + 0.1, 5.0, 1));
+        let view = AsciiView { rows: 2, row_time: 0.125, lane_columns: 2, color: false };
+        let text = view.render_game(&game);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        // the player is in lane 0, the wall in lane 1
+        assert_eq!(&lines[2][15..], "|PP|XX|--|--|--|--|");
+        assert_eq!(&lines[3][15..], "|--|XX|--|--|--|--|");
+    }
+}
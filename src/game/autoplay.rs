@@ -0,0 +1,352 @@
+use std::f32::consts::TAU;
+
+use super::collision::{self, Arc};
+use super::{Game, GameState, Obstacle};
+use crate::input::{InputID, VirtualInput};
+
+/// What the bot holds during one planning step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Move {
+    Stay,
+    Left,
+    Right,
+}
+
+impl Move {
+    fn keys(self) -> &'static [InputID] {
+        match self {
+            Move::Stay => &[],
+            Move::Left => &[InputID::Left],
+            Move::Right => &[InputID::Right],
+        }
+    }
+}
+
+/// Computer player, for attract mode and for testing that patterns can be
+/// survived.
+///
+/// It sees what the player sees, the obstacles and the lanes, and presses
+/// keys on a `VirtualInput` like the player presses them on the keyboard.
+/// Each update it searches for a way through the obstacles: they are moved
+/// ahead in `STEP`s up to `HORIZON` seconds, and every position the player
+/// can reach by holding Left, Right or nothing during each step is followed
+/// with the game's player speed and width and its collision rules. The
+/// first move of the path that survives the longest is pressed, standing
+/// still if that is as good, then keeping the direction of the last move.
+/// It never jumps, ducks or hops to another ring, every wall is one to get
+/// around on the ring the player is on. In a versus run it plays the first
+/// player.
+#[derive(Debug, Clone)]
+pub struct Autoplay {
+    last_move: Move,
+}
+
+impl Autoplay {
+    /// Seconds between the planned positions.
+    pub const STEP: f32 = 1.0 / 30.0;
+    /// Seconds planned ahead. New obstacles need longer than this to get
+    /// from the spawn distance to the player, so the plan sees all of them.
+    pub const HORIZON: f32 = 1.5;
+
+    pub fn new() -> Self {
+        Self { last_move: Move::Stay }
+    }
+
+    /// Keys to hold for the next update of `game`: Confirm on the title
+    /// screen, the planned move during a run.
+    pub fn keys(&mut self, game: &Game) -> &'static [InputID] {
+        match game.state() {
+            GameState::Title => &[InputID::Confirm],
+            GameState::Playing => {
+                self.last_move = self.plan(game);
+                self.last_move.keys()
+            }
+            GameState::Paused | GameState::GameOver => &[],
+        }
+    }
+
+    /// Presses the keys for the next update of `game` on `input`.
+    pub fn drive(&mut self, game: &Game, input: &mut VirtualInput) {
+        input.set_pressed(self.keys(game));
+    }
+
+    fn plan(&self, game: &Game) -> Move {
+        let steps = (Self::HORIZON / Self::STEP).round() as usize;
+        let (sides, hits) = Self::predict_arcs(game, steps);
+        if hits.iter().all(Vec::is_empty) {
+            return Move::Stay;
+        }
+        let distance = game.player_speed * Self::STEP;
+        // the first of the moves that survive the longest
+        let mut best = (Move::Stay, 0);
+        for first in self.preference() {
+            let movement = match first {
+                Move::Stay => 0.0,
+                Move::Left => -distance * game.screen_sense(),
+                Move::Right => distance * game.screen_sense(),
+            };
+            let player = &game.players[0];
+            let survived = Self::steps_survived(player.angle, movement, distance, player.width, &sides, &hits);
+            if survived == steps {
+                return first;
+            }
+            if survived > best.1 {
+                best = (first, survived);
+            }
+        }
+        best.0
+    }
+
+    /// Number of steps the player at `angle` can survive after moving by
+    /// `movement` during the first step and up to `distance` either way
+    /// during each one after it.
+    ///
+    /// The positions the player can be at are kept as intervals: each step
+    /// they grow by `distance` at both ends, stopping at the sides of
+    /// obstacles like the game does, and lose the positions where the player
+    /// would overlap an obstacle by the end of the step.
+    fn steps_survived(angle: f32, movement: f32, distance: f32, width: f32, sides: &[Vec<Arc>], hits: &[Vec<Arc>]) -> usize {
+        let player = |angle| Arc::centered(angle, width);
+        let moved = angle + collision::sweep(player(angle), movement, &sides[0]);
+        let mut reachable = vec![(moved, moved)];
+        for (step, (sides, hits)) in sides.iter().zip(hits).enumerate() {
+            if step > 0 {
+                for (low, high) in &mut reachable {
+                    if *high - *low < TAU {
+                        *low += collision::sweep(player(*low), -distance, sides);
+                        *high += collision::sweep(player(*high), distance, sides);
+                    }
+                }
+            }
+            reachable = Self::remove_hits(&reachable, hits, width);
+            if reachable.is_empty() {
+                return step;
+            }
+        }
+        sides.len()
+    }
+
+    /// `reachable` without the player positions that overlap one of the
+    /// `hits`, with the intervals that now overlap merged.
+    fn remove_hits(reachable: &[(f32, f32)], hits: &[Arc], width: f32) -> Vec<(f32, f32)> {
+        let mut left = Vec::new();
+        for &(low, high) in reachable {
+            let high = high.min(low + TAU);
+            // positions that overlap a hit, in every turn that touches the interval
+            let mut blocked: Vec<(f32, f32)> = Vec::new();
+            for hit in hits {
+                let (start, end) = (hit.start - width / 2.0, hit.end() + width / 2.0);
+                let mut turn = ((low - end) / TAU).floor();
+                while start + turn * TAU <= high {
+                    blocked.push((start + turn * TAU, end + turn * TAU));
+                    turn += 1.0;
+                }
+            }
+            blocked.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut from = low;
+            for (start, end) in blocked {
+                if start - collision::SIDE_MARGIN >= from {
+                    left.push((from, start - collision::SIDE_MARGIN));
+                }
+                from = from.max(end + collision::SIDE_MARGIN);
+            }
+            if from <= high {
+                left.push((from, high));
+            }
+        }
+
+        // intervals that grew into each other are one, also across a full turn
+        for (low, high) in &mut left {
+            let turns = (*low / TAU).floor() * TAU;
+            *low -= turns;
+            *high -= turns;
+        }
+        left.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut merged: Vec<(f32, f32)> = Vec::with_capacity(left.len());
+        for (low, high) in left {
+            match merged.last_mut() {
+                Some(last) if low <= last.1 => last.1 = last.1.max(high),
+                _ => merged.push((low, high)),
+            }
+        }
+        if let (Some(&(first_low, _)), Some(last)) = (merged.first(), merged.last()) {
+            if merged.len() > 1 && last.1 >= first_low + TAU {
+                let (_, first_high) = merged.remove(0);
+                let last = merged.last_mut().unwrap();
+                last.1 = last.1.max(first_high + TAU);
+            }
+        }
+        merged
+    }
+
+    /// For each planning step, the arcs of the player circle covered by
+    /// obstacles at its start, which the player stops at, and the arcs
+    /// covered at any time during it, which the player must not overlap at
+    /// its end.
+    fn predict_arcs(game: &Game, steps: usize) -> (Vec<Vec<Arc>>, Vec<Vec<Arc>>) {
+        let obstacles = game.get_obstacles_all();
+        let shape = game.lane_shape();
+        let radius = game.players[0].radius();
+        // slow time holds the walls back until it runs out, the player keeps its speed
+        let world_speed = game.world_speed();
+        let slow_time = game.players.iter()
+            .filter(|player| player.alive)
+            .map(|player| player.power_ups.slow_time)
+            .fold(0.0, f32::max);
+        let world_time = |time: f32| world_speed * time.min(slow_time) + (time - slow_time).max(0.0);
+        let at = |obstacle: &Obstacle, time: f32| {
+            let mut obstacle = *obstacle;
+            obstacle.update(world_time(time), game.obstacle_speed);
+            obstacle
+        };
+        let arcs = |obstacle: &Obstacle| collision::obstacle_arcs(obstacle, &shape, radius);
+
+        let mut sides = vec![Vec::new(); steps];
+        let mut hits = vec![Vec::new(); steps];
+        for obstacle in &obstacles {
+            // the steps during which the obstacle can cover part of the circle, it
+            // doesn't once its end is closer than the circle at the corners of its lane;
+            // slow time makes it arrive at the earliest at full speed and leave at
+            // the latest at the slowed speed
+            let speed = obstacle.speed * game.obstacle_speed;
+            let swing = obstacle.motion.pulse_amplitude.abs();
+            let arrival = (obstacle.current_start() - swing - radius) / speed / Self::STEP;
+            let departure = (obstacle.current_end() + swing - 0.5 * radius) / (speed * world_speed) / Self::STEP;
+            let first = (arrival.floor().max(1.0) as usize - 1).min(steps);
+            let last = (departure.ceil().max(0.0) as usize + 1).min(steps);
+            for step in first..last {
+                let time = step as f32 * Self::STEP;
+                let now = at(obstacle, time);
+                sides[step].extend(arcs(&now).into_iter().flatten());
+                let later = at(obstacle, time + Self::STEP);
+                hits[step].extend(collision::swept_obstacle_arcs(&now, &later, &shape, radius));
+            }
+        }
+        (sides, hits)
+    }
+
+    /// The moves in the order they are preferred when they survive equally
+    /// long: standing still, then the direction of the last move.
+    fn preference(&self) -> [Move; 3] {
+        match self.last_move {
+            Move::Right => [Move::Stay, Move::Right, Move::Left],
+            Move::Stay | Move::Left => [Move::Stay, Move::Left, Move::Right],
+        }
+    }
+}
+
+impl Default for Autoplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::difficulty::Difficulty;
+    use crate::game::spawner::{spawn_pattern, PatternTransforms, SpawnerInterface};
+    use crate::game::{Lane, LaneShape, Pattern, PowerUpKind, Stage};
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// Spawns one pattern on the first update and nothing after it.
+    struct SinglePattern(Option<Pattern>);
+
+    impl SpawnerInterface for SinglePattern {
+        fn update(&mut self, _dt: f32, lanes: &mut [Lane], _difficulty: &Difficulty) -> bool {
+            let Some(pattern) = self.0.take() else {
+                return false;
+            };
+            spawn_pattern(&pattern, lanes);
+            true
+        }
+
+        fn pattern_name(&self) -> Option<&str> {
+            None
+        }
+
+        fn reset(&mut self, _seed: u64) {}
+
+        fn spawn_named(&mut self, _name: &str, _lanes: &mut [Lane]) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn dodges_a_wall() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        let mut bot = Autoplay::new();
+        assert_eq!(bot.keys(&game), &[InputID::Confirm]);
+        game.start();
+        game.spawner = Box::new(SinglePattern(None));
+        // walls in the player's lane and the lanes next to it, only two lanes over is safe
+        let shape = game.lane_shape();
+        let lane = 2;
+        game.players[0].angle = shape.lane_center(lane);
+        for neighbor in [lane - 1, lane, lane + 1] {
+            game.lanes[neighbor].obstacles.push(Obstacle::new(6.0, 7.0, neighbor));
+        }
+        let mut input = VirtualInput::new();
+        while !game.get_obstacles_all().is_empty() {
+            bot.drive(&game, &mut input);
+            game.update(DT, &input);
+            assert!(!game.is_game_over());
+        }
+        let escaped = shape.lane_at_angle(game.players[0].angle);
+        assert!(escaped == lane + 2 || escaped == (lane + 4) % 6, "in lane {}", escaped);
+        // with nothing in the way it stands still
+        bot.drive(&game, &mut input);
+        assert_eq!(bot.last_move, Move::Stay);
+    }
+
+    /// Every default pattern, on its own, at the start of every difficulty
+    /// step and with the speed of every stage, with and without slow time.
+    #[test]
+    fn default_patterns_are_survivable() {
+        let mut patterns = Game::load_patterns();
+        for pattern in &mut patterns {
+            // the tightest the spawner makes them
+            pattern.stretch(PatternTransforms::ALL.stretch_min);
+        }
+        for stage in Stage::all() {
+            let mut game = Game::new(1.0, 1, stage.clone());
+            let mut schedule = game.difficulty_schedule.steps().to_vec();
+            // the later steps only change the lanes and the rotation, which don't matter here
+            schedule.dedup_by(|b, a| (a.obstacle_speed, a.player_speed) == (b.obstacle_speed, b.player_speed));
+            for step in schedule {
+                for (pattern, slow_time) in patterns.iter().flat_map(|p| [(p, false), (p, true)]) {
+                    game.start();
+                    if slow_time {
+                        game.players[0].power_ups.collect(PowerUpKind::SlowTime);
+                    }
+                    game.spawner = Box::new(SinglePattern(Some(pattern.clone())));
+                    game.supported_lane_counts = vec![pattern.lanes];
+                    game.lanes = Game::empty_lanes(pattern.lanes);
+                    game.shape = LaneShape::regular(pattern.lanes);
+                    game.target_lane_count = pattern.lanes;
+                    game.survival_time = step.start_time;
+                    game.update_difficulty();
+
+                    let mut bot = Autoplay::new();
+                    let mut input = VirtualInput::new();
+                    loop {
+                        bot.drive(&game, &mut input);
+                        game.update(DT, &input);
+                        assert!(
+                            !game.is_game_over(),
+                            "{} at {} s{}: hit {} after {:.2} s",
+                            stage.id, step.start_time, if slow_time { " in slow time" } else { "" },
+                            pattern.name, game.survival_time - step.start_time
+                        );
+                        let obstacles = game.get_obstacles_all();
+                        if obstacles.iter().all(|obstacle| obstacle.current_end() < Game::PLAYER_RADIUS) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
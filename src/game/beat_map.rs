@@ -0,0 +1,187 @@
+// Beat maps, sidecar files next to a music track with the same name and the
+// `.beats` extension. Lines starting with `#` are comments.
+//
+//   beat <seconds>...      beats, in seconds from the start of the track
+//   bar <seconds>...       bar lines, each one is also a beat
+//
+// Times can be listed in any order, over as many lines as needed.
+use std::{
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::pattern_file::tokenize;
+
+pub const BEAT_MAP_EXTENSION: &str = "beats";
+
+/// Beat and bar times of a music track, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeatMap {
+    beats: Vec<f32>,
+    bars: Vec<f32>,
+}
+
+impl BeatMap {
+    /// Bar lines are added to the beats if they are not in there already.
+    pub fn new(mut beats: Vec<f32>, mut bars: Vec<f32>) -> Self {
+        beats.extend(bars.iter().copied());
+        beats.sort_by(f32::total_cmp);
+        beats.dedup();
+        bars.sort_by(f32::total_cmp);
+        bars.dedup();
+        Self { beats, bars }
+    }
+
+    pub fn beats(&self) -> &[f32] {
+        &self.beats
+    }
+
+    pub fn bars(&self) -> &[f32] {
+        &self.bars
+    }
+
+    /// Index of the last beat at or before `time`.
+    pub fn beat_at(&self, time: f32) -> Option<usize> {
+        self.beats.partition_point(|&beat| beat <= time).checked_sub(1)
+    }
+
+    /// Index of the bar `time` is in.
+    pub fn bar_at(&self, time: f32) -> Option<usize> {
+        self.bars.partition_point(|&bar| bar <= time).checked_sub(1)
+    }
+
+    /// How far `time` is between two beats, 0 right on a beat and close to 1
+    /// right before the next one. Before the first and after the last beat the
+    /// nearest beat interval is repeated.
+    pub fn beat_phase(&self, time: f32) -> f32 {
+        if self.beats.len() < 2 {
+            return 0.0;
+        }
+        let last = self.beats.len() - 1;
+        let i = self.beat_at(time).unwrap_or(0).min(last - 1);
+        let (start, end) = (self.beats[i], self.beats[i + 1]);
+        ((time - start) / (end - start)).rem_euclid(1.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeatMapParseError {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for BeatMapParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "<beat map>".to_string(),
+        };
+        write!(f, "{}:{}:{}: {}", path, self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for BeatMapParseError {}
+
+#[derive(Debug)]
+pub enum BeatMapLoadError {
+    Io(PathBuf, io::Error),
+    Parse(BeatMapParseError),
+}
+
+impl fmt::Display for BeatMapLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BeatMapLoadError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            BeatMapLoadError::Parse(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BeatMapLoadError {}
+
+/// Parses a beat map from `source`. `path` is only used for error messages.
+pub fn parse_beat_map(source: &str, path: Option<&Path>) -> Result<BeatMap, BeatMapParseError> {
+    let mut beats = vec![];
+    let mut bars = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let error = |column, message: String| BeatMapParseError {
+            path: path.map(Path::to_path_buf),
+            line: i + 1,
+            column,
+            message,
+        };
+        let content = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let tokens = tokenize(content);
+        let Some(first) = tokens.first() else {
+            continue;
+        };
+        let times = match first.text {
+            "beat" => &mut beats,
+            "bar" => &mut bars,
+            other => return Err(error(first.column, format!("expected `beat` or `bar`, found `{}`", other))),
+        };
+        if tokens.len() == 1 {
+            let column = first.column + first.text.chars().count();
+            return Err(error(column, format!("missing time, expected `{} <seconds>...`", first.text)));
+        }
+        for token in &tokens[1..] {
+            match token.text.parse::<f32>() {
+                Ok(time) if time.is_finite() && time >= 0.0 => times.push(time),
+                _ => return Err(error(token.column, format!("expected a time in seconds, found `{}`", token.text))),
+            }
+        }
+    }
+    Ok(BeatMap::new(beats, bars))
+}
+
+pub fn load_beat_map(path: &Path) -> Result<BeatMap, BeatMapLoadError> {
+    let source = fs::read_to_string(path).map_err(|e| BeatMapLoadError::Io(path.to_path_buf(), e))?;
+    parse_beat_map(&source, Some(path)).map_err(BeatMapLoadError::Parse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_queries_beats_and_bars() {
+        let source = "\
+            # two bars of 3/4 at 120 BPM\n\
+            bar 0 1.5\n\
+            beat 0.5 1   # out of order is fine\n\
+            beat 2.5 2\n";
+        let map = parse_beat_map(source, None).unwrap();
+        assert_eq!(map.beats(), &[0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+        assert_eq!(map.bars(), &[0.0, 1.5]);
+
+        assert_eq!(map.beat_at(0.75), Some(1));
+        assert_eq!(map.beat_at(1.0), Some(2));
+        assert_eq!(map.bar_at(1.4), Some(0));
+        assert_eq!(map.bar_at(9.0), Some(1));
+        assert!((map.beat_phase(1.25) - 0.5).abs() < 1e-6);
+        assert_eq!(map.beat_phase(2.0), 0.0);
+        // the first and last intervals repeat outside the map
+        assert!((map.beat_phase(2.875) - 0.75).abs() < 1e-6);
+
+        let empty = parse_beat_map("# nothing yet\n", None).unwrap();
+        assert_eq!((empty.beat_at(1.0), empty.bar_at(1.0), empty.beat_phase(1.0)), (None, None, 0.0));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let err = parse_beat_map("beat 0\n  tempo 120\n", None).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+        let err = parse_beat_map("bar 0 -1\n", None).unwrap_err();
+        assert_eq!((err.line, err.column), (1, 7));
+        let err = parse_beat_map("bar\n", None).unwrap_err();
+        assert_eq!((err.line, err.column), (1, 4));
+        assert!(err.message.contains("bar <seconds>"), "{}", err);
+    }
+}
@@ -0,0 +1,270 @@
+use std::f32::consts::TAU;
+
+use super::{LaneShape, Obstacle};
+
+/// Gap left between the player and an obstacle side it is pushed against, so
+/// that the clamped position doesn't count as an overlap.
+pub(crate) const SIDE_MARGIN: f32 = 1e-4;
+
+/// Angular interval `[start, start + length]` on the player circle. `start`
+/// can be in any turn, all functions here work modulo a full turn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Arc {
+    pub start: f32,
+    pub length: f32,
+}
+
+impl Arc {
+    pub fn centered(center: f32, width: f32) -> Self {
+        Self { start: center - width / 2.0, length: width }
+    }
+
+    pub fn end(&self) -> f32 {
+        self.start + self.length
+    }
+
+    pub fn contains(&self, angle: f32) -> bool {
+        (angle - self.start).rem_euclid(TAU) <= self.length
+    }
+}
+
+pub(crate) fn arcs_overlap(a: Arc, b: Arc) -> bool {
+    a.contains(b.start) || b.contains(a.start)
+}
+
+/// Angle between the nearest ends of `a` and `b`, 0 if they overlap.
+pub(crate) fn arc_gap(a: Arc, b: Arc) -> f32 {
+    if arcs_overlap(a, b) {
+        return 0.0;
+    }
+    (b.start - a.end()).rem_euclid(TAU).min((a.start - b.end()).rem_euclid(TAU))
+}
+
+/// Parts of the circle with `radius` covered by `obstacle`.
+///
+/// An obstacle is drawn as a trapezoid (see `obstacleSdfPlanes` in
+/// scene_geometry.wgsl) that fills its lane, turned by its lane offset, between
+/// its current start and end distance measured along the lane's center
+/// direction. A point of the circle at `delta` from the lane center is at
+/// distance `radius * cos(delta)`, so the covered part is
+/// `acos(end / radius) <= |delta| <= acos(start / radius)`, cut to the lane.
+/// That is one arc, or two if `end` is inside the circle.
+pub(crate) fn obstacle_arcs(obstacle: &Obstacle, shape: &LaneShape, radius: f32) -> [Option<Arc>; 2] {
+    let (lane_start, lane_end) = shape.lane_bounds(obstacle.lane);
+    let center = (lane_start + lane_end) / 2.0 + obstacle.lane_offset() * shape.lane_width();
+    let half_width = (lane_end - lane_start) / 2.0;
+    let (start, end) = (obstacle.current_start(), obstacle.current_end());
+
+    if start > radius || end <= 0.0 {
+        return [None, None];
+    }
+    let outer = (start / radius).max(-1.0).acos().min(half_width);
+    let inner = (end / radius).min(1.0).acos();
+    if inner > outer {
+        return [None, None];
+    }
+    if inner == 0.0 {
+        return [Some(Arc { start: center - outer, length: 2.0 * outer }), None];
+    }
+    [
+        Some(Arc { start: center - outer, length: outer - inner }),
+        Some(Arc { start: center + inner, length: outer - inner }),
+    ]
+}
+
+/// Parts of the circle covered by an obstacle at any time while it moves
+/// from `now` to `later`, for time steps too long to only check their ends.
+/// The obstacle is stretched over the distances it passes during the step,
+/// at the lane offsets of both ends.
+pub(crate) fn swept_obstacle_arcs(now: &Obstacle, later: &Obstacle, shape: &LaneShape, radius: f32) -> Vec<Arc> {
+    let closer = (later.current_start() - now.current_start()).max(0.0);
+    let farther = (now.current_end() - later.current_end()).max(0.0);
+    let mut arcs = Vec::new();
+    for mut swept in [*now, *later] {
+        swept.start -= closer;
+        swept.end += farther;
+        arcs.extend(obstacle_arcs(&swept, shape, radius).into_iter().flatten());
+    }
+    arcs
+}
+
+/// How far `player` can move by `movement` radians (positive is counter-clockwise)
+/// before its leading side touches one of the `blocked` arcs. Arcs the player
+/// already overlaps are ignored, those are a collision and not a side push.
+pub(crate) fn sweep(player: Arc, movement: f32, blocked: &[Arc]) -> f32 {
+    let mut allowed = movement.abs();
+    for arc in blocked {
+        if arcs_overlap(player, *arc) {
+            continue;
+        }
+        let distance = if movement > 0.0 {
+            (arc.start - player.end()).rem_euclid(TAU)
+        } else {
+            (player.start - arc.end()).rem_euclid(TAU)
+        };
+        allowed = allowed.min((distance - SIDE_MARGIN).max(0.0));
+    }
+    allowed.copysign(movement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::rng::Rng;
+    use crate::game::ObstacleMotion;
+
+    const RADIUS: f32 = 3.0;
+
+    fn random_arc(rng: &mut Rng) -> Arc {
+        // starts around 0 / 2π, negative and several turns away
+        let start = rng.range_f32(-0.3, 0.3) + TAU * rng.below(7) as f32 - 3.0 * TAU;
+        Arc { start, length: rng.range_f32(0.01, 1.2) }
+    }
+
+    fn random_case(rng: &mut Rng) -> (Arc, f32, Vec<Arc>) {
+        let player = Arc::centered(rng.range_f32(-2.0 * TAU, 2.0 * TAU), rng.range_f32(0.05, 0.5));
+        let movement = rng.range_f32(-8.0, 8.0);
+        let blocked = (0..rng.below(4))
+            .map(|_| {
+                let mut arc = random_arc(rng);
+                arc.start += rng.range_f32(0.0, TAU);
+                arc
+            })
+            .collect();
+        (player, movement, blocked)
+    }
+
+    /// Point-in-trapezoid test written against the shader geometry: inside if
+    /// the distance along the lane normal is in `[start, end]` and the
+    /// sideways offset is within the lane's half width at that distance.
+    fn inside_trapezoid(angle: f32, obstacle: &Obstacle, shape: &LaneShape) -> bool {
+        let (lane_start, lane_end) = shape.lane_bounds(obstacle.lane);
+        let center = (lane_start + lane_end) / 2.0 + obstacle.lane_offset() * shape.lane_width();
+        let half_width = (lane_end - lane_start) / 2.0;
+        let (x, y) = (RADIUS * angle.cos(), RADIUS * angle.sin());
+        let along = x * center.cos() + y * center.sin();
+        let across = -x * center.sin() + y * center.cos();
+        along >= obstacle.current_start() && along <= obstacle.current_end() && across.abs() <= along * half_width.tan()
+    }
+
+    #[test]
+    fn arcs_match_rendered_trapezoid() {
+        let mut rng = Rng::new(1);
+        for lane_count in 4..=8 {
+            let shape = LaneShape::regular(lane_count);
+            for _ in 0..200 {
+                let start = rng.range_f32(1.0, 4.0);
+                let mut obstacle = Obstacle::new(start, start + rng.range_f32(0.05, 2.0), rng.below(lane_count));
+                if rng.chance(0.5) {
+                    obstacle.motion = ObstacleMotion {
+                        slide_lanes: rng.range_f32(-3.0, 3.0),
+                        slide_time: rng.range_f32(0.5, 3.0),
+                        spin: rng.range_f32(-1.0, 1.0),
+                        pulse_amplitude: rng.range_f32(0.0, 1.0),
+                        pulse_period: rng.range_f32(0.5, 2.0),
+                    };
+                    obstacle.age = rng.range_f32(0.0, 5.0);
+                }
+                let arcs = obstacle_arcs(&obstacle, &shape, RADIUS);
+                for i in 0..720 {
+                    let angle = i as f32 / 720.0 * TAU - TAU;
+                    let in_arcs = arcs.iter().flatten().any(|arc| arc.contains(angle));
+                    if in_arcs != inside_trapezoid(angle, &obstacle, &shape) {
+                        // only allowed right at an edge, where rounding decides
+                        let near_edge = arcs.iter().flatten().any(|arc| {
+                            Arc::centered(arc.start, 1e-3).contains(angle) || Arc::centered(arc.end(), 1e-3).contains(angle)
+                        });
+                        assert!(near_edge, "{:?} at angle {}: arcs {:?}", obstacle, angle, arcs);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_never_ends_inside_an_obstacle() {
+        let mut rng = Rng::new(2);
+        for _ in 0..20000 {
+            let (player, movement, blocked) = random_case(&mut rng);
+            let allowed = sweep(player, movement, &blocked);
+            assert!(allowed.abs() <= movement.abs());
+            assert!(allowed == 0.0 || allowed.signum() == movement.signum());
+
+            let moved = Arc { start: player.start + allowed, ..player };
+            for arc in &blocked {
+                if !arcs_overlap(player, *arc) {
+                    assert!(!arcs_overlap(moved, *arc), "{:?} moved {} into {:?}", player, allowed, arc);
+                    let gap = arc_gap(moved, *arc);
+                    assert!(gap > 0.0 && (gap - arc_gap(*arc, moved)).abs() < 1e-4, "{:?} {:?}", moved, arc);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_never_tunnels() {
+        let mut rng = Rng::new(3);
+        for _ in 0..5000 {
+            let (player, movement, blocked) = random_case(&mut rng);
+            let allowed = sweep(player, movement, &blocked);
+            // every position along the way is free as well
+            for step in 0..=50 {
+                let position = Arc { start: player.start + allowed * step as f32 / 50.0, ..player };
+                for arc in blocked.iter().filter(|arc| !arcs_overlap(player, **arc)) {
+                    assert!(!arcs_overlap(position, *arc), "{:?} passed through {:?}", player, arc);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_clamps_against_the_nearest_side() {
+        let mut rng = Rng::new(4);
+        for _ in 0..5000 {
+            let (player, movement, blocked) = random_case(&mut rng);
+            let allowed = sweep(player, movement, &blocked);
+            if allowed.abs() < movement.abs() {
+                let moved = Arc { start: player.start + allowed, ..player };
+                let touching = blocked.iter().any(|arc| {
+                    let gap = if movement > 0.0 {
+                        (arc.start - moved.end()).rem_euclid(TAU)
+                    } else {
+                        (moved.start - arc.end()).rem_euclid(TAU)
+                    };
+                    gap <= 2.0 * SIDE_MARGIN + 1e-4
+                });
+                assert!(touching || allowed == 0.0, "{:?} stopped short after {} of {}", player, allowed, movement);
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_is_the_same_in_every_turn() {
+        let mut rng = Rng::new(5);
+        for _ in 0..2000 {
+            let (player, movement, blocked) = random_case(&mut rng);
+            let allowed = sweep(player, movement, &blocked);
+            for turns in [-2.0, -1.0, 1.0, 3.0] {
+                let shifted = Arc { start: player.start + turns * TAU, ..player };
+                let shifted_allowed = sweep(shifted, movement, &blocked);
+                assert!((allowed - shifted_allowed).abs() < 1e-3, "{} vs {}", allowed, shifted_allowed);
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_across_zero() {
+        // obstacle side at 0.1, player left of it just below 2π
+        let blocked = [Arc { start: 0.1, length: 0.5 }];
+        let player = Arc::centered(TAU - 0.2, 0.2);
+        let allowed = sweep(player, 1.0, &blocked);
+        assert!((allowed - 0.2).abs() < 1e-3, "{}", allowed);
+        // the same from a negative angle
+        let player = Arc::centered(-0.2, 0.2);
+        assert!((sweep(player, 1.0, &blocked) - 0.2).abs() < 1e-3);
+        // and from the other side, through 2π
+        let player = Arc::centered(0.9 - TAU, 0.2);
+        let allowed = sweep(player, -5.0, &blocked);
+        assert!((allowed + 0.2).abs() < 1e-3, "{}", allowed);
+    }
+}
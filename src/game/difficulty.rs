@@ -0,0 +1,206 @@
+use std::ops::RangeInclusive;
+
+use super::Game;
+
+/// One step of the difficulty curve, active from `start_time` seconds of survival.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DifficultyStep {
+    pub start_time: f32,
+    /// Pattern difficulty levels the spawner picks from during this step.
+    pub pattern_levels: RangeInclusive<u32>,
+    pub obstacle_speed: f32,
+    pub player_speed: f32,
+    /// Patterns follow each other this much faster than their nominal duration.
+    pub density: f32,
+    /// The lanes morph to this count when the step starts.
+    pub lane_count: usize,
+    /// Radians per second the arena turns, see `ArenaRotation`.
+    pub rotation_speed: f32,
+}
+
+/// Gameplay parameters at a point in time, see `DifficultySchedule::at`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Difficulty {
+    pub step: usize,
+    pub pattern_levels: RangeInclusive<u32>,
+    pub obstacle_speed: f32,
+    pub player_speed: f32,
+    pub density: f32,
+    pub lane_count: usize,
+    pub rotation_speed: f32,
+}
+
+pub(crate) struct DifficultySchedule {
+    steps: Vec<DifficultyStep>,
+}
+
+impl DifficultySchedule {
+    /// `steps` must be sorted by `start_time` and the first step must start at 0.
+    pub fn new(steps: Vec<DifficultyStep>) -> Self {
+        assert!(!steps.is_empty(), "Difficulty schedule needs at least one step");
+        assert!(steps[0].start_time == 0.0, "The first difficulty step must start at 0");
+        assert!(
+            steps.windows(2).all(|w| w[0].start_time < w[1].start_time),
+            "Difficulty steps must be sorted by start time"
+        );
+        Self { steps }
+    }
+
+    pub fn default_schedule() -> Self {
+        let step = |start_time, pattern_levels, obstacle_speed, player_speed, density, lane_count, rotation_speed| DifficultyStep {
+            start_time,
+            pattern_levels,
+            obstacle_speed,
+            player_speed,
+            density,
+            lane_count,
+            rotation_speed,
+        };
+        Self::new(vec![
+            step(0.0, 1..=1, Game::OBSTACLE_SPEED, 4.0, 1.0, 6, 0.5),
+            step(10.0, 1..=2, 4.5, 4.3, 1.1, 6, 0.7),
+            step(25.0, 1..=3, 5.5, 4.8, 1.2, 6, 0.9),
+            step(40.0, 2..=3, 6.5, 5.4, 1.35, 6, 1.2),
+            step(60.0, 2..=3, 7.5, 6.0, 1.5, 6, 1.5),
+            // like Super Hexagon, the hexagon turns into a pentagon and a square late in the run
+            step(75.0, 2..=3, 7.5, 6.0, 1.5, 5, 1.5),
+            step(90.0, 2..=3, 7.5, 6.0, 1.5, 4, 1.5),
+            step(105.0, 2..=3, 7.5, 6.0, 1.5, 6, 1.8),
+        ])
+    }
+
+    #[cfg(test)]
+    pub fn steps(&self) -> &[DifficultyStep] {
+        &self.steps
+    }
+
+    /// Speeds and density ramp linearly towards the next step, the pattern
+    /// levels switch when the next step starts.
+    pub fn at(&self, time: f32) -> Difficulty {
+        let index = self.steps
+            .iter()
+            .rposition(|step| step.start_time <= time)
+            .unwrap_or(0);
+        let current = &self.steps[index];
+        let (obstacle_speed, player_speed, density, rotation_speed) = match self.steps.get(index + 1) {
+            Some(next) => {
+                let t = ((time - current.start_time) / (next.start_time - current.start_time)).clamp(0.0, 1.0);
+                (
+                    lerp(current.obstacle_speed, next.obstacle_speed, t),
+                    lerp(current.player_speed, next.player_speed, t),
+                    lerp(current.density, next.density, t),
+                    lerp(current.rotation_speed, next.rotation_speed, t),
+                )
+            }
+            None => (current.obstacle_speed, current.player_speed, current.density, current.rotation_speed),
+        };
+        Difficulty {
+            step: index,
+            pattern_levels: current.pattern_levels.clone(),
+            obstacle_speed,
+            player_speed,
+            density,
+            lane_count: current.lane_count,
+            rotation_speed,
+        }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(start_time: f32, levels: RangeInclusive<u32>, obstacle_speed: f32) -> DifficultyStep {
+        DifficultyStep {
+            start_time,
+            pattern_levels: levels,
+            obstacle_speed,
+            player_speed: obstacle_speed,
+            density: 1.0,
+            lane_count: 6,
+            rotation_speed: 0.0,
+        }
+    }
+
+    fn schedule() -> DifficultySchedule {
+        DifficultySchedule::new(vec![
+            step(0.0, 1..=1, 4.0),
+            step(10.0, 1..=2, 6.0),
+            step(20.0, 2..=3, 10.0),
+        ])
+    }
+
+    #[test]
+    fn step_starts_exactly_at_its_start_time() {
+        let schedule = schedule();
+        let difficulty = schedule.at(10.0);
+        assert_eq!(difficulty.step, 1);
+        assert_eq!(difficulty.pattern_levels, 1..=2);
+        assert_eq!(difficulty.obstacle_speed, 6.0);
+        assert_eq!(schedule.at(9.99).step, 0);
+        assert_eq!(schedule.at(9.99).pattern_levels, 1..=1);
+        assert_eq!(schedule.at(20.0).step, 2);
+    }
+
+    #[test]
+    fn speeds_ramp_towards_the_next_step() {
+        let difficulty = schedule().at(15.0);
+        assert_eq!(difficulty.step, 1);
+        assert!((difficulty.obstacle_speed - 8.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn before_the_start_uses_the_first_step() {
+        let difficulty = schedule().at(-1.0);
+        assert_eq!(difficulty.step, 0);
+        assert_eq!(difficulty.pattern_levels, 1..=1);
+        // the ramp doesn't run backwards before the step starts
+        assert_eq!(difficulty.obstacle_speed, 4.0);
+    }
+
+    #[test]
+    fn after_the_last_step_holds_the_last_step() {
+        let schedule = schedule();
+        for time in [20.0, 30.0, 1000.0] {
+            let difficulty = schedule.at(time);
+            assert_eq!(difficulty.step, 2);
+            assert_eq!(difficulty.pattern_levels, 2..=3);
+            assert_eq!(difficulty.obstacle_speed, 10.0);
+        }
+    }
+
+    #[test]
+    fn default_schedule_is_valid() {
+        let difficulty = DifficultySchedule::default_schedule().at(0.0);
+        assert_eq!(difficulty.step, 0);
+        assert_eq!(difficulty.obstacle_speed, Game::OBSTACLE_SPEED);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one step")]
+    fn new_rejects_an_empty_schedule() {
+        DifficultySchedule::new(vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "must start at 0")]
+    fn new_rejects_a_first_step_that_starts_later() {
+        DifficultySchedule::new(vec![step(5.0, 1..=1, 4.0), step(10.0, 1..=2, 6.0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted by start time")]
+    fn new_rejects_unsorted_steps() {
+        DifficultySchedule::new(vec![step(0.0, 1..=1, 4.0), step(10.0, 1..=1, 4.0), step(5.0, 1..=1, 4.0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted by start time")]
+    fn new_rejects_steps_with_the_same_start_time() {
+        DifficultySchedule::new(vec![step(0.0, 1..=1, 4.0), step(0.0, 1..=2, 4.0)]);
+    }
+}
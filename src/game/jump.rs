@@ -0,0 +1,89 @@
+/// Vertical movement of the player: Up jumps over low walls, Down ducks
+/// under high ones on the ground and falls faster in the air. A jump always
+/// follows the same arc, so the airtime is limited to `AIRTIME`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayerJump {
+    /// Height above the ground.
+    pub height: f32,
+    /// Upwards speed.
+    pub velocity: f32,
+    /// Down is held on the ground.
+    pub ducking: bool,
+}
+
+impl PlayerJump {
+    pub const JUMP_SPEED: f32 = 5.0;
+    pub const GRAVITY: f32 = 16.0;
+    /// Gravity while Down is held in the air.
+    pub const FAST_FALL_GRAVITY: f32 = 64.0;
+    /// Seconds from a jump until landing without a fast fall.
+    pub const AIRTIME: f32 = 2.0 * Self::JUMP_SPEED / Self::GRAVITY;
+    /// Height from which the player clears low walls.
+    pub const CLEAR_HEIGHT: f32 = 0.35;
+    /// How far the player sinks while ducking, only for drawing.
+    pub const DUCK_DEPTH: f32 = 0.3;
+
+    pub fn airborne(&self) -> bool {
+        self.height > 0.0 || self.velocity > 0.0
+    }
+
+    /// Advances by `dt` seconds. `jump` starts a jump from the ground, `down`
+    /// is whether Down is held.
+    pub fn update(&mut self, dt: f32, jump: bool, down: bool) {
+        if !self.airborne() && jump {
+            self.velocity = Self::JUMP_SPEED;
+        }
+        self.ducking = !self.airborne() && down;
+        if !self.airborne() {
+            return;
+        }
+        let gravity = if down { Self::FAST_FALL_GRAVITY } else { Self::GRAVITY };
+        // exact for a constant gravity, so long steps land the same
+        self.height += self.velocity * dt - gravity * dt * dt / 2.0;
+        self.velocity -= gravity * dt;
+        if self.height <= 0.0 {
+            self.height = 0.0;
+            self.velocity = 0.0;
+        }
+    }
+
+    /// Height the player is drawn at, relative to `Game::DISPLAY_HEIGHT`.
+    pub fn display_offset(&self) -> f32 {
+        if self.ducking { -Self::DUCK_DEPTH } else { self.height }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jump_lands_after_the_airtime() {
+        let dt = 1.0 / 60.0;
+        let mut jump = PlayerJump::default();
+        jump.update(dt, true, false);
+        let mut airtime = dt;
+        let mut peak: f32 = 0.0;
+        while jump.airborne() {
+            // holding Up doesn't jump again or longer
+            jump.update(dt, true, false);
+            peak = peak.max(jump.height);
+            airtime += dt;
+        }
+        assert!((airtime - PlayerJump::AIRTIME).abs() <= dt, "{}", airtime);
+        assert!(peak > PlayerJump::CLEAR_HEIGHT);
+        assert!(!jump.ducking);
+
+        // Down falls faster in the air and ducks on the ground
+        jump.update(dt, true, false);
+        let mut fast_airtime = dt;
+        while jump.airborne() {
+            jump.update(dt, false, true);
+            fast_airtime += dt;
+        }
+        assert!(fast_airtime < airtime / 2.0);
+        jump.update(dt, false, true);
+        assert!(jump.ducking && !jump.airborne());
+        assert_eq!(jump.display_offset(), -PlayerJump::DUCK_DEPTH);
+    }
+}
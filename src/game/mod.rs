@@ -0,0 +1,1515 @@
+
+
+
+
+use crate::common::camera::Camera;
+use crate::common::rng::Rng;
+use crate::input::{InputGetInterface, InputID, KeyState, MAX_PLAYERS};
+
+mod ascii_view;
+mod autoplay;
+mod beat_map;
+mod collision;
+mod difficulty;
+mod jump;
+mod obstacle;
+mod pattern;
+mod pattern_analysis;
+mod pattern_file;
+mod player;
+mod power_up;
+mod rotation;
+mod shape;
+mod spawner;
+mod stage;
+mod timeline;
+mod timeline_file;
+
+use collision::Arc;
+use difficulty::{Difficulty, DifficultySchedule};
+use pattern::SimplePatternRepo;
+use rotation::ArenaRotation;
+use spawner::{supported_lane_counts, PatternTransforms, RandomSpawner, SpawnerInterface};
+use timeline::{Timeline, TimelineEvent};
+
+pub use ascii_view::AsciiView;
+pub use autoplay::Autoplay;
+pub use beat_map::{load_beat_map, BeatMap, BeatMapLoadError, BEAT_MAP_EXTENSION};
+pub use jump::PlayerJump;
+pub use obstacle::{Obstacle, ObstacleHeight, ObstacleMotion};
+pub use pattern::Pattern;
+pub use pattern_analysis::{analyze_pattern, AnalysisParams, Dodge, PatternAnalysis};
+pub use pattern_file::{load_patterns_dir, parse_patterns, pattern_files, PATTERN_DIR};
+pub use player::Player;
+pub use power_up::{ActivePowerUps, PowerUp, PowerUpKind};
+pub use shape::LaneShape;
+pub use stage::Stage;
+pub use timeline_file::TimelineParseError;
+
+#[derive(Debug, Clone)]
+struct Lane {
+    obstacles: Vec<Obstacle>,
+    power_ups: Vec<PowerUp>,
+}
+
+/// Change of the lane count by one lane, animated over `Game::LANE_MORPH_DURATION`.
+#[derive(Debug, Clone, Copy)]
+struct LaneMorph {
+    from: usize,
+    to: usize,
+    progress: f32,
+}
+
+/// Camera zoom started by a timeline event, see `Game::camera_scale`.
+#[derive(Debug, Clone, Copy)]
+struct CameraPulse {
+    scale: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Fade between two color palettes, see `Game::palette`.
+#[derive(Debug, Clone, Copy)]
+struct PaletteFade {
+    from: u32,
+    to: u32,
+    progress: f32,
+}
+
+/// `Title` --Confirm--> `Playing` <--Cancel--> `Paused`
+/// `Playing` --collision--> `GameOver` --Confirm--> `Playing`, --Cancel--> `Title`
+/// A versus run is over once at most one player is left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameState {
+    Title,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+/// Something that happened during the last `Game::update`, for sound and
+/// effects outside of the game, see `Game::events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEvent {
+    /// The player hit an obstacle, the run is over.
+    Collision,
+    /// The spawner started a new pattern.
+    PatternStarted,
+    /// An obstacle passed the player within `Game::NEAR_MISS_MARGIN`, see `Game::bonus`.
+    NearMiss,
+    /// A menu key changed the game state.
+    MenuSelect,
+    /// A player picked up a power-up, see `Player::power_ups`.
+    PowerUpCollected(PowerUpKind),
+    /// The shield absorbed a collision and is gone.
+    ShieldBroken,
+}
+
+/// Playback position of the music, measured in samples played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicClock {
+    /// Seconds since the track started, counting loops.
+    pub elapsed: f64,
+    /// Seconds into the track.
+    pub position: f32,
+}
+
+/// Outcome of a finished run, see `Game::take_finished_run`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunResult {
+    /// Id of the stage, the high-score table the run goes into.
+    pub mode: &'static str,
+    /// Seconds survived.
+    pub time: f32,
+    pub seed: u64,
+    /// Last pattern spawned before the collision.
+    pub pattern: String,
+}
+
+/// Whether the camera keeps moving, it stands still while the game is paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnimationState {
+    Running,
+    Paused,
+}
+
+
+pub struct Game {
+    pub camera: Camera,
+    /// One player, or more in a versus run, see `set_player_count`.
+    pub players: Vec<Player>,
+    player_speed: f32,
+    obstacle_speed: f32,
+    lanes: Vec<Lane>,
+    shape: LaneShape,
+    /// Lane count requested by the difficulty schedule, reached one lane at a time.
+    target_lane_count: usize,
+    lane_morph: Option<LaneMorph>,
+    /// Lane counts there are patterns for, the lanes never morph to other counts.
+    supported_lane_counts: Vec<usize>,
+    /// Everything but the camera turns with the arena, `Player::angle` and the
+    /// lanes are relative to it.
+    rotation: ArenaRotation,
+    /// Level events on the beat grid, restarted with every run.
+    timeline: Timeline,
+    /// Direction the camera orbits in, turned around by the timeline.
+    camera_spin: f32,
+    camera_pulse: Option<CameraPulse>,
+    palette: PaletteFade,
+    /// Obstacle speed multiplier set by the timeline.
+    speed_factor: f32,
+    /// While music plays it is the clock of the timeline, see `Game::sync_music`.
+    music_clock: Option<MusicClock>,
+    /// `MusicClock::elapsed` the timeline was last advanced to.
+    timeline_music_elapsed: f64,
+    beat_map: Option<BeatMap>,
+    /// Music that should start, see `Game::take_music_change`.
+    music_change: Option<&'static str>,
+    pub time: f32,
+    /// Time spent playing in the current run, this is the score. Slow time
+    /// holds it back like the walls.
+    pub survival_time: f32,
+    /// Points from near misses in the current run, on top of the time.
+    bonus: f32,
+    /// Multiplier on the points of the next near miss, decays back to 1.
+    combo: f32,
+    /// Seconds since the last near miss, for effects.
+    near_miss_age: f32,
+    /// Last player standing of the last versus run, `None` after a draw.
+    winner: Option<usize>,
+    /// Spawner seed of the current run.
+    seed: u64,
+    /// Seed of the next run, derived from the previous one so restarts stay deterministic.
+    next_seed: u64,
+    finished_run: Option<RunResult>,
+    /// Events of the current update, cleared at the start of the next one.
+    events: Vec<GameEvent>,
+    state: GameState,
+    animation_state: AnimationState,
+    spawner: Box<dyn SpawnerInterface>,
+    difficulty_schedule: DifficultySchedule,
+    difficulty: Difficulty,
+    camera_angle_x: f32,
+    camera_angle_y: f32,
+    stage: Stage,
+}
+
+impl Game {
+    pub const DISPLAY_HEIGHT: f32 = 1.0;
+    /// Radius of the innermost ring, the only one unless the stage has more.
+    pub const PLAYER_RADIUS: f32 = 3.0;
+    /// Distance between neighboring rings.
+    pub const RING_SPACING: f32 = 1.5;
+    pub const MAX_RINGS: usize = 3;
+    /// Seconds a hop from one ring to the next takes.
+    pub const RING_HOP_DURATION: f32 = 0.15;
+    /// Angular width of the player, in radians.
+    pub const PLAYER_WIDTH: f32 = 0.3;
+    pub const OBSTACLE_SPEED: f32 = 4.0;
+    pub const OBSTACLE_SPAWN_DISTANCE: f32 = 20.0;
+    pub const DEFAULT_LANE_COUNT: usize = 6;
+    pub const MIN_LANE_COUNT: usize = 4;
+    pub const MAX_LANE_COUNT: usize = 8;
+    /// Seconds it takes to add or remove one lane.
+    pub const LANE_MORPH_DURATION: f32 = 0.75;
+    /// Number of color palettes in main_scene.wgsl.
+    pub const PALETTE_COUNT: u32 = 4;
+    /// Seconds a palette swap takes.
+    pub const PALETTE_FADE_DURATION: f32 = 0.5;
+    /// Largest angle between an obstacle and the player's side, while it
+    /// crosses the player circle, that counts as a near miss.
+    pub const NEAR_MISS_MARGIN: f32 = 0.06;
+    /// Points for a near miss, times the combo multiplier.
+    pub const NEAR_MISS_POINTS: f32 = 10.0;
+    /// The combo multiplier grows by this much with each near miss, up to `MAX_COMBO`.
+    pub const COMBO_STEP: f32 = 0.5;
+    pub const MAX_COMBO: f32 = 4.0;
+    /// Combo multiplier lost per second.
+    pub const COMBO_DECAY: f32 = 0.5;
+    /// Seconds the near-miss glow of the player fades over.
+    pub const NEAR_MISS_GLOW_DURATION: f32 = 0.4;
+
+    pub fn new(aspect: f32, seed: u64, stage: Stage) -> Self {
+        let camera = Camera {
+            // position the camera 1 unit up and 2 units back
+            // +z is out of the screen
+            eye: (0.0, 1.0, 2.0).into(),
+            // have it look at the origin
+            target: (0.0, 0.0, 0.0).into(),
+            // which way is "up"
+            up: cgmath::Vector3::unit_y(),
+            aspect,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        };
+
+        let patterns = Self::stage_patterns(&stage, Self::load_patterns());
+        let supported_lane_counts = supported_lane_counts(&patterns);
+        let timeline = Self::load_timeline(&patterns);
+
+        let spawner = RandomSpawner::new(patterns, seed, PatternTransforms::ALL);
+        let difficulty_schedule = DifficultySchedule::default_schedule();
+        let difficulty = difficulty_schedule.at(0.0);
+
+        Self {
+            camera,
+            players: Self::new_players(1),
+            camera_angle_x: 0.0,
+            camera_angle_y: 0.0,
+            lanes: Self::empty_lanes(Self::DEFAULT_LANE_COUNT),
+            shape: LaneShape::regular(Self::DEFAULT_LANE_COUNT),
+            target_lane_count: Self::DEFAULT_LANE_COUNT,
+            lane_morph: None,
+            supported_lane_counts,
+            rotation: ArenaRotation::new(seed, stage.rotation),
+            timeline,
+            camera_spin: 1.0,
+            camera_pulse: None,
+            palette: PaletteFade { from: stage.palette, to: stage.palette, progress: 1.0 },
+            speed_factor: 1.0,
+            music_clock: None,
+            timeline_music_elapsed: 0.0,
+            beat_map: None,
+            music_change: None,
+            time: 0.0,
+            survival_time: 0.0,
+            bonus: 0.0,
+            combo: 1.0,
+            near_miss_age: f32::INFINITY,
+            winner: None,
+            seed,
+            next_seed: seed,
+            finished_run: None,
+            events: vec![],
+            player_speed: difficulty.player_speed,
+            obstacle_speed: difficulty.obstacle_speed,
+            state: GameState::Title,
+            animation_state: AnimationState::Running,
+            spawner: Box::new(spawner) as Box<dyn SpawnerInterface>,
+            difficulty_schedule,
+            difficulty,
+            stage,
+        }
+    }
+
+    /// `count` players spread evenly around the arena, the first one at angle 0.
+    fn new_players(count: usize) -> Vec<Player> {
+        (0..count).map(|i| Player::new(i as f32 * std::f32::consts::TAU / count as f32)).collect()
+    }
+
+    fn empty_lanes(count: usize) -> Vec<Lane> {
+        (0..count).map(|_| Lane { obstacles: vec![], power_ups: vec![] }).collect()
+    }
+
+    /// Loads the patterns from the assets directory, falling back to the built-in set
+    /// if the files are missing or broken.
+    fn load_patterns() -> Vec<Pattern> {
+        let loaded = if cfg!(target_arch = "wasm32") {
+            pattern_file::parse_patterns(include_str!("../../assets/patterns/basic.pattern"), None)
+                .map_err(pattern_file::PatternLoadError::Parse)
+        } else {
+            pattern_file::load_patterns_dir(std::path::Path::new(pattern_file::PATTERN_DIR))
+        };
+        match loaded {
+            Ok(patterns) if !patterns.is_empty() => patterns,
+            Ok(_) => {
+                eprintln!("No patterns found in {}, using built-in patterns", pattern_file::PATTERN_DIR);
+                SimplePatternRepo::create_patterns()
+            }
+            Err(err) => {
+                eprintln!("Failed to load patterns: {}", err);
+                eprintln!("Using built-in patterns");
+                SimplePatternRepo::create_patterns()
+            }
+        }
+    }
+
+    /// The stage's pattern pool, or all patterns if the stage names patterns
+    /// that don't exist.
+    fn stage_patterns(stage: &Stage, patterns: Vec<Pattern>) -> Vec<Pattern> {
+        if stage.patterns.is_empty() {
+            return patterns;
+        }
+        let missing: Vec<&str> = stage.patterns
+            .iter()
+            .copied()
+            .filter(|name| !patterns.iter().any(|pattern| pattern.name == *name))
+            .collect();
+        if !missing.is_empty() {
+            eprintln!("Stage {} uses unknown patterns: {}", stage.id, missing.join(", "));
+            eprintln!("Using all patterns");
+            return patterns;
+        }
+        patterns.into_iter().filter(|pattern| stage.patterns.contains(&pattern.name.as_str())).collect()
+    }
+
+    /// Loads the level timeline, falling back to the built-in one if the file
+    /// is missing, broken or spawns patterns that don't exist.
+    fn load_timeline(patterns: &[Pattern]) -> Timeline {
+        let loaded = if cfg!(target_arch = "wasm32") {
+            timeline_file::parse_timeline(include_str!("../../assets/timelines/default.timeline"), None)
+                .map_err(timeline_file::TimelineLoadError::Parse)
+        } else {
+            timeline_file::load_timeline(std::path::Path::new(timeline_file::TIMELINE_PATH))
+        };
+        let timeline = match loaded {
+            Ok(timeline) => timeline,
+            Err(err) => {
+                eprintln!("Failed to load timeline: {}", err);
+                eprintln!("Using built-in timeline");
+                return Timeline::default_timeline();
+            }
+        };
+        for timed in timeline.events() {
+            if let TimelineEvent::SpawnPattern(name) = &timed.event {
+                if !patterns.iter().any(|pattern| &pattern.name == name) {
+                    eprintln!("Timeline spawns unknown pattern `{}` at beat {}", name, timed.beat);
+                    eprintln!("Using built-in timeline");
+                    return Timeline::default_timeline();
+                }
+            }
+        }
+        timeline
+    }
+
+    pub fn update(&mut self, dt: f32, input: &dyn InputGetInterface) {
+        self.events.clear();
+        self.update_state(input);
+
+        if self.animation_state == AnimationState::Running {
+            self.time += dt;
+            // without music, slow time holds back the timeline too, or its
+            // patterns would bunch up behind the slowed walls; music doesn't
+            // slow down, and its beats stay on it
+            let timeline_dt = match self.music_step() {
+                Some(step) => step,
+                None if self.state == GameState::Playing => dt * self.world_speed(),
+                None => dt,
+            };
+            self.update_timeline(timeline_dt);
+            self.animate_camera(dt);
+            self.rotation.update(dt, self.difficulty.rotation_speed);
+        }
+        self.update_camera();
+
+        if self.state != GameState::Playing {
+            return;
+        }
+
+        self.near_miss_age += dt;
+        self.combo = (self.combo - Self::COMBO_DECAY * dt).max(1.0);
+        self.update_power_ups(dt);
+        // slow time holds back everything coming towards the players, and the
+        // survival time with the difficulty that follows it, so the same
+        // walls are worth the same time
+        let world_dt = dt * self.world_speed();
+        self.survival_time += world_dt;
+        self.update_difficulty();
+        for index in 0..self.players.len() {
+            self.update_player(index, dt, input);
+        }
+        self.update_obstacles(world_dt);
+        self.collect_power_ups(world_dt);
+        self.player_check_collisions();
+        self.update_lane_morph(dt);
+        // no new obstacles until the lanes have their new shape
+        if self.lane_morph.is_none()
+            && self.target_lane_count == self.lanes.len()
+            && self.spawner.update(world_dt, &mut self.lanes, &self.difficulty)
+        {
+            self.events.push(GameEvent::PatternStarted);
+        }
+    }
+
+    fn update_state(&mut self, input: &dyn InputGetInterface) {
+        let confirm = input.get_key_state(InputID::Confirm).just_pressed;
+        let cancel = input.get_key_state(InputID::Cancel).just_pressed;
+        let switch_players = input.get_key_state(InputID::Up).just_pressed || input.get_key_state(InputID::Down).just_pressed;
+        let (state, player_count) = (self.state, self.players.len());
+        match self.state {
+            GameState::Title if confirm => self.start(),
+            GameState::Title if switch_players => {
+                self.set_player_count(if player_count == 1 { MAX_PLAYERS } else { 1 });
+            }
+            GameState::Playing if cancel => self.set_state(GameState::Paused),
+            GameState::Paused if cancel => self.set_state(GameState::Playing),
+            GameState::GameOver if confirm => self.start(),
+            GameState::GameOver if cancel => self.set_state(GameState::Title),
+            _ => {}
+        }
+        if self.state != state || self.players.len() != player_count {
+            self.events.push(GameEvent::MenuSelect);
+        }
+    }
+
+    fn set_state(&mut self, state: GameState) {
+        self.state = state;
+        self.animation_state = match state {
+            GameState::Paused => AnimationState::Paused,
+            _ => AnimationState::Running,
+        };
+    }
+
+    /// Starts a new run: clears the lanes and resets the spawner, the
+    /// survival time and the difficulty.
+    pub fn start(&mut self) {
+        self.seed = self.next_seed;
+        self.next_seed = Rng::new(self.seed).next_u64();
+        self.spawner.reset(self.seed);
+        self.rotation = ArenaRotation::new(self.seed, self.stage.rotation);
+        self.timeline.reset();
+        self.camera_spin = 1.0;
+        self.camera_pulse = None;
+        self.palette = PaletteFade { from: self.stage.palette, to: self.stage.palette, progress: 1.0 };
+        self.speed_factor = 1.0;
+        self.music_clock = None;
+        self.timeline_music_elapsed = 0.0;
+        self.music_change = Some(self.stage.music);
+        self.players = Self::new_players(self.players.len());
+        self.winner = None;
+        self.survival_time = 0.0;
+        self.bonus = 0.0;
+        self.combo = 1.0;
+        self.near_miss_age = f32::INFINITY;
+        self.difficulty = self.difficulty_at(0.0);
+        let lane_count = match self.difficulty.lane_count {
+            count if self.supported_lane_counts.contains(&count) => count,
+            _ => Self::DEFAULT_LANE_COUNT,
+        };
+        self.lanes = Self::empty_lanes(lane_count);
+        self.shape = LaneShape::regular(lane_count);
+        self.target_lane_count = lane_count;
+        self.lane_morph = None;
+        self.player_speed = self.difficulty.player_speed;
+        self.obstacle_speed = self.difficulty.obstacle_speed;
+        self.finished_run = None;
+        self.set_state(GameState::Playing);
+    }
+
+    pub fn stage(&self) -> &Stage {
+        &self.stage
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    /// Plays the next runs with `count` players, 2 is a versus run on one
+    /// arena where the last player standing wins. Up and Down switch between
+    /// 1 and 2 on the title screen.
+    pub fn set_player_count(&mut self, count: usize) {
+        assert!((1..=MAX_PLAYERS).contains(&count), "Player count must be between 1 and {}", MAX_PLAYERS);
+        self.players = Self::new_players(count);
+    }
+
+    /// Whether this is a run with more than one player.
+    pub fn is_versus(&self) -> bool {
+        self.players.len() > 1
+    }
+
+    /// Index of the player who won the last versus run, `None` after a draw
+    /// or outside of versus runs.
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    /// Spawner seed of the current (or last) run.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The scheduled difficulty at `time`, sped up by the stage and the timeline.
+    fn difficulty_at(&self, time: f32) -> Difficulty {
+        let mut difficulty = self.difficulty_schedule.at(time);
+        difficulty.obstacle_speed *= self.speed_factor * self.stage.speed;
+        difficulty.player_speed *= self.stage.speed;
+        difficulty
+    }
+
+    fn update_difficulty(&mut self) {
+        let difficulty = self.difficulty_at(self.survival_time);
+        if difficulty.step != self.difficulty.step {
+            // every new step turns the arena around
+            self.rotation.reverse();
+        }
+        self.player_speed = difficulty.player_speed;
+        self.obstacle_speed = difficulty.obstacle_speed;
+        if difficulty.step != self.difficulty.step
+            && self.supported_lane_counts.contains(&difficulty.lane_count)
+        {
+            self.set_lane_count(difficulty.lane_count);
+        }
+        self.difficulty = difficulty;
+    }
+
+    /// Morphs the lanes to `count` lanes, one lane at a time. Spawning stops
+    /// until the new shape is reached.
+    pub fn set_lane_count(&mut self, count: usize) {
+        assert!(
+            (Self::MIN_LANE_COUNT..=Self::MAX_LANE_COUNT).contains(&count),
+            "Lane count must be between {} and {}", Self::MIN_LANE_COUNT, Self::MAX_LANE_COUNT
+        );
+        self.target_lane_count = count;
+    }
+
+    /// Advances a running morph, or starts the next one once the lanes are
+    /// clear. A lane is always added or removed at the end, while it grows
+    /// from or shrinks to zero width the other lanes make room.
+    fn update_lane_morph(&mut self, dt: f32) {
+        if let Some(morph) = &mut self.lane_morph {
+            morph.progress = (morph.progress + dt / Self::LANE_MORPH_DURATION).min(1.0);
+            let t = morph.progress * morph.progress * (3.0 - 2.0 * morph.progress);
+            self.shape.sides = morph.from as f32 + (morph.to as f32 - morph.from as f32) * t;
+            if morph.progress >= 1.0 {
+                let to = morph.to;
+                self.lanes.truncate(to);
+                self.shape = LaneShape::regular(to);
+                self.lane_morph = None;
+            }
+            return;
+        }
+
+        let current = self.lanes.len();
+        // obstacles don't survive a change of the lane layout, wait until they are gone
+        if self.target_lane_count == current
+            || self.lanes.iter().any(|lane| !lane.obstacles.is_empty() || !lane.power_ups.is_empty())
+        {
+            return;
+        }
+        let to = if self.target_lane_count > current { current + 1 } else { current - 1 };
+        if to > current {
+            self.lanes.push(Lane { obstacles: vec![], power_ups: vec![] });
+        }
+        self.shape = LaneShape { lane_count: current.max(to), sides: current as f32 };
+        self.lane_morph = Some(LaneMorph { from: current, to, progress: 0.0 });
+    }
+
+    /// Current lane layout, fractional while the lane count morphs.
+    pub fn lane_shape(&self) -> LaneShape {
+        self.shape
+    }
+
+    /// World angle of the arena, add it to arena angles like `Player::angle`
+    /// to get the angle on screen.
+    pub fn arena_rotation(&self) -> f32 {
+        self.rotation.angle()
+    }
+
+    /// How far the music got since the timeline last moved, `None` without
+    /// music. A restarted track counts from 0.
+    fn music_step(&mut self) -> Option<f32> {
+        let clock = self.music_clock?;
+        let step = match clock.elapsed >= self.timeline_music_elapsed {
+            true => clock.elapsed - self.timeline_music_elapsed,
+            false => clock.elapsed,
+        };
+        self.timeline_music_elapsed = clock.elapsed;
+        Some(step as f32)
+    }
+
+    /// Makes the music the clock of the timeline, so beat events stay on the
+    /// music even when frames are dropped. Call it after every update with
+    /// the position the music got to, `None` while there is no music.
+    /// Replays record it, see `ReplayRecorder::record_state`.
+    pub fn sync_music(&mut self, clock: Option<MusicClock>) {
+        self.music_clock = clock;
+    }
+
+    pub fn music_clock(&self) -> Option<MusicClock> {
+        self.music_clock
+    }
+
+    /// Replaces the level timeline with the one in `source`, in the format
+    /// of src/game/timeline_file.rs. The next run starts it over.
+    pub fn set_timeline_source(&mut self, source: &str) -> Result<(), TimelineParseError> {
+        self.timeline = timeline_file::parse_timeline(source, None)?;
+        Ok(())
+    }
+
+    /// Beats of the music that is playing, set when a track starts.
+    pub fn set_beat_map(&mut self, beat_map: Option<BeatMap>) {
+        self.beat_map = beat_map;
+    }
+
+    pub fn beat_map(&self) -> Option<&BeatMap> {
+        self.beat_map.as_ref()
+    }
+
+    /// Name of the music that should start now, once per run.
+    pub fn take_music_change(&mut self) -> Option<&'static str> {
+        self.music_change.take()
+    }
+
+    /// Position between two beats, 0 on a beat. From the beat map if the
+    /// music has one, from the timeline tempo otherwise.
+    pub fn beat_phase(&self) -> f32 {
+        match (&self.beat_map, self.music_clock) {
+            (Some(beat_map), Some(clock)) => beat_map.beat_phase(clock.position),
+            _ => self.timeline.beat().fract(),
+        }
+    }
+
+    /// Bar of the music that is playing, if it has a beat map.
+    pub fn bar(&self) -> Option<usize> {
+        self.beat_map.as_ref()?.bar_at(self.music_clock?.position)
+    }
+
+    /// Advances the timeline and the effects it started.
+    fn update_timeline(&mut self, dt: f32) {
+        for event in self.timeline.advance(dt) {
+            self.apply_timeline_event(event);
+        }
+        if let Some(pulse) = &mut self.camera_pulse {
+            pulse.elapsed += dt;
+            if pulse.elapsed >= pulse.duration {
+                self.camera_pulse = None;
+            }
+        }
+        self.palette.progress = (self.palette.progress + dt / Self::PALETTE_FADE_DURATION).min(1.0);
+    }
+
+    /// Gameplay events only apply during a run, the others also animate the
+    /// title and game over screens.
+    fn apply_timeline_event(&mut self, event: TimelineEvent) {
+        let playing = self.state == GameState::Playing;
+        match event {
+            // a pattern made for another lane count than the current one is skipped
+            TimelineEvent::SpawnPattern(name) if playing => {
+                if self.spawner.spawn_named(&name, &mut self.lanes) {
+                    self.events.push(GameEvent::PatternStarted);
+                }
+            }
+            TimelineEvent::Speed(factor) if playing => self.speed_factor = factor,
+            TimelineEvent::SpawnPattern(_) | TimelineEvent::Speed(_) => {}
+            TimelineEvent::ReverseRotation => {
+                self.camera_spin = -self.camera_spin;
+                self.rotation.reverse();
+            }
+            TimelineEvent::CameraPulse { scale, beats } => {
+                let duration = beats * self.timeline.seconds_per_beat();
+                self.camera_pulse = Some(CameraPulse { scale, duration, elapsed: 0.0 });
+            }
+            TimelineEvent::Palette(index) => {
+                let (_, current, _) = self.palette();
+                self.palette = PaletteFade { from: current, to: index, progress: 0.0 };
+            }
+        }
+    }
+
+    /// Camera zoom factor, 1 unless a pulse is running. A pulse jumps to its
+    /// scale and eases back.
+    pub fn camera_scale(&self) -> f32 {
+        match self.camera_pulse {
+            Some(pulse) => {
+                let t = 1.0 - (pulse.elapsed / pulse.duration).min(1.0);
+                1.0 + (pulse.scale - 1.0) * t * t
+            }
+            None => 1.0,
+        }
+    }
+
+    /// Palettes to blend in the shader: `(from, to, blend)`.
+    pub fn palette(&self) -> (u32, u32, f32) {
+        let t = self.palette.progress;
+        let blend = t * t * (3.0 - 2.0 * t);
+        if blend >= 1.0 {
+            (self.palette.to, self.palette.to, 1.0)
+        } else {
+            (self.palette.from, self.palette.to, blend)
+        }
+    }
+
+    fn update_camera(&mut self) {
+        let angle = self.camera_angle_x;
+        let angle_y = self.camera_angle_y;
+        // let angle = input.get_mouse_x().to_radians() + self.camera_angle_x;
+        // let angle_y = input.get_mouse_y().to_radians() + 1.3 + self.camera_angle_y;
+        // let angle = 0.0 as f32;
+        // let angle_y = self.time * 0.01;
+        let direction = cgmath::Vector3::new(
+            angle.sin() * angle_y.cos(),
+            -angle_y.sin(),
+            -angle.cos() * angle_y.cos(),
+        );
+        // let direction = cgmath::Vector3::new(0.4, -0.6, 0.8).normalize();
+        let up = cgmath::Vector3::new(0.0, 1.0, 0.0);
+        // Place the camera 20 units behind the direction, closer during a pulse
+        self.camera.target = cgmath::Point3::new(0.0, 0.0, 0.0);
+        self.camera.eye = self.camera.target - 20.0 / self.camera_scale() * direction;
+        self.camera.up = up;
+        self.camera.target = self.camera.eye + direction;
+    }
+
+    fn animate_camera(&mut self, dt: f32) {
+        self.camera_angle_x += dt * self.camera_spin;
+        self.camera_angle_y = (self.time * 0.2).sin() * 0.1 + 1.3;
+    }
+
+
+    pub fn is_game_over(&self) -> bool {
+        self.state == GameState::GameOver
+    }
+
+    /// What happened during the last update.
+    pub fn events(&self) -> &[GameEvent] {
+        &self.events
+    }
+
+    /// Returns the result of the run that just ended, once.
+    pub fn take_finished_run(&mut self) -> Option<RunResult> {
+        self.finished_run.take()
+    }
+
+    pub fn ring_radius(ring: usize) -> f32 {
+        Self::PLAYER_RADIUS + ring as f32 * Self::RING_SPACING
+    }
+
+    /// World position of player `index`, including the arena rotation.
+    pub fn player_position(&self, index: usize) -> cgmath::Vector3<f32> {
+        let player = &self.players[index];
+        let radius = player.radius();
+        let angle = player.angle + self.arena_rotation();
+        let x = angle.cos() * radius;
+        let y = Self::DISPLAY_HEIGHT + player.jump.display_offset();
+        let z = angle.sin() * radius;
+        cgmath::Vector3::new(x, y, z)
+    }
+
+    fn update_obstacles(&mut self, dt: f32) {
+        let mut near_misses = 0;
+        for lane in &mut self.lanes {
+            for obstacle in &mut lane.obstacles {
+                obstacle.update(dt, self.obstacle_speed);
+                if Self::track_near_miss(obstacle, &self.players, &self.shape) {
+                    near_misses += 1;
+                }
+            }
+            // with their own speeds obstacles can overtake each other, so check all of them
+            lane.obstacles.retain(|obstacle| obstacle.current_end() >= 1.0);
+        }
+        for _ in 0..near_misses {
+            self.near_miss();
+        }
+    }
+
+    /// Follows `obstacle` while it crosses the player circles, keeping the
+    /// closest it gets to a player. Returns true once it has crossed them
+    /// all, if that was within `NEAR_MISS_MARGIN`, which is then in a lane
+    /// next to a player. Each obstacle crosses once. Walls a player jumps over
+    /// or ducks under don't get closer to them meanwhile.
+    fn track_near_miss(obstacle: &mut Obstacle, players: &[Player], shape: &LaneShape) -> bool {
+        if obstacle.passed {
+            return false;
+        }
+        let mut crossing = false;
+        for player in players.iter().filter(|player| player.alive) {
+            let arcs = collision::obstacle_arcs(obstacle, shape, player.radius());
+            if arcs.iter().all(Option::is_none) {
+                continue;
+            }
+            crossing = true;
+            if !obstacle.height.blocks(&player.jump) {
+                continue;
+            }
+            for arc in arcs.into_iter().flatten() {
+                obstacle.closest_gap = obstacle.closest_gap.min(collision::arc_gap(player.arc(), arc));
+            }
+        }
+        if crossing {
+            return false;
+        }
+        // gone past the innermost circle, not still on the way to the circles
+        let innermost = players
+            .iter()
+            .filter(|player| player.alive)
+            .map(Player::radius)
+            .fold(f32::INFINITY, f32::min);
+        obstacle.passed = obstacle.current_end() < innermost;
+        obstacle.passed && obstacle.closest_gap <= Self::NEAR_MISS_MARGIN
+    }
+
+    fn near_miss(&mut self) {
+        self.bonus += Self::NEAR_MISS_POINTS * self.combo;
+        self.combo = (self.combo + Self::COMBO_STEP).min(Self::MAX_COMBO);
+        self.near_miss_age = 0.0;
+        self.events.push(GameEvent::NearMiss);
+    }
+
+    /// Points from near misses in the current (or last) run.
+    pub fn bonus(&self) -> f32 {
+        self.bonus
+    }
+
+    /// Multiplier on the points of the next near miss.
+    pub fn combo(&self) -> f32 {
+        self.combo
+    }
+
+    /// Index of the difficulty step the run is in, see `DifficultySchedule`.
+    pub fn difficulty_step(&self) -> usize {
+        self.difficulty.step
+    }
+
+    /// 1 right after a near miss, fading to 0 over `NEAR_MISS_GLOW_DURATION`.
+    pub fn near_miss_glow(&self) -> f32 {
+        (1.0 - self.near_miss_age / Self::NEAR_MISS_GLOW_DURATION).max(0.0)
+    }
+
+    fn update_power_ups(&mut self, dt: f32) {
+        for player in &mut self.players {
+            player.power_ups.update(dt);
+            player.width = player.power_ups.player_width();
+        }
+    }
+
+    /// Multiplier on the time the walls, power-ups and the spawner move by,
+    /// slow time of any player holds them back for everyone.
+    pub(crate) fn world_speed(&self) -> f32 {
+        self.players
+            .iter()
+            .filter(|player| player.alive)
+            .map(|player| player.power_ups.world_speed())
+            .fold(1.0, f32::min)
+    }
+
+    /// Moves the power-ups in the lanes. A power-up is collected by the first
+    /// player whose circle it reaches in their lane, and lost once it reaches
+    /// the innermost circle.
+    fn collect_power_ups(&mut self, dt: f32) {
+        let (players, shape, obstacle_speed) = (&self.players, self.shape, self.obstacle_speed);
+        let innermost = players
+            .iter()
+            .filter(|player| player.alive)
+            .map(Player::radius)
+            .fold(f32::INFINITY, f32::min);
+        let mut collected = vec![];
+        for (lane_index, lane) in self.lanes.iter_mut().enumerate() {
+            lane.power_ups.retain_mut(|power_up| {
+                power_up.update(dt, obstacle_speed);
+                let collector = players.iter().position(|player| {
+                    player.alive && power_up.distance <= player.radius() && shape.lane_at_angle(player.angle) == lane_index
+                });
+                match collector {
+                    Some(index) => {
+                        collected.push((index, power_up.kind));
+                        false
+                    }
+                    None => power_up.distance > innermost,
+                }
+            });
+        }
+        for (index, kind) in collected {
+            let player = &mut self.players[index];
+            player.power_ups.collect(kind);
+            player.width = player.power_ups.player_width();
+            self.events.push(GameEvent::PowerUpCollected(kind));
+        }
+    }
+
+    /// Power-ups on their way to the player.
+    pub fn get_power_ups_all(&self) -> Vec<PowerUp> {
+        let mut power_ups: Vec<PowerUp> = self.lanes.iter().flat_map(|lane| lane.power_ups.clone()).collect();
+        power_ups.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        power_ups
+    }
+
+    /// State of `key` for player `index`: any player's keys if there is only
+    /// one, the player's own keys in a versus run.
+    fn key_state(&self, input: &dyn InputGetInterface, index: usize, key: InputID) -> KeyState {
+        match self.is_versus() {
+            true => *input.get_player_key_state(index, key),
+            false => *input.get_key_state(key),
+        }
+    }
+
+    fn update_player(&mut self, index: usize, dt: f32, input: &dyn InputGetInterface) {
+        if !self.players[index].alive {
+            return;
+        }
+        let up = self.key_state(input, index, InputID::Up);
+        let down = self.key_state(input, index, InputID::Down);
+        let rings = self.stage.rings;
+        let player = &mut self.players[index];
+        // with more than one ring Up and Down are for hopping between them
+        if rings > 1 {
+            player.update_ring(dt, up.just_pressed, down.just_pressed, rings);
+        } else {
+            player.jump.update(dt, up.just_pressed, down.pressed);
+        }
+
+        let left_pressed = self.key_state(input, index, InputID::Left).pressed;
+        let right_pressed = self.key_state(input, index, InputID::Right).pressed;
+        let screen_sense = self.screen_sense();
+        let movement = match (left_pressed, right_pressed) {
+            (true, false) => -self.player_speed * screen_sense * dt,
+            (false, true) => self.player_speed * screen_sense * dt,
+            _ => 0.0,
+        };
+        if movement != 0.0 {
+            // stop at the side of the first obstacle in the way, however far the player would move
+            let blocked = self.blocked_arcs(&self.players[index]);
+            let player = &mut self.players[index];
+            player.angle += collision::sweep(player.arc(), movement, &blocked);
+        }
+        let player = &mut self.players[index];
+        player.angle = player.angle.rem_euclid(std::f32::consts::TAU);
+    }
+
+    /// Sign of the player movement for Right, Left moves the other way.
+    fn screen_sense(&self) -> f32 {
+        // Left is counter-clockwise on screen whichever way the arena turns.
+        // Angles grow clockwise seen from above, so the sense flips if the
+        // camera ever looks up at the arena.
+        if self.camera.direction().y <= 0.0 { 1.0 } else { -1.0 }
+    }
+
+    /// Every player checks for collisions on their own. A single player's run
+    /// is over with the first wall they hit, a versus run once at most one
+    /// player is left.
+    fn player_check_collisions(&mut self) {
+        let mut out = vec![];
+        for index in 0..self.players.len() {
+            let player = self.players[index];
+            if !player.alive {
+                continue;
+            }
+            let collided = self.blocked_arcs(&player).into_iter().any(|arc| collision::arcs_overlap(player.arc(), arc));
+            if collided && player.power_ups.shield {
+                // the shield takes the hit and the walls it hit are gone
+                self.players[index].power_ups.shield = false;
+                let (shape, radius) = (self.shape, player.radius());
+                for lane in &mut self.lanes {
+                    lane.obstacles.retain(|obstacle| {
+                        if !obstacle.height.blocks(&player.jump) {
+                            return true;
+                        }
+                        let arcs = collision::obstacle_arcs(obstacle, &shape, radius);
+                        !arcs.into_iter().flatten().any(|arc| collision::arcs_overlap(player.arc(), arc))
+                    });
+                }
+                self.events.push(GameEvent::ShieldBroken);
+            } else if collided {
+                out.push(index);
+            }
+        }
+        // players hitting walls in the same update are out together
+        for &index in &out {
+            self.players[index].alive = false;
+        }
+        let alive: Vec<usize> = (0..self.players.len()).filter(|&index| self.players[index].alive).collect();
+        // in a versus run the others play on until one player is left
+        if out.is_empty() || (self.is_versus() && alive.len() > 1) {
+            return;
+        }
+        self.set_state(GameState::GameOver);
+        self.events.push(GameEvent::Collision);
+        if self.is_versus() {
+            // versus runs don't go into the high scores
+            self.winner = alive.first().copied();
+            return;
+        }
+        self.finished_run = Some(RunResult {
+            mode: self.stage.id,
+            time: self.survival_time,
+            seed: self.seed,
+            pattern: self.spawner.pattern_name().unwrap_or("-").to_string(),
+        });
+    }
+
+    /// Parts of the circle of `player` covered by obstacles right now,
+    /// without the ones they are jumping over or ducking under.
+    fn blocked_arcs(&self, player: &Player) -> Vec<Arc> {
+        self.lanes
+            .iter()
+            .flat_map(|lane| &lane.obstacles)
+            .filter(|obstacle| obstacle.height.blocks(&player.jump))
+            .flat_map(|obstacle| collision::obstacle_arcs(obstacle, &self.shape, player.radius()))
+            .flatten()
+            .collect()
+    }
+
+    /// Hash of the gameplay state, used by replays to detect desyncs.
+    pub fn state_checksum(&self) -> u32 {
+        // FNV-1a over the raw bits, so that any difference shows up
+        let mut hash: u32 = 0x811c_9dc5;
+        let mut add = |value: u32| {
+            for byte in value.to_le_bytes() {
+                hash ^= byte as u32;
+                hash = hash.wrapping_mul(0x0100_0193);
+            }
+        };
+        add(self.state as u32);
+        for player in &self.players {
+            add(player.angle.to_bits());
+            add(player.jump.height.to_bits());
+            add(player.jump.velocity.to_bits());
+            add(player.jump.ducking as u32);
+            add(player.radius().to_bits());
+            add(player.alive as u32);
+            add(player.power_ups.shield as u32);
+            add(player.power_ups.slow_time.to_bits());
+            add(player.power_ups.wide_gap.to_bits());
+        }
+        add(self.survival_time.to_bits());
+        add(self.bonus.to_bits());
+        add(self.shape.sides.to_bits());
+        for lane in &self.lanes {
+            add(lane.obstacles.len() as u32);
+            for obstacle in &lane.obstacles {
+                add(obstacle.start.to_bits());
+                add(obstacle.end.to_bits());
+                add(obstacle.age.to_bits());
+            }
+            add(lane.power_ups.len() as u32);
+            for power_up in &lane.power_ups {
+                add(power_up.distance.to_bits());
+            }
+        }
+        hash
+    }
+
+    pub fn get_obstacles_all(&self) -> Vec<Obstacle> {
+        let mut obstacles: Vec<Obstacle> = self.lanes.iter().flat_map(|lane| lane.obstacles.clone()).collect();
+        obstacles.sort_by(|a, b| a.current_start().total_cmp(&b.current_start()));
+        obstacles
+    }
+
+    
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{PI, TAU};
+
+    use cgmath::InnerSpace;
+
+    use super::*;
+    use crate::input::VirtualInput;
+
+    #[test]
+    fn lanes_morph_one_at_a_time() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        let input = VirtualInput::new();
+        game.start();
+        game.set_lane_count(4);
+        let dt = 1.0 / 60.0;
+
+        let mut saw_partial = false;
+        let mut time = 0.0;
+        while game.lanes.len() != 4 || game.lane_morph.is_some() {
+            game.update(dt, &input);
+            let shape = game.lane_shape();
+            assert!(shape.sides >= 4.0 && shape.sides <= 6.0);
+            assert!(shape.sides >= shape.lane_count as f32 - 1.0);
+            saw_partial |= shape.sides.fract() != 0.0;
+            time += dt;
+            assert!(time < 3.0 * Game::LANE_MORPH_DURATION, "morph didn't finish");
+        }
+        assert!(saw_partial);
+        assert!(time > 2.0 * Game::LANE_MORPH_DURATION - 2.0 * dt);
+        assert_eq!(game.lane_shape(), LaneShape::regular(4));
+
+        // spawning resumes with patterns for four lanes
+        game.update(dt, &input);
+        let obstacles = game.get_obstacles_all();
+        assert!(!obstacles.is_empty());
+        assert!(obstacles.iter().all(|o| o.lane < 4));
+    }
+
+    #[test]
+    fn player_stops_at_obstacle_side_for_any_dt() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        game.start();
+        // lane 1 covers 120 to 180 degrees, the wall fills it at the player's radius
+        game.lanes[1].obstacles.push(Obstacle::new(2.0, 4.0, 1));
+        let mut input = VirtualInput::new();
+        input.set_pressed(&[InputID::Right]);
+        let side = 120f32.to_radians() - game.players[0].width / 2.0;
+        for dt in [1.0 / 60.0, 0.1, 0.5, 3.0] {
+            game.players[0].angle = 90f32.to_radians();
+            for _ in 0..200 {
+                game.update_player(0, dt, &input);
+            }
+            assert!((game.players[0].angle - side).abs() < 1e-3, "dt {}: {}", dt, game.players[0].angle);
+            game.player_check_collisions();
+            assert_eq!(game.state(), GameState::Playing);
+        }
+
+        // coming from the other side, through 0
+        input.set_pressed(&[InputID::Left]);
+        let side = 180f32.to_radians() + game.players[0].width / 2.0;
+        for dt in [1.0 / 60.0, 0.5, 3.0] {
+            game.players[0].angle = 300f32.to_radians();
+            for _ in 0..200 {
+                game.update_player(0, dt, &input);
+            }
+            assert!((game.players[0].angle - side).abs() < 1e-3, "dt {}: {}", dt, game.players[0].angle);
+        }
+    }
+
+    #[test]
+    fn near_misses_count_once_and_build_a_combo() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        game.start();
+        let dt = 1.0 / 60.0;
+        let pass_walls = |game: &mut Game, player_angle: f32, lanes: &[usize]| {
+            game.players[0].angle = player_angle;
+            for &lane in lanes {
+                game.lanes[lane].obstacles.push(Obstacle::new(4.0, 4.5, lane));
+            }
+            let mut near_misses = 0;
+            for _ in 0..120 {
+                game.events.clear();
+                game.update_obstacles(dt);
+                near_misses += game.events().iter().filter(|&&event| event == GameEvent::NearMiss).count();
+            }
+            assert!(game.get_obstacles_all().is_empty());
+            near_misses
+        };
+        // lane 1 starts at 120 degrees, the player's side is just short of it
+        let close = 120f32.to_radians() - game.players[0].width / 2.0 - 0.03;
+        assert_eq!(pass_walls(&mut game, close, &[1, 3]), 1);
+        assert_eq!((game.bonus(), game.combo()), (Game::NEAR_MISS_POINTS, 1.0 + Game::COMBO_STEP));
+        assert_eq!(game.near_miss_glow(), 1.0);
+        assert_eq!(pass_walls(&mut game, close, &[1]), 1);
+        assert_eq!(game.bonus(), 2.5 * Game::NEAR_MISS_POINTS);
+        // in the middle of lane 0 the walls next to it are too far
+        assert_eq!(pass_walls(&mut game, 90f32.to_radians(), &[1, 5]), 0);
+
+        // the combo decays while playing
+        let input = VirtualInput::new();
+        game.players[0].angle = 90f32.to_radians();
+        let combo = game.combo();
+        game.update(1.0, &input);
+        assert_eq!(game.combo(), combo - Game::COMBO_DECAY);
+        assert_eq!(game.near_miss_glow(), 0.0);
+    }
+
+    #[test]
+    fn power_ups_are_collected_in_the_player_lane() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        game.start();
+        let input = VirtualInput::new();
+        let dt = 1.0 / 60.0;
+        game.players[0].angle = game.lane_shape().lane_center(0);
+        game.lanes[0].power_ups.push(PowerUp::new(PowerUpKind::Shield, 4.0, 0));
+        game.lanes[0].power_ups.push(PowerUp::new(PowerUpKind::WideGap, 4.5, 0));
+        game.lanes[2].power_ups.push(PowerUp::new(PowerUpKind::SlowTime, 4.0, 2));
+        let mut collected = vec![];
+        for _ in 0..60 {
+            game.update(dt, &input);
+            collected.extend(game.events().iter().filter_map(|event| match event {
+                GameEvent::PowerUpCollected(kind) => Some(*kind),
+                _ => None,
+            }));
+        }
+        // the one in another lane is lost
+        assert_eq!(collected, vec![PowerUpKind::Shield, PowerUpKind::WideGap]);
+        assert!(game.get_power_ups_all().is_empty());
+        assert!(game.players[0].power_ups.shield);
+        assert_eq!(game.players[0].width, Game::PLAYER_WIDTH * ActivePowerUps::WIDE_GAP_FACTOR);
+
+        // the shield takes one hit and the wall it hit is gone
+        game.lanes = Game::empty_lanes(6);
+        game.lanes[0].obstacles.push(Obstacle::new(2.0, 4.0, 0));
+        game.lanes[3].obstacles.push(Obstacle::new(2.0, 4.0, 3));
+        game.player_check_collisions();
+        assert_eq!(game.state(), GameState::Playing);
+        assert_eq!(game.events().last(), Some(&GameEvent::ShieldBroken));
+        assert_eq!(game.get_obstacles_all().len(), 1);
+        game.lanes[0].obstacles.push(Obstacle::new(2.0, 4.0, 0));
+        game.player_check_collisions();
+        assert_eq!(game.state(), GameState::GameOver);
+    }
+
+    #[test]
+    fn slow_time_holds_back_the_walls() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        game.start();
+        let input = VirtualInput::new();
+        game.players[0].angle = game.lane_shape().lane_center(0);
+        game.lanes[3].obstacles.push(Obstacle::new(10.0, 12.0, 3));
+        game.players[0].power_ups.collect(PowerUpKind::SlowTime);
+        let beat = game.timeline.beat();
+        game.update(0.5, &input);
+        let moved = 10.0 - game.lanes[3].obstacles[0].start;
+        assert!((moved - 0.5 * game.obstacle_speed * ActivePowerUps::SLOW_TIME_FACTOR).abs() < 1e-4);
+        // and the timeline, which spawns patterns too
+        let beats = 0.5 * ActivePowerUps::SLOW_TIME_FACTOR / game.timeline.seconds_per_beat();
+        assert!((game.timeline.beat() - beat - beats).abs() < 1e-4, "{}", game.timeline.beat() - beat);
+
+        game.update(ActivePowerUps::SLOW_TIME_DURATION, &input);
+        assert_eq!(game.players[0].power_ups.world_speed(), 1.0);
+    }
+
+    #[test]
+    fn slow_time_holds_back_the_survival_time_and_the_difficulty() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        game.start();
+        let input = VirtualInput::new();
+        let second_step = game.difficulty_schedule.steps()[1].start_time;
+        game.survival_time = second_step - 0.2;
+        game.players[0].power_ups.collect(PowerUpKind::SlowTime);
+        for _ in 0..30 {
+            game.update(0.01, &input);
+        }
+        let slowed = 0.3 * ActivePowerUps::SLOW_TIME_FACTOR;
+        assert!((game.survival_time - (second_step - 0.2 + slowed)).abs() < 1e-4, "{}", game.survival_time);
+        // 0.3 s of game time would have reached the second step
+        assert_eq!(game.difficulty_step(), 0);
+        for _ in 0..20 {
+            game.update(0.01, &input);
+        }
+        assert_eq!(game.difficulty_step(), 1);
+    }
+
+    #[test]
+    fn jump_over_low_walls_and_duck_under_high_ones() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        game.start();
+        let dt = 1.0 / 60.0;
+        let mut input = VirtualInput::new();
+        let mut wall = |game: &mut Game, height: ObstacleHeight, keys: &[InputID]| {
+            game.lanes = Game::empty_lanes(6);
+            game.players[0].jump = PlayerJump::default();
+            game.players[0].angle = game.lane_shape().lane_center(0);
+            game.lanes[0].obstacles.push(Obstacle { height, ..Obstacle::new(2.0, 4.0, 0) });
+            input.set_pressed(keys);
+            game.update_player(0, dt, &input);
+            // up in the air the low wall is cleared
+            for _ in 0..10 {
+                game.update_player(0, dt, &input);
+            }
+            let mut blocked = game.blocked_arcs(&game.players[0]);
+            game.players[0].jump = PlayerJump::default();
+            blocked.extend(game.blocked_arcs(&game.players[0]));
+            blocked.len()
+        };
+        assert_eq!(wall(&mut game, ObstacleHeight::Low, &[InputID::Up]), 1);
+        assert_eq!(wall(&mut game, ObstacleHeight::Low, &[]), 2);
+        assert_eq!(wall(&mut game, ObstacleHeight::High, &[InputID::Down]), 1);
+        assert_eq!(wall(&mut game, ObstacleHeight::High, &[InputID::Up]), 2);
+        assert_eq!(wall(&mut game, ObstacleHeight::Full, &[InputID::Up]), 2);
+
+        // landing on a low wall is a collision
+        game.lanes = Game::empty_lanes(6);
+        game.lanes[0].obstacles.push(Obstacle { height: ObstacleHeight::Low, ..Obstacle::new(2.0, 4.0, 0) });
+        game.players[0].jump = PlayerJump { height: 1.0, ..PlayerJump::default() };
+        game.player_check_collisions();
+        assert_eq!(game.state(), GameState::Playing);
+        let position = game.player_position(0).y;
+        game.players[0].jump.height = 0.0;
+        assert!(game.player_position(0).y < position);
+        game.player_check_collisions();
+        assert_eq!(game.state(), GameState::GameOver);
+    }
+
+    #[test]
+    fn player_hops_between_rings() {
+        let stage = Stage::all().iter().find(|stage| stage.rings == 3).unwrap();
+        let mut game = Game::new(1.0, 1, stage.clone());
+        game.start();
+        let dt = 1.0 / 60.0;
+        let mut input = VirtualInput::new();
+        game.players[0].angle = game.lane_shape().lane_center(0);
+        // covers the inner ring but not the middle one, not even in the corners of the lane
+        game.lanes[0].obstacles.push(Obstacle::new(2.0, 3.2, 0));
+        assert_eq!(game.blocked_arcs(&game.players[0]).len(), 1);
+
+        input.set_pressed(&[InputID::Up]);
+        game.update_player(0, dt, &input);
+        assert_eq!(game.players[0].ring, 1);
+        assert!(!game.players[0].jump.airborne());
+        let mut radius = game.players[0].radius();
+        input.set_pressed(&[]);
+        for _ in 0..(Game::RING_HOP_DURATION / dt).ceil() as usize {
+            game.update_player(0, dt, &input);
+            assert!(game.players[0].radius() > radius);
+            radius = game.players[0].radius();
+        }
+        assert_eq!(game.players[0].radius(), Game::ring_radius(1));
+        assert!(game.blocked_arcs(&game.players[0]).is_empty());
+        game.player_check_collisions();
+        assert_eq!(game.state(), GameState::Playing);
+        assert!((game.player_position(0).magnitude2() - radius * radius - 1.0).abs() < 1e-3);
+
+        // the outermost ring is as far as it goes
+        for keys in [&[InputID::Up][..], &[], &[InputID::Up], &[], &[InputID::Up]] {
+            input.set_pressed(keys);
+            game.update_player(0, dt, &input);
+        }
+        assert_eq!(game.players[0].ring, Game::MAX_RINGS - 1);
+        input.set_pressed(&[InputID::Down]);
+        game.update_player(0, dt, &input);
+        assert_eq!(game.players[0].ring, 1);
+    }
+
+    #[test]
+    fn stage_sets_patterns_speed_and_palette() {
+        let first = Stage::first();
+        let pool = Game::stage_patterns(first, Game::load_patterns());
+        assert!(!pool.is_empty());
+        assert!(pool.iter().all(|pattern| first.patterns.contains(&pattern.name.as_str())));
+        let last = Stage::all().last().unwrap();
+        assert!(last.patterns.is_empty());
+        assert_eq!(Game::stage_patterns(last, Game::load_patterns()).len(), Game::load_patterns().len());
+
+        let mut easy = Game::new(1.0, 1, first.clone());
+        let mut hard = Game::new(1.0, 1, last.clone());
+        easy.start();
+        hard.start();
+        assert!((hard.obstacle_speed - last.speed * easy.obstacle_speed).abs() < 1e-4);
+        assert!((hard.player_speed - last.speed * easy.player_speed).abs() < 1e-4);
+        assert_eq!(hard.palette(), (last.palette, last.palette, 1.0));
+        assert_eq!(hard.take_music_change(), Some(last.music));
+        hard.lanes[1].obstacles.push(Obstacle::new(2.0, 4.0, 1));
+        hard.players[0].angle = 150f32.to_radians();
+        hard.player_check_collisions();
+        assert_eq!(hard.take_finished_run().map(|run| run.mode), Some(last.id));
+    }
+
+    #[test]
+    fn timeline_drives_the_run() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        let input = VirtualInput::new();
+        game.timeline = timeline_file::parse_timeline(
+            "bpm 60\n1 spawn square_gaps\n2 speed 2\n3 pulse 1.5 1\n3 palette 2\n",
+            None,
+        ).unwrap();
+        game.start();
+        game.set_lane_count(4);
+        game.lanes = Game::empty_lanes(4);
+        game.shape = LaneShape::regular(4);
+        let dt = 1.0 / 60.0;
+        let step = |game: &mut Game, seconds: f32| {
+            for _ in 0..(seconds / dt).round() as usize {
+                game.update(dt, &input);
+            }
+        };
+
+        step(&mut game, 1.01);
+        assert_eq!(game.spawner.pattern_name(), Some("square_gaps"));
+        assert!(!game.get_obstacles_all().is_empty());
+
+        let base_speed = game.difficulty_schedule.at(game.survival_time).obstacle_speed;
+        assert!((game.obstacle_speed - base_speed).abs() < 1e-3);
+        step(&mut game, 1.0);
+        let base_speed = game.difficulty_schedule.at(game.survival_time).obstacle_speed;
+        assert!((game.obstacle_speed - 2.0 * base_speed).abs() < 1e-3);
+
+        assert_eq!(game.camera_scale(), 1.0);
+        step(&mut game, 1.0);
+        assert!(game.camera_scale() > 1.4);
+        assert_eq!(game.palette().1, 2);
+        step(&mut game, 1.0);
+        assert_eq!(game.camera_scale(), 1.0);
+        assert_eq!(game.palette(), (2, 2, 1.0));
+
+        // a new run starts the timeline over
+        game.start();
+        assert_eq!(game.speed_factor, 1.0);
+        assert_eq!(game.palette(), (0, 0, 1.0));
+    }
+
+    #[test]
+    fn beat_phase_and_bar_follow_the_music() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        let input = VirtualInput::new();
+        game.timeline = timeline_file::parse_timeline("bpm 60\n", None).unwrap();
+        game.start();
+        assert_eq!(game.take_music_change(), Some("hexagon"));
+        assert_eq!(game.take_music_change(), None);
+        game.set_beat_map(Some(BeatMap::new(vec![0.5, 1.0, 1.5], vec![0.0, 2.0])));
+
+        game.sync_music(Some(MusicClock { elapsed: 5.25, position: 1.25 }));
+        assert!((game.beat_phase() - 0.5).abs() < 1e-6);
+        assert_eq!(game.bar(), Some(0));
+        game.sync_music(Some(MusicClock { elapsed: 6.0, position: 2.0 }));
+        assert_eq!((game.beat_phase(), game.bar()), (0.0, Some(1)));
+
+        // without music the timeline tempo gives the beat
+        game.sync_music(None);
+        assert_eq!(game.bar(), None);
+        let phase = game.beat_phase();
+        game.update(0.25, &input);
+        assert!(((phase + 0.25).fract() - game.beat_phase()).abs() < 1e-3, "{}", game.beat_phase());
+    }
+
+    #[test]
+    fn player_turns_with_the_arena() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        let mut input = VirtualInput::new();
+        game.start();
+        let dt = 1.0 / 60.0;
+        for _ in 0..60 {
+            game.update(dt, &input);
+        }
+        // without input the player keeps its place in the arena but not on screen
+        assert_eq!(game.players[0].angle, 0.0);
+        assert!(game.arena_rotation() != 0.0);
+        let position = game.player_position(0);
+        let world_angle = position.z.atan2(position.x).rem_euclid(TAU);
+        assert!((world_angle - game.arena_rotation()).abs() < 1e-4);
+
+        // Left stays counter-clockwise on screen while the arena turns both ways
+        input.set_pressed(&[InputID::Left]);
+        for frame in 0..120 {
+            if frame == 60 {
+                game.rotation.reverse();
+            }
+            let before = game.players[0].angle;
+            game.update(dt, &input);
+            let moved = (game.players[0].angle - before + PI).rem_euclid(TAU) - PI;
+            assert!((moved + game.player_speed * dt).abs() < 1e-4, "frame {}: {}", frame, moved);
+        }
+    }
+
+    #[test]
+    fn versus_players_have_their_own_keys_and_the_last_one_wins() {
+        let mut game = Game::new(1.0, 1, Stage::first().clone());
+        let mut input = VirtualInput::new();
+        let dt = 1.0 / 60.0;
+        // Up or Down on the title screen switches to two players
+        for keys in [&[InputID::Up][..], &[], &[InputID::Confirm]] {
+            input.set_pressed(keys);
+            game.update(dt, &input);
+        }
+        assert_eq!(game.state(), GameState::Playing);
+        assert!(game.is_versus());
+        assert_eq!(game.players.iter().map(|player| player.angle).collect::<Vec<_>>(), vec![0.0, PI]);
+
+        input.set_players_pressed([&[InputID::Right], &[InputID::Left]]);
+        game.update(dt, &input);
+        let step = game.player_speed * game.screen_sense() * dt;
+        assert!((game.players[0].angle - step).abs() < 1e-4, "{}", game.players[0].angle);
+        assert!((game.players[1].angle - (PI - step)).abs() < 1e-4, "{}", game.players[1].angle);
+
+        // a wall that only one of them runs into ends the run for that one
+        game.lanes = Game::empty_lanes(6);
+        game.players[0].angle = game.lane_shape().lane_center(0);
+        game.players[1].angle = game.lane_shape().lane_center(3);
+        game.lanes[3].obstacles.push(Obstacle::new(2.0, 4.0, 3));
+        game.player_check_collisions();
+        assert_eq!(game.state(), GameState::GameOver);
+        assert_eq!((game.players[0].alive, game.players[1].alive), (true, false));
+        assert_eq!(game.winner(), Some(0));
+        assert_eq!(game.events(), &[GameEvent::Collision]);
+        // versus runs don't count for the high scores
+        assert!(game.take_finished_run().is_none());
+
+        // the rematch is with both players again
+        input.set_pressed(&[InputID::Confirm]);
+        game.update(dt, &input);
+        assert_eq!(game.state(), GameState::Playing);
+        assert!(game.players.iter().all(|player| player.alive));
+        assert_eq!(game.winner(), None);
+
+        // alone, a player plays with anyone's keys
+        let mut solo = Game::new(1.0, 1, Stage::first().clone());
+        solo.start();
+        input.set_players_pressed([&[], &[InputID::Left]]);
+        solo.update(dt, &input);
+        assert!((solo.players[0].angle - (TAU - step)).abs() < 1e-4, "{}", solo.players[0].angle);
+    }
+}
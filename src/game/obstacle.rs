@@ -0,0 +1,158 @@
+use std::f32::consts::TAU;
+
+use super::PlayerJump;
+
+/// A wall in one lane, moving towards the center.
+///
+/// `start` and `end` are the distances from the center along the lane's
+/// center direction before `motion` is applied, see `current_start` and
+/// `current_end` for where the wall is drawn and collides.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obstacle {
+    pub start: f32,
+    pub end: f32,
+    /// Lane the obstacle was spawned in. Sliding and spinning move it away
+    /// from that lane, see `lane_offset`.
+    pub lane: usize,
+    /// Multiplier on the game's obstacle speed.
+    pub speed: f32,
+    pub motion: ObstacleMotion,
+    pub height: ObstacleHeight,
+    /// Seconds since the obstacle was spawned, this drives `motion`.
+    pub age: f32,
+    /// Smallest angle between the obstacle and the player's sides while it
+    /// crossed the player circle, see `Game::track_near_miss`.
+    pub closest_gap: f32,
+    /// Set once the obstacle has crossed the player circle, so that it is
+    /// counted as a near miss at most once.
+    pub passed: bool,
+}
+
+/// How much of the space above the ground a wall fills, which decides how
+/// the player can get past it, see `PlayerJump`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObstacleHeight {
+    /// Blocks the player whatever it does.
+    #[default]
+    Full,
+    /// Can be jumped over.
+    Low,
+    /// Can be ducked under.
+    High,
+}
+
+impl ObstacleHeight {
+    /// Name in pattern files, `Full` is the default and has none.
+    pub fn name(self) -> &'static str {
+        match self {
+            ObstacleHeight::Full => "full",
+            ObstacleHeight::Low => "low",
+            ObstacleHeight::High => "high",
+        }
+    }
+
+    /// Whether a wall of this height hits the player at `jump`.
+    pub fn blocks(self, jump: &PlayerJump) -> bool {
+        match self {
+            ObstacleHeight::Full => true,
+            ObstacleHeight::Low => jump.height < PlayerJump::CLEAR_HEIGHT,
+            ObstacleHeight::High => !jump.ducking,
+        }
+    }
+}
+
+/// Movement on top of moving towards the center. Everything is zero for a
+/// static wall, the parts can be combined.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ObstacleMotion {
+    /// Lanes to slide sideways within `slide_time` seconds after spawning,
+    /// positive is counter-clockwise.
+    pub slide_lanes: f32,
+    pub slide_time: f32,
+    /// Lanes per second to spin around the center, positive is counter-clockwise.
+    pub spin: f32,
+    /// The wall swings in and out by this distance, once per `pulse_period` seconds.
+    pub pulse_amplitude: f32,
+    pub pulse_period: f32,
+}
+
+impl ObstacleMotion {
+    pub const SLIDE: u32 = 1;
+    pub const SPIN: u32 = 2;
+    pub const PULSE: u32 = 4;
+
+    pub fn is_static(&self) -> bool {
+        self.flags() == 0
+    }
+
+    /// Bit set of `SLIDE`, `SPIN` and `PULSE`.
+    pub fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.slide_lanes != 0.0 && self.slide_time > 0.0 {
+            flags |= Self::SLIDE;
+        }
+        if self.spin != 0.0 {
+            flags |= Self::SPIN;
+        }
+        if self.pulse_amplitude != 0.0 && self.pulse_period > 0.0 {
+            flags |= Self::PULSE;
+        }
+        flags
+    }
+
+    /// Mirrors the direction of sliding and spinning.
+    pub fn mirror(&mut self) {
+        self.slide_lanes = -self.slide_lanes;
+        self.spin = -self.spin;
+    }
+}
+
+impl Obstacle {
+    pub fn new(start: f32, end: f32, lane: usize) -> Self {
+        Self {
+            start,
+            end,
+            lane,
+            speed: 1.0,
+            motion: ObstacleMotion::default(),
+            height: ObstacleHeight::Full,
+            age: 0.0,
+            closest_gap: f32::INFINITY,
+            passed: false,
+        }
+    }
+
+    /// Sideways offset from `lane` in lanes, can be fractional and beyond the lane count.
+    pub fn lane_offset(&self) -> f32 {
+        let motion = &self.motion;
+        let mut offset = motion.spin * self.age;
+        if motion.flags() & ObstacleMotion::SLIDE != 0 {
+            let t = (self.age / motion.slide_time).min(1.0);
+            // ease in and out, so the wall doesn't jump into motion
+            offset += motion.slide_lanes * t * t * (3.0 - 2.0 * t);
+        }
+        offset
+    }
+
+    fn radial_offset(&self) -> f32 {
+        let motion = &self.motion;
+        if motion.flags() & ObstacleMotion::PULSE == 0 {
+            return 0.0;
+        }
+        motion.pulse_amplitude * (self.age / motion.pulse_period * TAU).sin()
+    }
+
+    pub fn current_start(&self) -> f32 {
+        self.start + self.radial_offset()
+    }
+
+    pub fn current_end(&self) -> f32 {
+        self.end + self.radial_offset()
+    }
+
+    pub fn update(&mut self, dt: f32, obstacle_speed: f32) {
+        self.start -= dt * obstacle_speed * self.speed;
+        self.end -= dt * obstacle_speed * self.speed;
+        self.age += dt;
+    }
+}
@@ -0,0 +1,138 @@
+use super::{Game, Obstacle, PowerUp};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub name: String,
+    pub obstacles: Vec<Obstacle>,
+    /// Pickups placed by the pattern, `distance` is measured like the walls' `start`.
+    pub power_ups: Vec<PowerUp>,
+    pub duration: f32,
+    /// Difficulty level, the spawner picks patterns matching the current difficulty step.
+    pub difficulty: u32,
+    /// Number of lanes the pattern is made for, it is only spawned with that many.
+    pub lanes: usize,
+}
+
+impl Pattern {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            obstacles: vec![],
+            power_ups: vec![],
+            duration: 0.0,
+            difficulty: 1,
+            lanes: Game::DEFAULT_LANE_COUNT,
+        }
+    }
+
+    /// Sets the lane count, existing walls are wrapped into the new range.
+    pub fn set_lanes(&mut self, lanes: usize) {
+        self.lanes = lanes;
+        self.rotate(0);
+    }
+
+    pub fn rotate(&mut self, offset: i32) {
+        for obstacle in &mut self.obstacles {
+            obstacle.lane = (obstacle.lane as i32 + offset).rem_euclid(self.lanes as i32) as usize;
+        }
+        for power_up in &mut self.power_ups {
+            power_up.lane = (power_up.lane as i32 + offset).rem_euclid(self.lanes as i32) as usize;
+        }
+    }
+
+    /// Mirrors the pattern, lane `i` becomes lane `lanes - 1 - i` and moving
+    /// walls turn the other way.
+    pub fn mirror(&mut self) {
+        for obstacle in &mut self.obstacles {
+            obstacle.lane = self.lanes - 1 - obstacle.lane;
+            obstacle.motion.mirror();
+        }
+        for power_up in &mut self.power_ups {
+            power_up.lane = self.lanes - 1 - power_up.lane;
+        }
+    }
+
+    /// Scales the pattern along the direction of travel, walls and gaps get longer
+    /// for `factor > 1`.
+    pub fn stretch(&mut self, factor: f32) {
+        for obstacle in &mut self.obstacles {
+            obstacle.start *= factor;
+            obstacle.end *= factor;
+        }
+        for power_up in &mut self.power_ups {
+            power_up.distance *= factor;
+        }
+        self.duration *= factor;
+    }
+
+    pub fn add(&mut self, start: f32, end: f32, lane: usize) {
+        self.add_obstacle(Obstacle::new(start, end, lane));
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        self.obstacles.push(Obstacle { lane: obstacle.lane % self.lanes, ..obstacle });
+    }
+
+    pub fn add_power_up(&mut self, power_up: PowerUp) {
+        self.power_ups.push(PowerUp { lane: power_up.lane % self.lanes, ..power_up });
+    }
+
+    pub fn set_duration_auto(&mut self, break_reduction: f32) {
+        let mut max_end = 0.0;
+        for obstacle in &self.obstacles {
+            if obstacle.end > max_end {
+                max_end = obstacle.end;
+            }
+        }
+        self.duration = (max_end + Game::OBSTACLE_SPAWN_DISTANCE - break_reduction) / Game::OBSTACLE_SPEED;
+    }
+}
+
+
+pub(crate) struct SimplePatternRepo {
+}
+impl SimplePatternRepo {
+    pub fn create_patterns() -> Vec<Pattern> {
+
+        let mut pattern1 = Pattern::new("spiral");
+        for i in 0..12 {
+            pattern1.add((i * 2) as f32, (i * 2) as f32 + 4.0, i % 6);
+        }
+        pattern1.set_duration_auto(8.0);
+
+        let mut pattern2 = Pattern::new("spiral_back_and_forth");
+        pattern2.duration = 12.0;
+        for i in 0..12 {
+            pattern2.add((i * 2) as f32, (i * 2) as f32 + 4.0, i);
+        }
+        for i in 14..26 {
+            pattern2.add((i * 2) as f32 - 4.0, (i * 2) as f32, 26-i);
+        }
+        pattern2.set_duration_auto(8.0);
+        pattern2.difficulty = 3;
+
+        let mut pattern3 = Pattern::new("rotating_gaps");
+        for i in 0..5 {
+            pattern3.add(0.0, 2.0, i);
+        }
+        pattern3.rotate(4);
+        for i in 0..5 {
+            pattern3.add(8.0, 10.0, i);
+        }
+        pattern3.rotate(4);
+        for i in 0..5 {
+            pattern3.add(16.0, 18.0, i);
+        }
+        pattern3.set_duration_auto(8.0);
+        pattern3.difficulty = 2;
+
+        let patterns = vec![
+            pattern1,
+            pattern2,
+            pattern3,
+        ];
+
+        patterns
+    }
+
+}
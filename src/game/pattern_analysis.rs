@@ -0,0 +1,345 @@
+// Static analysis of obstacle patterns: whether a pattern can be survived at
+// all and how hard it is, without running the game.
+use std::{f32::consts::TAU, fmt};
+
+use super::collision;
+use super::difficulty::DifficultySchedule;
+use super::pattern::Pattern;
+use super::{Game, LaneShape, Obstacle};
+
+/// Positions of the player center the analysis tells apart.
+const BINS: usize = 720;
+const BIN_WIDTH: f32 = TAU / BINS as f32;
+/// Seconds between the analyzed positions, one frame of the engine.
+const STEP: f32 = 1.0 / 60.0;
+/// Score added per lane the player has to travel through a pattern.
+const LANE_SCORE: f32 = 0.5;
+/// Score of a dodge that takes the whole time there is for it at full speed.
+const TIGHTNESS_SCORE: f32 = 10.0;
+
+/// Speeds the patterns are analyzed with, like `Difficulty` gives them to a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnalysisParams {
+    pub obstacle_speed: f32,
+    /// Radians per second.
+    pub player_speed: f32,
+    /// Radians.
+    pub player_width: f32,
+}
+
+impl AnalysisParams {
+    /// The speeds of the default difficulty schedule after `time` seconds of
+    /// survival, on a stage with speed 1.
+    pub fn at(time: f32) -> Self {
+        let difficulty = DifficultySchedule::default_schedule().at(time);
+        Self {
+            obstacle_speed: difficulty.obstacle_speed,
+            player_speed: difficulty.player_speed,
+            player_width: Game::PLAYER_WIDTH,
+        }
+    }
+}
+
+impl Default for AnalysisParams {
+    /// The speeds at the start of a run.
+    fn default() -> Self {
+        Self::at(0.0)
+    }
+}
+
+/// One sideways move the player has to make, on the path that moves as
+/// little and as late as possible.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dodge {
+    /// Seconds after the pattern spawned when the move starts and ends.
+    pub start: f32,
+    pub end: f32,
+    /// Radians moved, in either direction.
+    pub travel: f32,
+    /// Seconds between the end of the move before it, or the spawn, and the
+    /// end of this one. The player has to react and move within it.
+    pub window: f32,
+    /// Share of the window the move takes at full player speed, above 1 the
+    /// move is impossible.
+    pub tightness: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternAnalysis {
+    pub name: String,
+    pub lanes: usize,
+    /// Lanes the player can't survive the pattern from, standing at their center.
+    pub unsafe_start_lanes: Vec<usize>,
+    /// The dodge with the highest tightness over all safe start lanes.
+    pub tightest: Option<Dodge>,
+    /// The most radians the player has to travel from any safe start lane.
+    pub travel: f32,
+    /// `TIGHTNESS_SCORE` times the tightness of the tightest dodge plus
+    /// `LANE_SCORE` per lane of travel, from the worst start lane. Infinite if
+    /// the pattern is unsolvable from any lane.
+    pub score: f32,
+}
+
+impl PatternAnalysis {
+    pub fn solvable(&self) -> bool {
+        self.unsafe_start_lanes.is_empty()
+    }
+}
+
+impl fmt::Display for PatternAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.solvable() {
+            let lanes: Vec<String> = self.unsafe_start_lanes.iter().map(|lane| lane.to_string()).collect();
+            return write!(f, "{}: unsolvable from lane {}", self.name, lanes.join(", "));
+        }
+        let lane_width = TAU / self.lanes as f32;
+        write!(f, "{}: score {:.2}, travel {:.2} lanes", self.name, self.score, self.travel / lane_width)?;
+        match &self.tightest {
+            Some(dodge) => write!(
+                f,
+                ", tightest dodge {:.2} lanes in {:.2} s at {:.2} s ({:.0}% of full speed)",
+                dodge.travel / lane_width,
+                dodge.window,
+                dodge.end,
+                dodge.tightness * 100.0
+            ),
+            None => write!(f, ", no dodges"),
+        }
+    }
+}
+
+/// Checks if `pattern` can be survived from the center of every lane, and
+/// rates it.
+///
+/// The pattern is spawned like the spawner does on a regular lane shape, and
+/// moved in `STEP`s until all walls have passed. The circle of player
+/// positions is split into `BINS`; a bin is free during a step if the player
+/// centered on it overlaps no wall at any time of the step. Going backwards
+/// from the last step, the bins the player survives from are the free ones
+/// from which a bin it survives from in the next step can be reached, moving
+/// at most the player speed through free bins. The dodges are measured on
+/// the path that stands still whenever that survives, and otherwise moves to
+/// the nearest surviving bin. Low and high walls count as full ones, the
+/// player has to get around them here, so jumping and ducking can only make
+/// a pattern easier than rated.
+pub fn analyze_pattern(pattern: &Pattern, params: &AnalysisParams) -> PatternAnalysis {
+    let shape = LaneShape::regular(pattern.lanes);
+    let free = free_bins(pattern, &shape, params);
+    let reach = (params.player_speed * STEP / BIN_WIDTH) as usize;
+
+    // survivable[k]: the bins the player survives from at the start of step k
+    let mut survivable = vec![vec![true; BINS]; free.len() + 1];
+    for k in (0..free.len()).rev() {
+        let target = and(&free[k], &survivable[k + 1]);
+        let distance = distance_to(&target, &free[k]);
+        survivable[k] = distance.iter().map(|&d| d <= reach).collect();
+    }
+
+    let mut analysis = PatternAnalysis {
+        name: pattern.name.clone(),
+        lanes: pattern.lanes,
+        unsafe_start_lanes: vec![],
+        tightest: None,
+        travel: 0.0,
+        score: 0.0,
+    };
+    for lane in 0..pattern.lanes {
+        let bin = bin_at(shape.lane_center(lane));
+        if !survivable[0][bin] {
+            analysis.unsafe_start_lanes.push(lane);
+            continue;
+        }
+        let dodges = lazy_dodges(bin, &free, &survivable, reach, params);
+        let travel: f32 = dodges.iter().map(|dodge| dodge.travel).sum();
+        let tightest = dodges.into_iter().max_by(|a, b| a.tightness.total_cmp(&b.tightness));
+        let tightness = tightest.map_or(0.0, |dodge| dodge.tightness);
+        let score = TIGHTNESS_SCORE * tightness + LANE_SCORE * travel / shape.lane_width();
+        analysis.travel = analysis.travel.max(travel);
+        analysis.score = analysis.score.max(score);
+        if tightest.is_some_and(|dodge| analysis.tightest.is_none_or(|worst| dodge.tightness > worst.tightness)) {
+            analysis.tightest = tightest;
+        }
+    }
+    if !analysis.solvable() {
+        analysis.score = f32::INFINITY;
+    }
+    analysis
+}
+
+fn bin_at(angle: f32) -> usize {
+    ((angle.rem_euclid(TAU) / BIN_WIDTH) as usize).min(BINS - 1)
+}
+
+fn and(a: &[bool], b: &[bool]) -> Vec<bool> {
+    a.iter().zip(b).map(|(&a, &b)| a && b).collect()
+}
+
+/// For each step until the last wall has passed the player, the bins where
+/// the player overlaps no wall during the step.
+fn free_bins(pattern: &Pattern, shape: &LaneShape, params: &AnalysisParams) -> Vec<Vec<bool>> {
+    let obstacles: Vec<Obstacle> = pattern
+        .obstacles
+        .iter()
+        .map(|obstacle| Obstacle {
+            start: Game::OBSTACLE_SPAWN_DISTANCE + obstacle.start,
+            end: Game::OBSTACLE_SPAWN_DISTANCE + obstacle.end,
+            lane: obstacle.lane % pattern.lanes,
+            age: 0.0,
+            ..*obstacle
+        })
+        .collect();
+    let duration = obstacles
+        .iter()
+        .map(|obstacle| (obstacle.end + obstacle.motion.pulse_amplitude.abs()) / (obstacle.speed * params.obstacle_speed))
+        .fold(0.0, f32::max);
+    let steps = (duration / STEP).ceil() as usize + 1;
+
+    let at = |obstacle: &Obstacle, time: f32| {
+        let mut obstacle = *obstacle;
+        obstacle.update(time, params.obstacle_speed);
+        obstacle
+    };
+    (0..steps)
+        .map(|step| {
+            let time = step as f32 * STEP;
+            let mut free = vec![true; BINS];
+            for obstacle in &obstacles {
+                let (now, later) = (at(obstacle, time), at(obstacle, time + STEP));
+                for arc in collision::swept_obstacle_arcs(&now, &later, shape, Game::PLAYER_RADIUS) {
+                    // the player centers whose arc overlaps this one
+                    let first = ((arc.start - params.player_width / 2.0) / BIN_WIDTH - 0.5).ceil() as i64;
+                    let last = ((arc.end() + params.player_width / 2.0) / BIN_WIDTH - 0.5).floor() as i64;
+                    for bin in first..=last {
+                        free[bin.rem_euclid(BINS as i64) as usize] = false;
+                    }
+                }
+            }
+            free
+        })
+        .collect()
+}
+
+/// For each bin, the fewest bins to move through `passable` ones to a
+/// `target` one, `usize::MAX` if there is none.
+fn distance_to(target: &[bool], passable: &[bool]) -> Vec<usize> {
+    let mut distance: Vec<usize> = target.iter().map(|&t| if t { 0 } else { usize::MAX }).collect();
+    // two turns in each direction, so paths across the wrap are found too
+    for i in 1..=2 * BINS {
+        let (bin, previous) = (i % BINS, (i - 1) % BINS);
+        if passable[bin] && distance[previous] != usize::MAX {
+            distance[bin] = distance[bin].min(distance[previous] + 1);
+        }
+    }
+    for i in (0..2 * BINS).rev() {
+        let (bin, next) = (i % BINS, (i + 1) % BINS);
+        if passable[bin] && distance[next] != usize::MAX {
+            distance[bin] = distance[bin].min(distance[next] + 1);
+        }
+    }
+    distance
+}
+
+/// The dodges of the path from `start` that stands still while that
+/// survives, and otherwise moves to the nearest bin that does.
+fn lazy_dodges(
+    start: usize,
+    free: &[Vec<bool>],
+    survivable: &[Vec<bool>],
+    reach: usize,
+    params: &AnalysisParams,
+) -> Vec<Dodge> {
+    let mut dodges: Vec<Dodge> = Vec::new();
+    let mut moving: Option<Dodge> = None;
+    let mut bin = start;
+    let mut direction = 1;
+    for k in 0..free.len() {
+        let time = k as f32 * STEP;
+        let target = |bin: usize| free[k][bin] && survivable[k + 1][bin];
+        let moved = if target(bin) {
+            None
+        } else {
+            // nearest target through free bins, ties go the way of the last move
+            (1..=reach as i64).find_map(|distance| {
+                [direction, -direction].into_iter().find_map(|sign| {
+                    let at = |d: i64| (bin as i64 + sign * d).rem_euclid(BINS as i64) as usize;
+                    ((1..=distance).all(|d| free[k][at(d)]) && target(at(distance))).then_some((at(distance), sign, distance))
+                })
+            })
+        };
+        match moved {
+            Some((to, sign, distance)) => {
+                bin = to;
+                direction = sign;
+                let travel = distance as f32 * BIN_WIDTH;
+                let dodge = moving.get_or_insert(Dodge { start: time, end: time, travel: 0.0, window: 0.0, tightness: 0.0 });
+                dodge.end = time + STEP;
+                dodge.travel += travel;
+            }
+            None => dodges.extend(moving.take()),
+        }
+    }
+    dodges.extend(moving);
+
+    let mut previous_end = 0.0;
+    for dodge in &mut dodges {
+        dodge.window = dodge.end - previous_end;
+        dodge.tightness = dodge.travel / (dodge.window * params.player_speed);
+        previous_end = dodge.end;
+    }
+    dodges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_patterns_are_solvable() {
+        for pattern in Game::load_patterns() {
+            let analysis = analyze_pattern(&pattern, &AnalysisParams::default());
+            assert!(analysis.solvable(), "{}", analysis);
+            assert!(analysis.score.is_finite() && analysis.score > 0.0, "{}", analysis);
+            let tightest = analysis.tightest.expect("every pattern needs a dodge");
+            assert!(tightest.tightness <= 1.0 && tightest.travel > 0.0, "{}", analysis);
+        }
+    }
+
+    #[test]
+    fn measures_dodges_and_rejects_walls_without_gaps() {
+        // one wall in lane 0: only that lane has to move, by half a lane and half the player
+        let mut pattern = Pattern::new("single");
+        pattern.obstacles.push(Obstacle::new(0.0, 1.0, 0));
+        let params = AnalysisParams::default();
+        let analysis = analyze_pattern(&pattern, &params);
+        assert!(analysis.solvable());
+        let dodge = analysis.tightest.unwrap();
+        let expected = TAU / 12.0 + params.player_width / 2.0;
+        assert!((dodge.travel - expected).abs() < 0.03, "{:?}", dodge);
+        // it moves at the last moment, keeping ahead of the wall while it covers the lane
+        let arrival = (Game::OBSTACLE_SPAWN_DISTANCE - Game::PLAYER_RADIUS * (TAU / 12.0).cos()) / params.obstacle_speed;
+        assert!(dodge.end < arrival && dodge.end > arrival - 0.3, "{:?}", dodge);
+        assert!((dodge.window - dodge.end).abs() < 1e-6);
+
+        // a wall in every lane can't be passed
+        for lane in 1..6 {
+            pattern.obstacles.push(Obstacle::new(0.0, 1.0, lane));
+        }
+        let analysis = analyze_pattern(&pattern, &params);
+        assert_eq!(analysis.unsafe_start_lanes, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(analysis.score, f32::INFINITY);
+
+        // a gap three lanes away from lane 0 right after a gap at lane 0 needs
+        // a faster player
+        let mut pattern = Pattern::new("far_gap");
+        for lane in 1..6 {
+            pattern.obstacles.push(Obstacle::new(0.0, 0.5, lane));
+        }
+        for lane in (0..6).filter(|&lane| lane != 3) {
+            pattern.obstacles.push(Obstacle::new(2.0, 2.5, lane));
+        }
+        assert!(!analyze_pattern(&pattern, &params).solvable());
+        let fast = AnalysisParams { player_speed: 2.5 * params.player_speed, ..params };
+        let analysis = analyze_pattern(&pattern, &fast);
+        assert!(analysis.solvable(), "{}", analysis);
+    }
+}
@@ -0,0 +1,713 @@
+// Text format for obstacle patterns, so walls can be tweaked without recompiling.
+//
+// A file contains one or more patterns. Lines starting with `#` are comments.
+//
+//   pattern <name>                          start a new pattern
+//   wall <start> <end> <lane> [options]     add one obstacle, options make it move:
+//       speed <factor>                      move towards the center `factor` times as fast
+//       slide <lanes> <seconds>             slide sideways by `lanes` after spawning
+//       spin <lanes_per_second>             keep turning around the center
+//       pulse <distance> <seconds>          swing in and out by `distance`, once per `seconds`
+//       low | high                          a wall to jump over or to duck under
+//   powerup <kind> <distance> <lane>        add a pickup: `shield`, `slow` or `wide`
+//   repeat <count> [shift <d>] [lane <n>]   repeat the block up to the matching `end`,
+//   ...                                     moving it by `d` distance and `n` lanes
+//   end                                     each iteration
+//   rotate <offset>                         rotate all walls added so far by `offset` lanes
+//   duration <seconds>                      set the pattern duration
+//   duration auto <break_reduction>         see `Pattern::set_duration_auto`
+//   difficulty <level>                      difficulty level used by the spawner, default 1
+//   lanes <count>                           number of lanes (4 to 8), default 6, must come
+//                                           before the first wall
+//
+// Directives run in order, like the equivalent calls on `Pattern`. A pattern
+// without a duration directive gets `duration auto 0`.
+use std::{
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::pattern::Pattern;
+use super::{Game, Obstacle, ObstacleHeight, ObstacleMotion, PowerUp, PowerUpKind};
+
+pub const PATTERN_DIR: &str = "assets/patterns";
+pub const PATTERN_EXTENSION: &str = "pattern";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternParseError {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PatternParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "<pattern>".to_string(),
+        };
+        write!(f, "{}:{}:{}: {}", path, self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for PatternParseError {}
+
+#[derive(Debug)]
+pub enum PatternLoadError {
+    Io(PathBuf, io::Error),
+    Parse(PatternParseError),
+}
+
+impl fmt::Display for PatternLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternLoadError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            PatternLoadError::Parse(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PatternLoadError {}
+
+/// A whitespace separated word with its 1-based column.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Token<'a> {
+    pub text: &'a str,
+    pub column: usize,
+}
+
+pub(super) fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices() {
+        if c.is_whitespace() {
+            if let Some(s) = start.take() {
+                tokens.push(Token { text: &line[s..i], column: line[..s].chars().count() + 1 });
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(Token { text: &line[s..], column: line[..s].chars().count() + 1 });
+    }
+    tokens
+}
+
+struct Wall {
+    start: f32,
+    end: f32,
+    lane: i32,
+    speed: f32,
+    motion: ObstacleMotion,
+    height: ObstacleHeight,
+}
+
+enum Command {
+    Wall(Wall),
+    PowerUp { kind: PowerUpKind, distance: f32, lane: i32 },
+    Repeat { count: usize, shift: f32, lane_step: i32, body: Vec<Command> },
+    Rotate(i32),
+    Duration(f32),
+    DurationAuto(f32),
+    Difficulty(u32),
+    Lanes(usize),
+}
+
+/// A `repeat` block that has not seen its `end` line yet.
+struct OpenRepeat {
+    line: usize,
+    column: usize,
+    count: usize,
+    shift: f32,
+    lane_step: i32,
+    body: Vec<Command>,
+}
+
+/// Line-by-line parser state. Repeat blocks are collected on a stack until
+/// their `end` line and only executed once a pattern is complete.
+struct Parser<'a> {
+    path: Option<&'a Path>,
+    line: usize,
+    /// Patterns from other files, their names can't be used again.
+    defined: &'a [Pattern],
+    patterns: Vec<Pattern>,
+    current: Option<(String, Vec<Command>)>,
+    open_blocks: Vec<OpenRepeat>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, column: usize, message: impl Into<String>) -> PatternParseError {
+        PatternParseError {
+            path: self.path.map(Path::to_path_buf),
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn parse_f32(&self, token: Token) -> Result<f32, PatternParseError> {
+        match token.text.parse::<f32>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(self.error(token.column, format!("expected a number, found `{}`", token.text))),
+        }
+    }
+
+    fn parse_i32(&self, token: Token) -> Result<i32, PatternParseError> {
+        token.text.parse::<i32>()
+            .map_err(|_| self.error(token.column, format!("expected an integer, found `{}`", token.text)))
+    }
+
+    fn parse_count(&self, token: Token) -> Result<usize, PatternParseError> {
+        token.text.parse::<usize>()
+            .map_err(|_| self.error(token.column, format!("expected a repeat count, found `{}`", token.text)))
+    }
+
+    fn expect_args(&self, tokens: &[Token], count: usize, usage: &str) -> Result<(), PatternParseError> {
+        if tokens.len() - 1 < count {
+            let column = tokens.last().map(|t| t.column + t.text.chars().count()).unwrap_or(1);
+            return Err(self.error(column, format!("missing argument, expected `{}`", usage)));
+        }
+        if tokens.len() - 1 > count {
+            return Err(self.error(tokens[count + 1].column, format!("unexpected argument, expected `{}`", usage)));
+        }
+        Ok(())
+    }
+
+    fn parse_positive(&self, token: Token) -> Result<f32, PatternParseError> {
+        let value = self.parse_f32(token)?;
+        if value <= 0.0 {
+            return Err(self.error(token.column, format!("expected a positive number, found `{}`", token.text)));
+        }
+        Ok(value)
+    }
+
+    fn parse_wall_options(&self, tokens: &[Token], wall: &mut Wall) -> Result<(), PatternParseError> {
+        let mut tokens = tokens.iter().copied();
+        while let Some(option) = tokens.next() {
+            let value_count = match option.text {
+                "low" | "high" => 0,
+                "speed" | "spin" => 1,
+                "slide" | "pulse" => 2,
+                _ => return Err(self.error(option.column, format!(
+                    "unknown wall option `{}`, expected `speed`, `slide`, `spin`, `pulse`, `low` or `high`", option.text
+                ))),
+            };
+            let values: Vec<Token> = tokens.by_ref().take(value_count).collect();
+            if values.len() < value_count {
+                return Err(self.error(option.column, format!("missing value for `{}`", option.text)));
+            }
+            match option.text {
+                "low" => wall.height = ObstacleHeight::Low,
+                "high" => wall.height = ObstacleHeight::High,
+                "speed" => wall.speed = self.parse_positive(values[0])?,
+                "spin" => wall.motion.spin = self.parse_f32(values[0])?,
+                "slide" => {
+                    wall.motion.slide_lanes = self.parse_f32(values[0])?;
+                    wall.motion.slide_time = self.parse_positive(values[1])?;
+                }
+                _ => {
+                    wall.motion.pulse_amplitude = self.parse_f32(values[0])?;
+                    wall.motion.pulse_period = self.parse_positive(values[1])?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push_command(&mut self, command: Command, column: usize) -> Result<(), PatternParseError> {
+        if let Some(block) = self.open_blocks.last_mut() {
+            block.body.push(command);
+            return Ok(());
+        }
+        match &mut self.current {
+            Some((_, commands)) => {
+                commands.push(command);
+                Ok(())
+            }
+            None => Err(self.error(column, "directive outside of a pattern, add a `pattern <name>` line first")),
+        }
+    }
+
+    fn finish_pattern(&mut self) -> Result<(), PatternParseError> {
+        if let Some(block) = self.open_blocks.last() {
+            return Err(PatternParseError {
+                path: self.path.map(Path::to_path_buf),
+                line: block.line,
+                column: block.column,
+                message: "`repeat` is never closed with `end`".to_string(),
+            });
+        }
+        if let Some((name, commands)) = self.current.take() {
+            let mut pattern = Pattern::new(&name);
+            let mut has_duration = false;
+            for command in &commands {
+                has_duration |= run_command(&mut pattern, command, 0.0, 0);
+            }
+            if !has_duration {
+                pattern.set_duration_auto(0.0);
+            }
+            self.patterns.push(pattern);
+        }
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), PatternParseError> {
+        let content = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let tokens = tokenize(content);
+        let Some(directive) = tokens.first().copied() else {
+            return Ok(());
+        };
+
+        match directive.text {
+            "pattern" => {
+                self.expect_args(&tokens, 1, "pattern <name>")?;
+                self.finish_pattern()?;
+                let name = tokens[1].text.to_string();
+                if self.patterns.iter().any(|p| p.name == name) {
+                    return Err(self.error(tokens[1].column, format!("duplicate pattern name `{}`", name)));
+                }
+                if self.defined.iter().any(|p| p.name == name) {
+                    return Err(self.error(tokens[1].column, format!("pattern `{}` is already defined in another file", name)));
+                }
+                self.current = Some((name, vec![]));
+            }
+            "wall" => {
+                if tokens.len() < 4 {
+                    self.expect_args(&tokens, 3, "wall <start> <end> <lane> [options]")?;
+                }
+                let start = self.parse_f32(tokens[1])?;
+                let end = self.parse_f32(tokens[2])?;
+                let lane = self.parse_i32(tokens[3])?;
+                if end <= start {
+                    return Err(self.error(tokens[2].column, "wall end must be greater than its start"));
+                }
+                let mut wall = Wall {
+                    start,
+                    end,
+                    lane,
+                    speed: 1.0,
+                    motion: ObstacleMotion::default(),
+                    height: ObstacleHeight::Full,
+                };
+                self.parse_wall_options(&tokens[4..], &mut wall)?;
+                self.push_command(Command::Wall(wall), directive.column)?;
+            }
+            "powerup" => {
+                self.expect_args(&tokens, 3, "powerup <kind> <distance> <lane>")?;
+                let kind = PowerUpKind::from_name(tokens[1].text).ok_or_else(|| self.error(tokens[1].column, format!(
+                    "unknown power-up `{}`, expected `shield`, `slow` or `wide`", tokens[1].text
+                )))?;
+                let distance = self.parse_f32(tokens[2])?;
+                let lane = self.parse_i32(tokens[3])?;
+                self.push_command(Command::PowerUp { kind, distance, lane }, directive.column)?;
+            }
+            "repeat" => {
+                if tokens.len() < 2 {
+                    self.expect_args(&tokens, 1, "repeat <count> [shift <distance>] [lane <step>]")?;
+                }
+                if self.current.is_none() {
+                    return Err(self.error(directive.column, "directive outside of a pattern, add a `pattern <name>` line first"));
+                }
+                let count = self.parse_count(tokens[1])?;
+                let mut shift = 0.0;
+                let mut lane_step = 0;
+                let mut options = tokens[2..].iter();
+                while let Some(option) = options.next() {
+                    let Some(value) = options.next() else {
+                        return Err(self.error(option.column, format!("missing value for `{}`", option.text)));
+                    };
+                    match option.text {
+                        "shift" => shift = self.parse_f32(*value)?,
+                        "lane" => lane_step = self.parse_i32(*value)?,
+                        _ => return Err(self.error(option.column, format!("unknown repeat option `{}`, expected `shift` or `lane`", option.text))),
+                    }
+                }
+                self.open_blocks.push(OpenRepeat {
+                    line: self.line,
+                    column: directive.column,
+                    count,
+                    shift,
+                    lane_step,
+                    body: vec![],
+                });
+            }
+            "end" => {
+                self.expect_args(&tokens, 0, "end")?;
+                let Some(block) = self.open_blocks.pop() else {
+                    return Err(self.error(directive.column, "`end` without a matching `repeat`"));
+                };
+                let command = Command::Repeat {
+                    count: block.count,
+                    shift: block.shift,
+                    lane_step: block.lane_step,
+                    body: block.body,
+                };
+                self.push_command(command, directive.column)?;
+            }
+            "rotate" => {
+                self.expect_args(&tokens, 1, "rotate <offset>")?;
+                let offset = self.parse_i32(tokens[1])?;
+                self.push_command(Command::Rotate(offset), directive.column)?;
+            }
+            "duration" => {
+                if tokens.get(1).map(|t| t.text) == Some("auto") {
+                    self.expect_args(&tokens, 2, "duration auto <break_reduction>")?;
+                    let break_reduction = self.parse_f32(tokens[2])?;
+                    self.push_command(Command::DurationAuto(break_reduction), directive.column)?;
+                } else {
+                    self.expect_args(&tokens, 1, "duration <seconds>")?;
+                    let duration = self.parse_f32(tokens[1])?;
+                    if duration <= 0.0 {
+                        return Err(self.error(tokens[1].column, "duration must be positive"));
+                    }
+                    self.push_command(Command::Duration(duration), directive.column)?;
+                }
+            }
+            "difficulty" => {
+                self.expect_args(&tokens, 1, "difficulty <level>")?;
+                let level = tokens[1].text.parse::<u32>()
+                    .map_err(|_| self.error(tokens[1].column, format!("expected a difficulty level, found `{}`", tokens[1].text)))?;
+                self.push_command(Command::Difficulty(level), directive.column)?;
+            }
+            "lanes" => {
+                self.expect_args(&tokens, 1, "lanes <count>")?;
+                let lanes = tokens[1].text.parse::<usize>().ok()
+                    .filter(|lanes| (Game::MIN_LANE_COUNT..=Game::MAX_LANE_COUNT).contains(lanes))
+                    .ok_or_else(|| self.error(tokens[1].column, format!(
+                        "expected a lane count from {} to {}, found `{}`",
+                        Game::MIN_LANE_COUNT, Game::MAX_LANE_COUNT, tokens[1].text
+                    )))?;
+                let has_walls = !self.open_blocks.is_empty() || self.current.as_ref().is_some_and(|(_, commands)| {
+                    commands.iter().any(|c| matches!(c, Command::Wall(_) | Command::PowerUp { .. } | Command::Repeat { .. } | Command::Rotate(_)))
+                });
+                if has_walls {
+                    return Err(self.error(directive.column, "`lanes` must come before the walls of a pattern"));
+                }
+                self.push_command(Command::Lanes(lanes), directive.column)?;
+            }
+            other => {
+                return Err(self.error(directive.column, format!("unknown directive `{}`", other)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies `command` to `pattern`, with walls moved by `shift` and `lane_offset`.
+/// Returns whether the command set the duration.
+fn run_command(pattern: &mut Pattern, command: &Command, shift: f32, lane_offset: i32) -> bool {
+    match command {
+        Command::Wall(wall) => {
+            let lane = (wall.lane + lane_offset).rem_euclid(pattern.lanes as i32) as usize;
+            pattern.add_obstacle(Obstacle {
+                speed: wall.speed,
+                motion: wall.motion,
+                height: wall.height,
+                ..Obstacle::new(wall.start + shift, wall.end + shift, lane)
+            });
+            false
+        }
+        Command::PowerUp { kind, distance, lane } => {
+            let lane = (lane + lane_offset).rem_euclid(pattern.lanes as i32) as usize;
+            pattern.add_power_up(PowerUp::new(*kind, distance + shift, lane));
+            false
+        }
+        Command::Repeat { count, shift: step, lane_step, body } => {
+            let mut has_duration = false;
+            for k in 0..*count {
+                for inner in body {
+                    has_duration |= run_command(
+                        pattern,
+                        inner,
+                        shift + step * k as f32,
+                        lane_offset + lane_step * k as i32,
+                    );
+                }
+            }
+            has_duration
+        }
+        Command::Rotate(offset) => {
+            pattern.rotate(*offset);
+            false
+        }
+        Command::Duration(duration) => {
+            pattern.duration = *duration;
+            true
+        }
+        Command::DurationAuto(break_reduction) => {
+            pattern.set_duration_auto(*break_reduction);
+            true
+        }
+        Command::Difficulty(level) => {
+            pattern.difficulty = *level;
+            false
+        }
+        Command::Lanes(lanes) => {
+            pattern.set_lanes(*lanes);
+            false
+        }
+    }
+}
+
+/// Parses all patterns in `source`. `path` is only used for error messages.
+pub fn parse_patterns(source: &str, path: Option<&Path>) -> Result<Vec<Pattern>, PatternParseError> {
+    parse_patterns_after(source, path, &[])
+}
+
+/// Like `parse_patterns`, for a file read after the one that had `defined`.
+fn parse_patterns_after(source: &str, path: Option<&Path>, defined: &[Pattern]) -> Result<Vec<Pattern>, PatternParseError> {
+    let mut parser = Parser {
+        path,
+        line: 0,
+        defined,
+        patterns: vec![],
+        current: None,
+        open_blocks: vec![],
+    };
+    for (i, line) in source.lines().enumerate() {
+        parser.line = i + 1;
+        parser.parse_line(line)?;
+    }
+    parser.finish_pattern()?;
+    Ok(parser.patterns)
+}
+
+/// Paths of the `*.pattern` files in `dir`, in file name order.
+pub fn pattern_files(dir: &Path) -> Result<Vec<PathBuf>, PatternLoadError> {
+    let mut files = Vec::new();
+    let entries = fs::read_dir(dir).map_err(|e| PatternLoadError::Io(dir.to_path_buf(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| PatternLoadError::Io(dir.to_path_buf(), e))?.path();
+        if path.extension().is_some_and(|ext| ext == PATTERN_EXTENSION) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Loads all `*.pattern` files in `dir`, in file name order.
+pub fn load_patterns_dir(dir: &Path) -> Result<Vec<Pattern>, PatternLoadError> {
+    let mut patterns: Vec<Pattern> = Vec::new();
+    for file in pattern_files(dir)? {
+        let source = fs::read_to_string(&file).map_err(|e| PatternLoadError::Io(file.clone(), e))?;
+        let parsed = parse_patterns_after(&source, Some(&file), &patterns).map_err(PatternLoadError::Parse)?;
+        patterns.extend(parsed);
+    }
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::pattern::SimplePatternRepo;
+
+    /// Writes `patterns` in the text format, one `wall` line per obstacle.
+    fn write_patterns(patterns: &[Pattern]) -> String {
+        let mut out = String::new();
+        for (i, pattern) in patterns.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(&format!("pattern {}\n", pattern.name));
+            out.push_str(&format!("    lanes {}\n", pattern.lanes));
+            out.push_str(&format!("    difficulty {}\n", pattern.difficulty));
+            for obstacle in &pattern.obstacles {
+                out.push_str(&format!("    wall {:?} {:?} {}", obstacle.start, obstacle.end, obstacle.lane));
+                let motion = &obstacle.motion;
+                if obstacle.speed != 1.0 {
+                    out.push_str(&format!(" speed {:?}", obstacle.speed));
+                }
+                if motion.flags() & ObstacleMotion::SLIDE != 0 {
+                    out.push_str(&format!(" slide {:?} {:?}", motion.slide_lanes, motion.slide_time));
+                }
+                if motion.flags() & ObstacleMotion::SPIN != 0 {
+                    out.push_str(&format!(" spin {:?}", motion.spin));
+                }
+                if motion.flags() & ObstacleMotion::PULSE != 0 {
+                    out.push_str(&format!(" pulse {:?} {:?}", motion.pulse_amplitude, motion.pulse_period));
+                }
+                if obstacle.height != ObstacleHeight::Full {
+                    out.push_str(&format!(" {}", obstacle.height.name()));
+                }
+                out.push('\n');
+            }
+            for power_up in &pattern.power_ups {
+                out.push_str(&format!("    powerup {} {:?} {}\n", power_up.kind.name(), power_up.distance, power_up.lane));
+            }
+            out.push_str(&format!("    duration {:?}\n", pattern.duration));
+        }
+        out
+    }
+
+    fn parse_error(source: &str) -> PatternParseError {
+        parse_patterns(source, None).expect_err("expected a parse error")
+    }
+
+    #[test]
+    fn builtin_patterns_round_trip() {
+        let builtin = SimplePatternRepo::create_patterns();
+        let text = write_patterns(&builtin);
+        let parsed = parse_patterns(&text, None).unwrap();
+        assert_eq!(parsed, builtin);
+    }
+
+    #[test]
+    fn repeat_shifts_and_wraps_lanes() {
+        let patterns = parse_patterns(
+            "pattern p\n  repeat 3 shift 2 lane -1\n    wall 0 1 0\n  end\n  duration 5\n",
+            None,
+        ).unwrap();
+        let walls: Vec<_> = patterns[0].obstacles.iter().map(|o| (o.start, o.end, o.lane)).collect();
+        assert_eq!(walls, vec![(0.0, 1.0, 0), (2.0, 3.0, 5), (4.0, 5.0, 4)]);
+        assert_eq!(patterns[0].duration, 5.0);
+    }
+
+    #[test]
+    fn nested_repeat() {
+        let patterns = parse_patterns(
+            "pattern p\nrepeat 2 shift 10\n  repeat 2 lane 3\n    wall 0 1 1\n  end\nend\n",
+            None,
+        ).unwrap();
+        let walls: Vec<_> = patterns[0].obstacles.iter().map(|o| (o.start, o.lane)).collect();
+        assert_eq!(walls, vec![(0.0, 1), (0.0, 4), (10.0, 1), (10.0, 4)]);
+    }
+
+    #[test]
+    fn comments_and_multiple_patterns() {
+        let patterns = parse_patterns(
+            "# header\npattern a # trailing\n wall 0 1 0\n\npattern b\n wall 0 1 1\n",
+            None,
+        ).unwrap();
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[1].name, "b");
+    }
+
+    #[test]
+    fn error_unknown_directive() {
+        let err = parse_error("pattern p\n   wal 0 1 0\n");
+        assert_eq!((err.line, err.column), (2, 4));
+        assert!(err.message.contains("unknown directive"));
+    }
+
+    #[test]
+    fn error_points_at_bad_number() {
+        let err = parse_error("pattern p\nwall 0 x 0\n");
+        assert_eq!((err.line, err.column), (2, 8));
+        assert_eq!(err.to_string(), "<pattern>:2:8: expected a number, found `x`");
+    }
+
+    #[test]
+    fn error_missing_argument() {
+        let err = parse_error("pattern p\nwall 0 1\n");
+        assert_eq!((err.line, err.column), (2, 9));
+    }
+
+    #[test]
+    fn error_unclosed_repeat() {
+        let err = parse_error("pattern p\n  repeat 2\n  wall 0 1 0\n");
+        assert_eq!((err.line, err.column), (2, 3));
+    }
+
+    #[test]
+    fn error_end_without_repeat() {
+        let err = parse_error("pattern p\nend\n");
+        assert_eq!((err.line, err.column), (2, 1));
+    }
+
+    #[test]
+    fn error_wall_outside_pattern() {
+        let err = parse_error("wall 0 1 0\n");
+        assert_eq!((err.line, err.column), (1, 1));
+    }
+
+    #[test]
+    fn lanes_wrap_walls() {
+        let patterns = parse_patterns("pattern p\nlanes 4\nrepeat 6 lane 1\n  wall 0 1 0\nend\n", None).unwrap();
+        let lanes: Vec<_> = patterns[0].obstacles.iter().map(|o| o.lane).collect();
+        assert_eq!(patterns[0].lanes, 4);
+        assert_eq!(lanes, vec![0, 1, 2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn wall_options() {
+        let patterns = parse_patterns(
+            "pattern p\nwall 0 1 2 speed 1.5 slide -2 1.5\nwall 0 1 3 spin 0.25 pulse 0.5 2 low\nwall 0 1 4 high\nwall 0 1 5\n",
+            None,
+        ).unwrap();
+        let obstacles = &patterns[0].obstacles;
+        let heights: Vec<_> = obstacles.iter().map(|o| o.height).collect();
+        assert_eq!(heights, vec![ObstacleHeight::Full, ObstacleHeight::Low, ObstacleHeight::High, ObstacleHeight::Full]);
+        assert_eq!(parse_patterns(&write_patterns(&patterns), None).unwrap(), patterns);
+        assert_eq!(obstacles[0].speed, 1.5);
+        assert_eq!((obstacles[0].motion.slide_lanes, obstacles[0].motion.slide_time), (-2.0, 1.5));
+        assert_eq!(obstacles[1].motion.flags(), ObstacleMotion::SPIN | ObstacleMotion::PULSE);
+        assert!(obstacles[2].motion.is_static());
+        assert_eq!(obstacles[2].speed, 1.0);
+    }
+
+    #[test]
+    fn power_ups_repeat_and_rotate() {
+        let patterns = parse_patterns(
+            "pattern p\nrepeat 2 shift 4 lane 1\n  powerup shield 2 5\nend\nrotate 1\npowerup wide 1 0\n",
+            None,
+        ).unwrap();
+        let power_ups: Vec<_> = patterns[0].power_ups.iter().map(|p| (p.kind, p.distance, p.lane)).collect();
+        assert_eq!(power_ups, vec![
+            (PowerUpKind::Shield, 2.0, 0),
+            (PowerUpKind::Shield, 6.0, 1),
+            (PowerUpKind::WideGap, 1.0, 0),
+        ]);
+        let err = parse_error("pattern p\npowerup speed 1 0\n");
+        assert_eq!((err.line, err.column), (2, 9));
+        let err = parse_error("pattern p\npowerup slow 1\n");
+        assert_eq!((err.line, err.column), (2, 15));
+    }
+
+    #[test]
+    fn error_bad_wall_option() {
+        let err = parse_error("pattern p\nwall 0 1 0 wobble 1\n");
+        assert_eq!((err.line, err.column), (2, 12));
+        let err = parse_error("pattern p\nwall 0 1 0 slide 1\n");
+        assert_eq!((err.line, err.column), (2, 12));
+        let err = parse_error("pattern p\nwall 0 1 0 speed 0\n");
+        assert_eq!((err.line, err.column), (2, 18));
+    }
+
+    #[test]
+    fn error_lanes_after_walls() {
+        let err = parse_error("pattern p\nwall 0 1 0\nlanes 5\n");
+        assert_eq!((err.line, err.column), (3, 1));
+        let err = parse_error("pattern p\nlanes 3\n");
+        assert_eq!((err.line, err.column), (2, 7));
+    }
+
+    #[test]
+    fn error_duplicate_name() {
+        let err = parse_error("pattern p\nwall 0 1 0\npattern p\n");
+        assert_eq!((err.line, err.column), (3, 9));
+    }
+
+    #[test]
+    fn error_duplicate_name_in_another_file() {
+        let dir = std::env::temp_dir().join(format!("duplicate_patterns_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.pattern"), "pattern p\nwall 0 1 0\n").unwrap();
+        fs::write(dir.join("b.pattern"), "pattern q\nwall 0 1 0\n  pattern p\n").unwrap();
+        let result = load_patterns_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let Err(PatternLoadError::Parse(err)) = result else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(err.path, Some(dir.join("b.pattern")));
+        assert_eq!((err.line, err.column), (3, 11));
+        assert!(err.message.contains("another file"));
+    }
+}
@@ -0,0 +1,82 @@
+use crate::input::MAX_PLAYERS;
+
+use super::collision::Arc;
+use super::{ActivePowerUps, Game, PlayerJump};
+
+/// Hop of the player to another ring, animated over `Game::RING_HOP_DURATION`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RingHop {
+    /// Radius the hop started at, between two rings if it cut another hop short.
+    from: f32,
+    progress: f32,
+}
+
+/// One of the players on the arena. In a versus run each one plays with
+/// their own keys and collides on their own, see `Game::set_player_count`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Player {
+    /// Angle relative to the arena, in radians.
+    pub angle: f32,
+    /// Angular width of the hitbox, in radians.
+    pub width: f32,
+    pub jump: PlayerJump,
+    /// Ring the player is on or hopping to, 0 is the innermost, see `Stage::rings`.
+    pub ring: usize,
+    ring_hop: Option<RingHop>,
+    pub power_ups: ActivePowerUps,
+    /// False once the player hit a wall.
+    pub alive: bool,
+}
+
+impl Player {
+    /// Material of each player in main_scene.wgsl.
+    pub const MATERIALS: [u32; MAX_PLAYERS] = [1, 8];
+
+    pub fn new(angle: f32) -> Self {
+        Self {
+            angle,
+            width: Game::PLAYER_WIDTH,
+            jump: PlayerJump::default(),
+            ring: 0,
+            ring_hop: None,
+            power_ups: ActivePowerUps::default(),
+            alive: true,
+        }
+    }
+
+    /// Distance from the center, between two rings during a hop.
+    pub fn radius(&self) -> f32 {
+        let to = Game::ring_radius(self.ring);
+        match self.ring_hop {
+            Some(hop) => {
+                let t = hop.progress * hop.progress * (3.0 - 2.0 * hop.progress);
+                hop.from + (to - hop.from) * t
+            }
+            None => to,
+        }
+    }
+
+    pub(super) fn arc(&self) -> Arc {
+        Arc::centered(self.angle, self.width)
+    }
+
+    /// Up hops to the next ring outwards and Down to the next one inwards,
+    /// up to `rings`. A hop can be turned around halfway.
+    pub(super) fn update_ring(&mut self, dt: f32, up: bool, down: bool, rings: usize) {
+        if let Some(hop) = &mut self.ring_hop {
+            hop.progress = (hop.progress + dt / Game::RING_HOP_DURATION).min(1.0);
+            if hop.progress >= 1.0 {
+                self.ring_hop = None;
+            }
+        }
+        let ring = match (up, down) {
+            (true, false) => (self.ring + 1).min(rings - 1),
+            (false, true) => self.ring.saturating_sub(1),
+            _ => self.ring,
+        };
+        if ring != self.ring {
+            self.ring_hop = Some(RingHop { from: self.radius(), progress: 0.0 });
+            self.ring = ring;
+        }
+    }
+}
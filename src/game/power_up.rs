@@ -0,0 +1,129 @@
+use super::Game;
+
+/// What a power-up does once collected, see `ActivePowerUps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerUpKind {
+    /// Absorbs the next collision.
+    Shield,
+    /// Slows the walls, the spawner and the survival time down for
+    /// `SLOW_TIME_DURATION`, and the level timeline unless music clocks it.
+    SlowTime,
+    /// Narrows the player's hitbox for `WIDE_GAP_DURATION`, so every gap is wider.
+    WideGap,
+}
+
+impl PowerUpKind {
+    pub const ALL: [PowerUpKind; 3] = [PowerUpKind::Shield, PowerUpKind::SlowTime, PowerUpKind::WideGap];
+
+    /// Name in pattern files.
+    pub fn name(self) -> &'static str {
+        match self {
+            PowerUpKind::Shield => "shield",
+            PowerUpKind::SlowTime => "slow",
+            PowerUpKind::WideGap => "wide",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Material of the power-up in main_scene.wgsl, after the obstacle materials.
+    pub fn material(self) -> u32 {
+        match self {
+            PowerUpKind::Shield => 5,
+            PowerUpKind::SlowTime => 6,
+            PowerUpKind::WideGap => 7,
+        }
+    }
+}
+
+/// An optional pickup in the middle of a lane, moving towards the center
+/// like the walls. It is collected if the player is in its lane when it
+/// reaches the player's ring, and gone either way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+    /// Distance from the center.
+    pub distance: f32,
+    pub lane: usize,
+}
+
+impl PowerUp {
+    pub fn new(kind: PowerUpKind, distance: f32, lane: usize) -> Self {
+        Self { kind, distance, lane }
+    }
+
+    pub fn update(&mut self, dt: f32, obstacle_speed: f32) {
+        self.distance -= obstacle_speed * dt;
+    }
+}
+
+/// Collected power-ups that are still in effect.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ActivePowerUps {
+    pub shield: bool,
+    /// Seconds of slow time left.
+    pub slow_time: f32,
+    /// Seconds of the narrow hitbox left.
+    pub wide_gap: f32,
+}
+
+impl ActivePowerUps {
+    /// Seconds a slow-time power-up lasts, collecting another one starts over.
+    pub const SLOW_TIME_DURATION: f32 = 5.0;
+    /// Speed of the walls and the spawner during slow time.
+    pub const SLOW_TIME_FACTOR: f32 = 0.6;
+    pub const WIDE_GAP_DURATION: f32 = 6.0;
+    /// Width of the player's hitbox while the gaps are widened.
+    pub const WIDE_GAP_FACTOR: f32 = 0.5;
+
+    pub fn collect(&mut self, kind: PowerUpKind) {
+        match kind {
+            PowerUpKind::Shield => self.shield = true,
+            PowerUpKind::SlowTime => self.slow_time = Self::SLOW_TIME_DURATION,
+            PowerUpKind::WideGap => self.wide_gap = Self::WIDE_GAP_DURATION,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.slow_time = (self.slow_time - dt).max(0.0);
+        self.wide_gap = (self.wide_gap - dt).max(0.0);
+    }
+
+    /// Multiplier on the time the walls, power-ups and the spawner move by.
+    pub fn world_speed(&self) -> f32 {
+        if self.slow_time > 0.0 { Self::SLOW_TIME_FACTOR } else { 1.0 }
+    }
+
+    /// Angular width of the player's hitbox.
+    pub fn player_width(&self) -> f32 {
+        if self.wide_gap > 0.0 { Game::PLAYER_WIDTH * Self::WIDE_GAP_FACTOR } else { Game::PLAYER_WIDTH }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_run_out() {
+        let mut active = ActivePowerUps::default();
+        assert_eq!((active.world_speed(), active.player_width()), (1.0, Game::PLAYER_WIDTH));
+        for kind in PowerUpKind::ALL {
+            assert_eq!(PowerUpKind::from_name(kind.name()), Some(kind));
+            active.collect(kind);
+        }
+        assert!(active.shield);
+        assert_eq!(active.world_speed(), ActivePowerUps::SLOW_TIME_FACTOR);
+        assert!(active.player_width() < Game::PLAYER_WIDTH);
+
+        active.update(ActivePowerUps::SLOW_TIME_DURATION);
+        assert_eq!(active.world_speed(), 1.0);
+        assert!(active.player_width() < Game::PLAYER_WIDTH);
+        active.update(ActivePowerUps::WIDE_GAP_DURATION);
+        assert_eq!(active.player_width(), Game::PLAYER_WIDTH);
+        // the shield stays until it is used
+        assert!(active.shield);
+    }
+}
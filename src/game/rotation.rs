@@ -0,0 +1,184 @@
+use std::f32::consts::TAU;
+
+use crate::common::rng::Rng;
+
+/// How the arena of a stage turns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RotationStyle {
+    /// Multiplier on the rotation speed of the difficulty schedule.
+    pub speed: f32,
+    /// Seconds between random reversals, `None` turns only at new difficulty steps.
+    pub reversal_interval: Option<(f32, f32)>,
+    /// Seconds between jolts, `None` never jolts.
+    pub jolt_interval: Option<(f32, f32)>,
+}
+
+impl RotationStyle {
+    pub const DEFAULT: RotationStyle = RotationStyle {
+        speed: 1.0,
+        reversal_interval: Some((4.0, 10.0)),
+        jolt_interval: Some((5.0, 12.0)),
+    };
+}
+
+/// Rotation of the whole arena (lanes, obstacles and player) around the center.
+///
+/// Gameplay happens in the arena frame, where this rotation doesn't exist:
+/// add `angle` to get from arena angles to world angles for rendering. The
+/// arena turns at the difficulty's speed, reverses now and then and
+/// occasionally jolts ahead for a moment, as set by its `RotationStyle`. The
+/// timing is drawn from the run's seed, so replays see the same rotation.
+#[derive(Debug, Clone)]
+pub(crate) struct ArenaRotation {
+    /// World angle of the arena, in `[0, TAU)`.
+    angle: f32,
+    /// Current direction, eases between -1 and 1 when reversing.
+    direction: f32,
+    target_direction: f32,
+    /// Seconds until the next random reversal.
+    next_reversal: f32,
+    /// Seconds until the next jolt.
+    next_jolt: f32,
+    /// Seconds left of the current jolt.
+    jolt_left: f32,
+    style: RotationStyle,
+    rng: Rng,
+}
+
+impl ArenaRotation {
+    /// Seconds it takes to turn around.
+    const REVERSAL_TIME: f32 = 0.4;
+    const JOLT_DURATION: f32 = 0.15;
+    /// Rotation speed multiplier during a jolt.
+    const JOLT_BOOST: f32 = 6.0;
+
+    pub fn new(seed: u64, style: RotationStyle) -> Self {
+        // don't share the sequence with the spawner, which is seeded the same
+        let mut rng = Rng::new(seed ^ 0x5bd1_e995_a3c6_4f2b);
+        let next_reversal = Self::next_interval(&mut rng, style.reversal_interval);
+        let next_jolt = Self::next_interval(&mut rng, style.jolt_interval);
+        Self {
+            angle: 0.0,
+            direction: 1.0,
+            target_direction: 1.0,
+            next_reversal,
+            next_jolt,
+            jolt_left: 0.0,
+            style,
+            rng,
+        }
+    }
+
+    /// Seconds until the next random event, never if there is no interval.
+    fn next_interval(rng: &mut Rng, interval: Option<(f32, f32)>) -> f32 {
+        match interval {
+            Some((min, max)) => rng.range_f32(min, max),
+            None => f32::INFINITY,
+        }
+    }
+
+    pub fn angle(&self) -> f32 {
+        self.angle
+    }
+
+    /// Turns the rotation around, easing through standstill.
+    pub fn reverse(&mut self) {
+        self.target_direction = -self.target_direction;
+    }
+
+    /// Advances the rotation by `dt` seconds at `speed` radians per second,
+    /// times the speed of the style.
+    pub fn update(&mut self, dt: f32, speed: f32) {
+        let speed = speed * self.style.speed;
+        self.next_reversal -= dt;
+        if self.next_reversal <= 0.0 {
+            self.reverse();
+            self.next_reversal += Self::next_interval(&mut self.rng, self.style.reversal_interval);
+        }
+        self.next_jolt -= dt;
+        if self.next_jolt <= 0.0 {
+            self.jolt_left = Self::JOLT_DURATION;
+            self.next_jolt += Self::next_interval(&mut self.rng, self.style.jolt_interval);
+        }
+
+        let max_change = 2.0 * dt / Self::REVERSAL_TIME;
+        self.direction += (self.target_direction - self.direction).clamp(-max_change, max_change);
+
+        let mut step = speed * self.direction * dt;
+        if self.jolt_left > 0.0 {
+            step += speed * self.direction * (Self::JOLT_BOOST - 1.0) * dt.min(self.jolt_left);
+            self.jolt_left -= dt;
+        }
+        self.angle = (self.angle + step).rem_euclid(TAU);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(rotation: &mut ArenaRotation, seconds: f32, dt: f32) -> Vec<f32> {
+        (0..(seconds / dt) as usize)
+            .map(|_| {
+                rotation.update(dt, 1.0);
+                rotation.angle()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_rotation() {
+        let a = run(&mut ArenaRotation::new(3, RotationStyle::DEFAULT), 60.0, 1.0 / 60.0);
+        let b = run(&mut ArenaRotation::new(3, RotationStyle::DEFAULT), 60.0, 1.0 / 60.0);
+        assert_eq!(a, b);
+        assert_ne!(a, run(&mut ArenaRotation::new(4, RotationStyle::DEFAULT), 60.0, 1.0 / 60.0));
+    }
+
+    #[test]
+    fn reverses_smoothly_and_jolts() {
+        let dt = 1.0 / 60.0;
+        let mut rotation = ArenaRotation::new(1, RotationStyle::DEFAULT);
+        let mut previous_step: f32 = 1.0 * dt;
+        let mut previous = rotation.angle();
+        let (mut reversals, mut jolts) = (0, 0);
+        let mut was_jolting = false;
+        let mut previous_direction = 1.0;
+        for _ in 0..(60.0 / dt) as usize {
+            rotation.update(dt, 1.0);
+            let mut step = rotation.angle() - previous;
+            if step.abs() > TAU / 2.0 {
+                step -= TAU.copysign(step);
+            }
+            previous = rotation.angle();
+            if rotation.direction.signum() != previous_direction {
+                reversals += 1;
+                previous_direction = rotation.direction.signum();
+            }
+            if rotation.jolt_left > 0.0 {
+                jolts += !was_jolting as usize;
+            } else {
+                // outside of jolts the speed never jumps, a reversal passes through zero
+                if !was_jolting {
+                    assert!((step - previous_step).abs() <= 2.0 * dt * dt / ArenaRotation::REVERSAL_TIME + 1e-4);
+                }
+                previous_step = step;
+            }
+            was_jolting = rotation.jolt_left > 0.0;
+        }
+        assert!(reversals >= 4, "{} reversals", reversals);
+        assert!(jolts >= 4, "{} jolts", jolts);
+    }
+
+    #[test]
+    fn style_sets_speed_and_events() {
+        let calm = RotationStyle { speed: 2.0, reversal_interval: None, jolt_interval: None };
+        let mut rotation = ArenaRotation::new(1, calm);
+        let dt = 1.0 / 60.0;
+        for _ in 0..(60.0 / dt) as usize {
+            rotation.update(dt, 0.05);
+            assert_eq!((rotation.direction, rotation.jolt_left), (1.0, 0.0));
+        }
+        // 60 seconds at 0.05 * 2 radians per second
+        assert!((rotation.angle() - 6.0).abs() < 1e-2, "{}", rotation.angle());
+    }
+}
@@ -0,0 +1,85 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+/// Angular layout of the lanes around the center.
+///
+/// Lane 0 is centered at `FRAC_PI_2` and the lanes follow counter-clockwise,
+/// each `TAU / sides` wide. `sides` is fractional while the lane count morphs:
+/// the last lane then covers whatever is left of the circle, so it grows from
+/// or shrinks to zero width while the other lanes make room.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneShape {
+    /// Number of lanes, including one that is currently growing or shrinking.
+    pub lane_count: usize,
+    /// Number of sides of the polygon, in `[lane_count - 1, lane_count]`.
+    pub sides: f32,
+}
+
+impl LaneShape {
+    pub fn regular(lane_count: usize) -> Self {
+        Self { lane_count, sides: lane_count as f32 }
+    }
+
+    /// Angular width of every lane but the last.
+    pub fn lane_width(&self) -> f32 {
+        TAU / self.sides
+    }
+
+    /// Angle where lane 0 starts.
+    fn first_lane_start(&self) -> f32 {
+        FRAC_PI_2 - self.lane_width() / 2.0
+    }
+
+    /// Start and end angle of `lane`, the end is greater than the start.
+    pub fn lane_bounds(&self, lane: usize) -> (f32, f32) {
+        let start = self.first_lane_start() + lane as f32 * self.lane_width();
+        if lane + 1 == self.lane_count {
+            (start, self.first_lane_start() + TAU)
+        } else {
+            (start, start + self.lane_width())
+        }
+    }
+
+    pub fn lane_center(&self, lane: usize) -> f32 {
+        let (start, end) = self.lane_bounds(lane);
+        (start + end) / 2.0
+    }
+
+    pub fn lane_at_angle(&self, angle: f32) -> usize {
+        let relative = (angle - self.first_lane_start()).rem_euclid(TAU);
+        ((relative / self.lane_width()) as usize).min(self.lane_count - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hexagon_matches_original_layout() {
+        let shape = LaneShape::regular(6);
+        // lane k used to cover [60 * (k + 1), 60 * (k + 2)) degrees
+        for lane in 0..6 {
+            let start = (60.0 * (lane + 1) as f32 + 1.0).to_radians();
+            let end = (60.0 * (lane + 2) as f32 - 1.0).to_radians();
+            assert_eq!(shape.lane_at_angle(start), lane);
+            assert_eq!(shape.lane_at_angle(end), lane);
+            assert_eq!(shape.lane_at_angle(end - TAU), lane);
+        }
+    }
+
+    #[test]
+    fn shrinking_lane_covers_the_rest() {
+        let shape = LaneShape { lane_count: 6, sides: 5.5 };
+        let (start, end) = shape.lane_bounds(5);
+        assert!((end - start - (TAU - 5.0 * shape.lane_width())).abs() < 1e-5);
+        assert_eq!(shape.lane_at_angle(shape.lane_center(5)), 5);
+        assert_eq!(shape.lane_at_angle(shape.lane_center(4)), 4);
+
+        let collapsed = LaneShape { lane_count: 6, sides: 5.0 };
+        let (start, end) = collapsed.lane_bounds(5);
+        assert!((end - start).abs() < 1e-5);
+        for lane in 0..5 {
+            assert_eq!(collapsed.lane_at_angle(collapsed.lane_center(lane)), lane);
+        }
+    }
+}
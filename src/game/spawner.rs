@@ -0,0 +1,342 @@
+use crate::common::rng::Rng;
+
+use super::{Difficulty, Game, Lane, Obstacle, Pattern, PowerUp, PowerUpKind};
+
+pub(crate) trait SpawnerInterface {
+    /// Returns whether a new pattern started.
+    fn update(&mut self, dt: f32, lanes: &mut [Lane], difficulty: &Difficulty) -> bool;
+    /// Name of the most recently spawned pattern.
+    fn pattern_name(&self) -> Option<&str>;
+    /// Starts over as if newly created with `seed`, for a new run.
+    fn reset(&mut self, seed: u64);
+    /// Spawns the pattern called `name` right away, without transforms. The
+    /// next pattern follows after its duration. Returns false if there is no
+    /// such pattern for the current lane count.
+    fn spawn_named(&mut self, name: &str, lanes: &mut [Lane]) -> bool;
+}
+
+fn find_pattern(patterns: &[Pattern], name: &str, lane_count: usize) -> Option<usize> {
+    patterns.iter().position(|p| p.name == name && p.lanes == lane_count)
+}
+
+/// Advances the pattern clock. Pattern durations are given at the base obstacle speed.
+fn pattern_time_step(dt: f32, difficulty: &Difficulty) -> f32 {
+    dt * difficulty.obstacle_speed / Game::OBSTACLE_SPEED * difficulty.density
+}
+
+pub(super) fn spawn_pattern(pattern: &Pattern, lanes: &mut [Lane]) {
+    for obstacle in &pattern.obstacles {
+        let lane = obstacle.lane % lanes.len();
+        lanes[lane].obstacles.push(Obstacle {
+            start: Game::OBSTACLE_SPAWN_DISTANCE + obstacle.start,
+            end: Game::OBSTACLE_SPAWN_DISTANCE + obstacle.end,
+            lane,
+            age: 0.0,
+            ..*obstacle
+        });
+    }
+    for power_up in &pattern.power_ups {
+        let lane = power_up.lane % lanes.len();
+        lanes[lane].power_ups.push(PowerUp {
+            distance: Game::OBSTACLE_SPAWN_DISTANCE + power_up.distance,
+            lane,
+            ..*power_up
+        });
+    }
+}
+
+/// Indices of the patterns made for `lane_count` lanes that fit the current
+/// difficulty step, or all patterns for `lane_count` lanes if none fit.
+fn eligible_patterns(patterns: &[Pattern], difficulty: &Difficulty, lane_count: usize) -> Vec<usize> {
+    let for_lanes: Vec<usize> = (0..patterns.len())
+        .filter(|&i| patterns[i].lanes == lane_count)
+        .collect();
+    let eligible: Vec<usize> = for_lanes.iter()
+        .copied()
+        .filter(|&i| difficulty.pattern_levels.contains(&patterns[i].difficulty))
+        .collect();
+    if eligible.is_empty() {
+        for_lanes
+    } else {
+        eligible
+    }
+}
+
+/// Lane counts that at least one of `patterns` is made for.
+pub(crate) fn supported_lane_counts(patterns: &[Pattern]) -> Vec<usize> {
+    let mut counts: Vec<usize> = patterns.iter().map(|p| p.lanes).collect();
+    counts.sort();
+    counts.dedup();
+    counts
+}
+
+/// Random transforms applied to each spawned pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PatternTransforms {
+    pub rotate: bool,
+    pub mirror: bool,
+    /// Range of the stretch factor along the direction of travel.
+    pub stretch_min: f32,
+    pub stretch_max: f32,
+}
+
+impl PatternTransforms {
+    pub const ALL: Self = Self { rotate: true, mirror: true, stretch_min: 0.85, stretch_max: 1.2 };
+}
+
+/// Picks patterns with a seeded random number generator. The same seed gives
+/// the same sequence of patterns and transforms. Patterns are drawn from a
+/// shuffle bag: every eligible pattern once per round in shuffled order, and
+/// never the same pattern twice in a row.
+pub(crate) struct RandomSpawner {
+    patterns: Vec<Pattern>,
+    rng: Rng,
+    /// Separate from `rng`, so that power-ups don't change the patterns of a seed.
+    power_up_rng: Rng,
+    transforms: PatternTransforms,
+    bag: Vec<usize>,
+    last_pattern: Option<usize>,
+    current_name: Option<String>,
+    current_duration: f32,
+    current_pattern_time: f32,
+}
+
+impl RandomSpawner {
+    pub fn new(patterns: Vec<Pattern>, seed: u64, transforms: PatternTransforms) -> Self {
+        assert!(!patterns.is_empty(), "RandomSpawner needs at least one pattern");
+        Self {
+            patterns,
+            rng: Rng::new(seed),
+            power_up_rng: Self::power_up_rng(seed),
+            transforms,
+            bag: vec![],
+            last_pattern: None,
+            current_name: None,
+            current_duration: 0.0,
+            current_pattern_time: 1e20,
+        }
+    }
+
+    /// Chance that a pattern without power-ups of its own gets a random one.
+    pub const POWER_UP_CHANCE: f32 = 0.2;
+
+    fn power_up_rng(seed: u64) -> Rng {
+        Rng::new(seed ^ 0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Power-ups only come with patterns from the second difficulty step
+    /// on: maybe one of a random kind, in a random lane, somewhere along
+    /// the pattern.
+    fn add_power_up(&mut self, pattern: &mut Pattern, difficulty: &Difficulty) {
+        if difficulty.step == 0 || !pattern.power_ups.is_empty() || !self.power_up_rng.chance(Self::POWER_UP_CHANCE) {
+            return;
+        }
+        let kind = PowerUpKind::ALL[self.power_up_rng.below(PowerUpKind::ALL.len())];
+        let lane = self.power_up_rng.below(pattern.lanes);
+        let length = pattern.obstacles.iter().map(|obstacle| obstacle.end).fold(0.0, f32::max);
+        let distance = self.power_up_rng.range_f32(0.0, length.max(1.0));
+        pattern.add_power_up(PowerUp::new(kind, distance, lane));
+    }
+
+    fn pick_pattern(&mut self, difficulty: &Difficulty, lane_count: usize) -> Option<usize> {
+        let eligible = eligible_patterns(&self.patterns, difficulty, lane_count);
+        if eligible.is_empty() {
+            return None;
+        }
+        // patterns that don't fit the difficulty step anymore are dropped from the bag
+        self.bag.retain(|i| eligible.contains(i));
+        if self.bag.is_empty() {
+            self.bag = eligible;
+            self.rng.shuffle(&mut self.bag);
+            // the bag is drawn from the back, so the last element comes next
+            let last = self.bag.len() - 1;
+            if last > 0 && Some(self.bag[last]) == self.last_pattern {
+                self.bag.swap(0, last);
+            }
+        }
+        self.bag.pop()
+    }
+
+    fn next_pattern(&mut self, difficulty: &Difficulty, lane_count: usize) -> Option<Pattern> {
+        let index = self.pick_pattern(difficulty, lane_count)?;
+        self.last_pattern = Some(index);
+
+        let mut pattern = self.patterns[index].clone();
+        if self.transforms.rotate {
+            pattern.rotate(self.rng.below(pattern.lanes) as i32);
+        }
+        if self.transforms.mirror && self.rng.chance(0.5) {
+            pattern.mirror();
+        }
+        if self.transforms.stretch_max > self.transforms.stretch_min {
+            pattern.stretch(self.rng.range_f32(self.transforms.stretch_min, self.transforms.stretch_max));
+        }
+        Some(pattern)
+    }
+}
+
+impl SpawnerInterface for RandomSpawner {
+    fn update(&mut self, dt: f32, lanes: &mut [Lane], difficulty: &Difficulty) -> bool {
+        self.current_pattern_time += pattern_time_step(dt, difficulty);
+        if self.current_pattern_time > self.current_duration {
+            self.current_pattern_time = 0.0;
+            let Some(mut pattern) = self.next_pattern(difficulty, lanes.len()) else {
+                return false;
+            };
+            self.add_power_up(&mut pattern, difficulty);
+            println!("Running Pattern: {}", pattern.name);
+            println!("Duration: {}", pattern.duration);
+            self.current_duration = pattern.duration;
+            spawn_pattern(&pattern, lanes);
+            self.current_name = Some(pattern.name);
+            return true;
+        }
+        false
+    }
+
+    fn pattern_name(&self) -> Option<&str> {
+        self.current_name.as_deref()
+    }
+
+    fn reset(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+        self.power_up_rng = Self::power_up_rng(seed);
+        self.bag.clear();
+        self.last_pattern = None;
+        self.current_name = None;
+        self.current_duration = 0.0;
+        self.current_pattern_time = 1e20;
+    }
+
+    fn spawn_named(&mut self, name: &str, lanes: &mut [Lane]) -> bool {
+        let Some(index) = find_pattern(&self.patterns, name, lanes.len()) else {
+            return false;
+        };
+        let pattern = &self.patterns[index];
+        println!("Running Pattern: {} (timeline)", pattern.name);
+        spawn_pattern(pattern, lanes);
+        self.last_pattern = Some(index);
+        self.current_name = Some(pattern.name.clone());
+        self.current_duration = pattern.duration;
+        self.current_pattern_time = 0.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::difficulty::DifficultySchedule;
+
+    const NO_TRANSFORMS: PatternTransforms =
+        PatternTransforms { rotate: false, mirror: false, stretch_min: 1.0, stretch_max: 1.0 };
+
+    fn spawn_sequence(spawner: &mut RandomSpawner, count: usize) -> (Vec<usize>, Vec<Obstacle>) {
+        let difficulty = DifficultySchedule::default_schedule().at(1000.0);
+        let mut lanes: Vec<Lane> = (0..6).map(|_| Lane { obstacles: vec![], power_ups: vec![] }).collect();
+        let mut picked = vec![];
+        for _ in 0..count {
+            // one step longer than any pattern forces the next spawn
+            spawner.update(1000.0, &mut lanes, &difficulty);
+            picked.push(spawner.last_pattern.unwrap());
+        }
+        let obstacles = lanes.into_iter().flat_map(|lane| lane.obstacles).collect();
+        (picked, obstacles)
+    }
+
+    fn all_patterns() -> Vec<Pattern> {
+        let mut patterns = Game::load_patterns();
+        for pattern in &mut patterns {
+            pattern.difficulty = 2;
+        }
+        patterns
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = RandomSpawner::new(all_patterns(), 42, PatternTransforms::ALL);
+        let mut b = RandomSpawner::new(all_patterns(), 42, PatternTransforms::ALL);
+        assert_eq!(spawn_sequence(&mut a, 50), spawn_sequence(&mut b, 50));
+    }
+
+    #[test]
+    fn different_seed_different_sequence() {
+        let mut a = RandomSpawner::new(all_patterns(), 1, PatternTransforms::ALL);
+        let mut b = RandomSpawner::new(all_patterns(), 2, PatternTransforms::ALL);
+        assert_ne!(spawn_sequence(&mut a, 50), spawn_sequence(&mut b, 50));
+    }
+
+    #[test]
+    fn shuffle_bag_never_repeats_back_to_back() {
+        // spawn_sequence plays on six lanes
+        let six_lanes: Vec<usize> = all_patterns()
+            .iter()
+            .enumerate()
+            .filter(|(_, pattern)| pattern.lanes == 6)
+            .map(|(i, _)| i)
+            .collect();
+        for seed in 0..20 {
+            let mut spawner = RandomSpawner::new(all_patterns(), seed, NO_TRANSFORMS);
+            let (picked, _) = spawn_sequence(&mut spawner, 60);
+            assert!(picked.windows(2).all(|w| w[0] != w[1]), "seed {}: {:?}", seed, picked);
+            // every round of draws contains each pattern once
+            for round in picked.chunks_exact(six_lanes.len()) {
+                let mut round = round.to_vec();
+                round.sort();
+                assert_eq!(round, six_lanes);
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_keep_lanes_valid() {
+        let mut spawner = RandomSpawner::new(all_patterns(), 7, PatternTransforms::ALL);
+        let (_, obstacles) = spawn_sequence(&mut spawner, 100);
+        assert!(obstacles.iter().all(|o| o.lane < 6 && o.end > o.start));
+    }
+
+    #[test]
+    fn power_ups_come_with_some_patterns_from_the_second_stage() {
+        let schedule = DifficultySchedule::default_schedule();
+        let spawn = |seed: u64, difficulty: &Difficulty| {
+            let mut patterns = all_patterns();
+            // the built-in slow time would always show up
+            patterns.iter_mut().for_each(|pattern| pattern.power_ups.clear());
+            let mut spawner = RandomSpawner::new(patterns, seed, PatternTransforms::ALL);
+            let mut lanes: Vec<Lane> = (0..6).map(|_| Lane { obstacles: vec![], power_ups: vec![] }).collect();
+            for _ in 0..50 {
+                spawner.update(1000.0, &mut lanes, difficulty);
+            }
+            (spawner.last_pattern, lanes.into_iter().flat_map(|lane| lane.power_ups).collect::<Vec<_>>())
+        };
+        assert!(spawn(5, &schedule.at(0.0)).1.is_empty());
+
+        let (last_pattern, power_ups) = spawn(5, &schedule.at(1000.0));
+        assert!(!power_ups.is_empty() && power_ups.len() < 50);
+        assert!(power_ups.iter().all(|p| p.lane < 6 && p.distance >= Game::OBSTACLE_SPAWN_DISTANCE));
+        assert_eq!(spawn(5, &schedule.at(1000.0)), (last_pattern, power_ups));
+        // the patterns are the same as without power-ups
+        let mut spawner = RandomSpawner::new(all_patterns(), 5, PatternTransforms::ALL);
+        assert_eq!(spawn_sequence(&mut spawner, 50).0.last().copied(), last_pattern);
+    }
+
+    #[test]
+    fn only_spawns_patterns_for_the_lane_count() {
+        let difficulty = DifficultySchedule::default_schedule().at(0.0);
+        for lane_count in [4, 5] {
+            let mut spawner = RandomSpawner::new(all_patterns(), 3, PatternTransforms::ALL);
+            let mut lanes: Vec<Lane> = (0..lane_count).map(|_| Lane { obstacles: vec![], power_ups: vec![] }).collect();
+            for _ in 0..10 {
+                spawner.update(1000.0, &mut lanes, &difficulty);
+                let pattern = &spawner.patterns[spawner.last_pattern.unwrap()];
+                assert_eq!(pattern.lanes, lane_count);
+            }
+        }
+
+        // nothing is spawned if no pattern fits
+        let mut spawner = RandomSpawner::new(all_patterns(), 3, PatternTransforms::ALL);
+        let mut lanes: Vec<Lane> = (0..8).map(|_| Lane { obstacles: vec![], power_ups: vec![] }).collect();
+        spawner.update(1000.0, &mut lanes, &difficulty);
+        assert!(lanes.iter().all(|lane| lane.obstacles.is_empty()));
+    }
+}
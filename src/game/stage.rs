@@ -0,0 +1,137 @@
+use super::rotation::RotationStyle;
+
+/// A level of the game, with its own patterns, speed, colors, rotation and
+/// music. The difficulty schedule is the same for every stage, the stage
+/// scales it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    /// Name in files: high-score tables, replays and the music file.
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Names of the patterns the spawner picks from, all patterns if empty.
+    pub patterns: &'static [&'static str],
+    /// Multiplier on the obstacle and player speed of the difficulty schedule.
+    pub speed: f32,
+    /// Color palette a run starts with, see `Game::PALETTE_COUNT`.
+    pub palette: u32,
+    pub rotation: RotationStyle,
+    /// Music file in `audio::MUSIC_DIR`, without the extension.
+    pub music: &'static str,
+    /// Concentric rings the player hops between with Up and Down, up to
+    /// `Game::MAX_RINGS`. With one ring Up and Down jump and duck.
+    pub rings: usize,
+}
+
+const STAGES: [Stage; 4] = [
+    Stage {
+        id: "hexagon",
+        name: "Hexagon",
+        patterns: &["spiral", "rotating_gaps", "pentagon_spiral", "square_gaps", "spinning_gaps", "pulsing_spiral"],
+        speed: 1.0,
+        palette: 0,
+        rotation: RotationStyle::DEFAULT,
+        music: "hexagon",
+        rings: 1,
+    },
+    Stage {
+        id: "hexagoner",
+        name: "Hexagoner",
+        patterns: &[],
+        speed: 1.15,
+        palette: 1,
+        rotation: RotationStyle { speed: 1.3, reversal_interval: Some((3.0, 7.0)), jolt_interval: Some((4.0, 9.0)) },
+        music: "hexagoner",
+        rings: 1,
+    },
+    Stage {
+        id: "hexagonest",
+        name: "Hexagonest",
+        patterns: &[],
+        speed: 1.3,
+        palette: 3,
+        rotation: RotationStyle { speed: 1.6, reversal_interval: Some((2.0, 5.0)), jolt_interval: Some((3.0, 6.0)) },
+        music: "hexagonest",
+        rings: 1,
+    },
+    Stage {
+        id: "rings",
+        name: "Hexagon Rings",
+        patterns: &[],
+        speed: 1.0,
+        palette: 2,
+        rotation: RotationStyle::DEFAULT,
+        music: "hexagon",
+        rings: 3,
+    },
+];
+
+impl Stage {
+    /// Seconds to survive on a stage to unlock the next one.
+    pub const UNLOCK_TIME: f32 = 60.0;
+
+    /// All stages, in the order they unlock.
+    pub fn all() -> &'static [Stage] {
+        &STAGES
+    }
+
+    pub fn first() -> &'static Stage {
+        &STAGES[0]
+    }
+
+    pub fn find(id: &str) -> Option<&'static Stage> {
+        STAGES.iter().find(|stage| stage.id == id)
+    }
+
+    /// The stage with `id`, or the first stage with a warning if there is none.
+    pub fn find_or_first(id: &str) -> &'static Stage {
+        Self::find(id).unwrap_or_else(|| {
+            eprintln!("Unknown stage {}, using {}", id, Self::first().id);
+            Self::first()
+        })
+    }
+
+    /// Stages that can be played: the first one, and each one after a stage
+    /// survived for `UNLOCK_TIME`. `best_time` gives the longest run on a
+    /// stage by id, the high-score tables keep it across sessions.
+    pub fn unlocked(best_time: impl Fn(&str) -> Option<f32>) -> Vec<&'static Stage> {
+        let locked = STAGES
+            .windows(2)
+            .position(|pair| best_time(pair[0].id).is_none_or(|time| time < Self::UNLOCK_TIME))
+            .map_or(STAGES.len(), |i| i + 1);
+        STAGES[..locked].iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::game::pattern_file::{load_patterns_dir, PATTERN_DIR};
+
+    #[test]
+    fn stages_unlock_in_order() {
+        let ids = |stages: Vec<&Stage>| stages.iter().map(|stage| stage.id).collect::<Vec<_>>();
+        assert_eq!(ids(Stage::unlocked(|_| None)), vec!["hexagon"]);
+        assert_eq!(ids(Stage::unlocked(|_| Some(59.9))), vec!["hexagon"]);
+        let best = |id: &str| (id == "hexagon").then_some(60.0);
+        assert_eq!(ids(Stage::unlocked(best)), vec!["hexagon", "hexagoner"]);
+        // a good time on a locked stage doesn't skip the one before it
+        let best = |id: &str| (id == "hexagoner").then_some(100.0);
+        assert_eq!(ids(Stage::unlocked(best)), vec!["hexagon"]);
+        assert_eq!(Stage::unlocked(|_| Some(60.0)).len(), Stage::all().len());
+    }
+
+    #[test]
+    fn stages_refer_to_existing_patterns() {
+        let patterns = load_patterns_dir(Path::new(PATTERN_DIR)).unwrap();
+        for stage in Stage::all() {
+            for name in stage.patterns {
+                assert!(patterns.iter().any(|pattern| pattern.name == *name), "{}: no pattern {}", stage.id, name);
+            }
+            assert!(stage.palette < crate::game::Game::PALETTE_COUNT);
+            assert!((1..=crate::game::Game::MAX_RINGS).contains(&stage.rings));
+            assert_eq!(Stage::find(stage.id), Some(stage));
+        }
+    }
+}
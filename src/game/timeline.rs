@@ -0,0 +1,175 @@
+/// Something that happens on a beat, see `Game::apply_timeline_event`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TimelineEvent {
+    /// Spawns the named pattern right away, random patterns follow after it.
+    SpawnPattern(String),
+    /// Multiplier on the obstacle speed, kept until the next `Speed` event.
+    Speed(f32),
+    /// Turns the arena and camera rotation around.
+    ReverseRotation,
+    /// Zooms the camera in by `scale`, easing back over `beats`.
+    CameraPulse { scale: f32, beats: f32 },
+    /// Fades to another color palette, see `Game::PALETTE_COUNT`.
+    Palette(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TimedEvent {
+    pub beat: f32,
+    pub event: TimelineEvent,
+}
+
+/// Events on a beat grid, played back by stepping time.
+///
+/// `advance` returns the events whose beat was reached, in order. A looping
+/// timeline starts over after `loop_beats`, events right on the loop end
+/// still fire before it wraps.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Timeline {
+    pub bpm: f32,
+    pub loop_beats: Option<f32>,
+    events: Vec<TimedEvent>,
+    /// Current position in beats, within the loop.
+    beat: f32,
+    /// Index of the next event to fire.
+    next: usize,
+}
+
+impl Timeline {
+    pub fn new(bpm: f32, loop_beats: Option<f32>, mut events: Vec<TimedEvent>) -> Self {
+        assert!(bpm > 0.0, "Timeline needs a positive BPM");
+        assert!(loop_beats.is_none_or(|beats| beats > 0.0), "Timeline loop must be longer than zero beats");
+        // stable, events on the same beat keep their order
+        events.sort_by(|a, b| a.beat.partial_cmp(&b.beat).unwrap());
+        Self { bpm, loop_beats, events, beat: 0.0, next: 0 }
+    }
+
+    /// Same behavior as the old fixed camera animation: the rotation turns
+    /// around every 12.5 seconds.
+    pub fn default_timeline() -> Self {
+        let reverse = |beat| TimedEvent { beat, event: TimelineEvent::ReverseRotation };
+        Self::new(120.0, Some(50.0), vec![reverse(25.0), reverse(50.0)])
+    }
+
+    pub fn events(&self) -> &[TimedEvent] {
+        &self.events
+    }
+
+    /// Current position in beats, within the loop.
+    pub fn beat(&self) -> f32 {
+        self.beat
+    }
+
+    pub fn seconds_per_beat(&self) -> f32 {
+        60.0 / self.bpm
+    }
+
+    /// Goes back to beat 0, the events at beat 0 fire on the next `advance`.
+    pub fn reset(&mut self) {
+        self.beat = 0.0;
+        self.next = 0;
+    }
+
+    /// Moves forward by `dt` seconds and returns the events in between.
+    pub fn advance(&mut self, dt: f32) -> Vec<TimelineEvent> {
+        let mut fired = vec![];
+        let mut target = self.beat + dt / self.seconds_per_beat();
+        loop {
+            match self.loop_beats {
+                Some(loop_beats) if target >= loop_beats => {
+                    self.fire_until(&mut fired, |beat| beat <= loop_beats);
+                    target -= loop_beats;
+                    self.beat = 0.0;
+                    self.next = 0;
+                }
+                _ => {
+                    self.fire_until(&mut fired, |beat| beat < target);
+                    self.beat = target;
+                    return fired;
+                }
+            }
+        }
+    }
+
+    fn fire_until(&mut self, fired: &mut Vec<TimelineEvent>, reached: impl Fn(f32) -> bool) {
+        while let Some(timed) = self.events.get(self.next) {
+            if !reached(timed.beat) {
+                break;
+            }
+            fired.push(timed.event.clone());
+            self.next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(beat: f32, scale: f32) -> TimedEvent {
+        TimedEvent { beat, event: TimelineEvent::CameraPulse { scale, beats: 1.0 } }
+    }
+
+    fn scales(events: Vec<TimelineEvent>) -> Vec<f32> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                TimelineEvent::CameraPulse { scale, .. } => scale,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_events_on_their_beat() {
+        // 120 BPM, a beat every half second
+        let mut timeline = Timeline::new(120.0, None, vec![event(2.0, 2.0), event(0.0, 0.0), event(1.0, 1.0)]);
+        assert_eq!(scales(timeline.advance(0.1)), vec![0.0]);
+        assert_eq!(scales(timeline.advance(0.35)), vec![]);
+        assert_eq!(scales(timeline.advance(0.1)), vec![1.0]);
+        assert_eq!(scales(timeline.advance(10.0)), vec![2.0]);
+        assert_eq!(scales(timeline.advance(10.0)), vec![]);
+
+        timeline.reset();
+        assert_eq!(scales(timeline.advance(1.5)), vec![0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn loops_fire_every_event_once_per_loop() {
+        let events = vec![event(0.0, 0.0), event(1.5, 1.0), event(4.0, 2.0)];
+        let mut timeline = Timeline::new(60.0, Some(4.0), events);
+        assert_eq!(scales(timeline.advance(3.9)), vec![0.0, 1.0]);
+        // the end of the loop and the start of the next one
+        assert_eq!(scales(timeline.advance(0.2)), vec![2.0, 0.0]);
+        assert!((timeline.beat - 0.1).abs() < 1e-4);
+        // several loops in one step
+        assert_eq!(scales(timeline.advance(8.0)), vec![1.0, 2.0, 0.0, 1.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn small_steps_match_one_big_step() {
+        let events: Vec<TimedEvent> = (0..16).map(|i| event(i as f32 * 0.75, i as f32)).collect();
+        let mut stepped = Timeline::new(140.0, Some(12.0), events.clone());
+        let mut fired = vec![];
+        for _ in 0..600 {
+            fired.extend(stepped.advance(1.0 / 60.0));
+        }
+        let mut once = Timeline::new(140.0, Some(12.0), events);
+        assert_eq!(fired, once.advance(10.0));
+    }
+
+    #[test]
+    fn default_timeline_reverses_every_twelve_and_a_half_seconds() {
+        let mut timeline = Timeline::default_timeline();
+        let mut reversals = vec![];
+        for frame in 1..=3030 {
+            if !timeline.advance(1.0 / 60.0).is_empty() {
+                reversals.push(frame as f32 / 60.0);
+            }
+        }
+        assert_eq!(reversals.len(), 4);
+        for (i, time) in reversals.iter().enumerate() {
+            assert!((time - 12.5 * (i + 1) as f32).abs() < 0.02, "{:?}", reversals);
+        }
+    }
+}
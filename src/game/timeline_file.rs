@@ -0,0 +1,274 @@
+// Text format for beat timelines. Lines starting with `#` are comments.
+//
+//   bpm <beats_per_minute>          tempo of the beat grid, required
+//   loop <beats>                    start over after `beats`, without it the
+//                                   timeline plays once
+//   <beat> spawn <pattern>          spawn the named pattern
+//   <beat> speed <factor>           obstacle speed multiplier from here on
+//   <beat> reverse                  turn the rotation around
+//   <beat> pulse <scale> <beats>    zoom the camera in by `scale`, easing back over `beats`
+//   <beat> palette <index>          fade to another color palette
+//
+// `bpm` and `loop` come before the first event. Beats can be fractional,
+// events on the same beat fire in file order.
+use std::{
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::pattern_file::{tokenize, Token};
+use super::timeline::{TimedEvent, Timeline, TimelineEvent};
+use super::Game;
+
+pub const TIMELINE_PATH: &str = "assets/timelines/default.timeline";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineParseError {
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TimelineParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "<timeline>".to_string(),
+        };
+        write!(f, "{}:{}:{}: {}", path, self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for TimelineParseError {}
+
+#[derive(Debug)]
+pub enum TimelineLoadError {
+    Io(PathBuf, io::Error),
+    Parse(TimelineParseError),
+}
+
+impl fmt::Display for TimelineLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineLoadError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            TimelineLoadError::Parse(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TimelineLoadError {}
+
+struct Parser<'a> {
+    path: Option<&'a Path>,
+    line: usize,
+    bpm: Option<f32>,
+    loop_beats: Option<f32>,
+    events: Vec<TimedEvent>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, column: usize, message: impl Into<String>) -> TimelineParseError {
+        TimelineParseError {
+            path: self.path.map(Path::to_path_buf),
+            line: self.line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn parse_positive(&self, token: Token) -> Result<f32, TimelineParseError> {
+        match token.text.parse::<f32>() {
+            Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+            _ => Err(self.error(token.column, format!("expected a positive number, found `{}`", token.text))),
+        }
+    }
+
+    fn expect_args(&self, tokens: &[Token], count: usize, usage: &str) -> Result<(), TimelineParseError> {
+        if tokens.len() - 1 < count {
+            let column = tokens.last().map(|t| t.column + t.text.chars().count()).unwrap_or(1);
+            return Err(self.error(column, format!("missing argument, expected `{}`", usage)));
+        }
+        if tokens.len() - 1 > count {
+            return Err(self.error(tokens[count + 1].column, format!("unexpected argument, expected `{}`", usage)));
+        }
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), TimelineParseError> {
+        let content = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        let tokens = tokenize(content);
+        let Some(first) = tokens.first().copied() else {
+            return Ok(());
+        };
+
+        match first.text {
+            "bpm" | "loop" => {
+                self.expect_args(&tokens, 1, &format!("{} <value>", first.text))?;
+                if !self.events.is_empty() {
+                    return Err(self.error(first.column, format!("`{}` must come before the first event", first.text)));
+                }
+                let value = self.parse_positive(tokens[1])?;
+                if first.text == "bpm" {
+                    self.bpm = Some(value);
+                } else {
+                    self.loop_beats = Some(value);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let beat = match first.text.parse::<f32>() {
+            Ok(beat) if beat.is_finite() && beat >= 0.0 => beat,
+            _ => return Err(self.error(first.column, format!(
+                "expected `bpm`, `loop` or the beat of an event, found `{}`", first.text
+            ))),
+        };
+        if self.bpm.is_none() {
+            return Err(self.error(first.column, "add a `bpm <beats_per_minute>` line before the first event"));
+        }
+        if self.loop_beats.is_some_and(|loop_beats| beat > loop_beats) {
+            return Err(self.error(first.column, "event is after the end of the loop"));
+        }
+        let Some(kind) = tokens.get(1).copied() else {
+            return Err(self.error(first.column + first.text.chars().count(), "missing event after the beat"));
+        };
+        let args = &tokens[1..];
+        let event = match kind.text {
+            "spawn" => {
+                self.expect_args(args, 1, "spawn <pattern>")?;
+                TimelineEvent::SpawnPattern(args[1].text.to_string())
+            }
+            "speed" => {
+                self.expect_args(args, 1, "speed <factor>")?;
+                TimelineEvent::Speed(self.parse_positive(args[1])?)
+            }
+            "reverse" => {
+                self.expect_args(args, 0, "reverse")?;
+                TimelineEvent::ReverseRotation
+            }
+            "pulse" => {
+                self.expect_args(args, 2, "pulse <scale> <beats>")?;
+                TimelineEvent::CameraPulse {
+                    scale: self.parse_positive(args[1])?,
+                    beats: self.parse_positive(args[2])?,
+                }
+            }
+            "palette" => {
+                self.expect_args(args, 1, "palette <index>")?;
+                let index = args[1].text.parse::<u32>().ok()
+                    .filter(|&index| index < Game::PALETTE_COUNT)
+                    .ok_or_else(|| self.error(args[1].column, format!(
+                        "expected a palette from 0 to {}, found `{}`", Game::PALETTE_COUNT - 1, args[1].text
+                    )))?;
+                TimelineEvent::Palette(index)
+            }
+            other => {
+                return Err(self.error(kind.column, format!(
+                    "unknown event `{}`, expected `spawn`, `speed`, `reverse`, `pulse` or `palette`", other
+                )));
+            }
+        };
+        self.events.push(TimedEvent { beat, event });
+        Ok(())
+    }
+}
+
+/// Parses a timeline from `source`. `path` is only used for error messages.
+pub fn parse_timeline(source: &str, path: Option<&Path>) -> Result<Timeline, TimelineParseError> {
+    let mut parser = Parser {
+        path,
+        line: 0,
+        bpm: None,
+        loop_beats: None,
+        events: vec![],
+    };
+    for (i, line) in source.lines().enumerate() {
+        parser.line = i + 1;
+        parser.parse_line(line)?;
+    }
+    let Some(bpm) = parser.bpm else {
+        parser.line = 1;
+        return Err(parser.error(1, "timeline has no `bpm` line"));
+    };
+    Ok(Timeline::new(bpm, parser.loop_beats, parser.events))
+}
+
+pub fn load_timeline(path: &Path) -> Result<Timeline, TimelineLoadError> {
+    let source = fs::read_to_string(path).map_err(|e| TimelineLoadError::Io(path.to_path_buf(), e))?;
+    parse_timeline(&source, Some(path)).map_err(TimelineLoadError::Parse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> TimelineParseError {
+        parse_timeline(source, None).expect_err("expected a parse error")
+    }
+
+    #[test]
+    fn default_file_matches_default_timeline() {
+        let source = fs::read_to_string(TIMELINE_PATH).unwrap();
+        assert_eq!(parse_timeline(&source, None).unwrap(), Timeline::default_timeline());
+    }
+
+    #[test]
+    fn parses_all_events() {
+        let source = "\
+            # a short level\n\
+            bpm 150\n\
+            loop 16\n\
+            0 spawn spiral\n\
+            4 speed 1.5   # faster\n\
+            8 reverse\n\
+            8 pulse 1.2 0.5\n\
+            15.5 palette 2\n";
+        let timeline = parse_timeline(source, None).unwrap();
+        assert_eq!(timeline.bpm, 150.0);
+        assert_eq!(timeline.loop_beats, Some(16.0));
+        let events: Vec<TimelineEvent> = timeline.events().iter().map(|e| e.event.clone()).collect();
+        assert_eq!(events, vec![
+            TimelineEvent::SpawnPattern("spiral".to_string()),
+            TimelineEvent::Speed(1.5),
+            TimelineEvent::ReverseRotation,
+            TimelineEvent::CameraPulse { scale: 1.2, beats: 0.5 },
+            TimelineEvent::Palette(2),
+        ]);
+        assert_eq!(timeline.events()[4].beat, 15.5);
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let err = parse_error("0 reverse\n");
+        assert_eq!((err.line, err.column), (1, 1));
+        assert!(err.message.contains("bpm"), "{}", err);
+
+        let err = parse_error("bpm 120\n  4 jump\n");
+        assert_eq!((err.line, err.column), (2, 5));
+        assert!(err.message.contains("unknown event `jump`"), "{}", err);
+
+        let err = parse_error("bpm 120\nloop 8\n9 reverse\n");
+        assert_eq!((err.line, err.column), (3, 1));
+
+        let err = parse_error("bpm 120\n1 palette 9\n");
+        assert_eq!((err.line, err.column), (2, 11));
+
+        let err = parse_error("bpm 120\n1 reverse\nloop 4\n");
+        assert_eq!((err.line, err.column), (3, 1));
+
+        let err = parse_error("bpm 120\n1 pulse 1.2\n");
+        assert!(err.message.contains("pulse <scale> <beats>"), "{}", err);
+
+        let err = parse_error("bpm -5\n");
+        assert_eq!((err.line, err.column), (1, 5));
+
+        assert!(parse_error("# nothing\n").message.contains("no `bpm`"));
+    }
+}
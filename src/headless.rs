@@ -0,0 +1,336 @@
+// Runs the game logic without a window or GPU, for automated gameplay tests.
+use crate::replay::{Desync, Replay, ReplayPlayer};
+
+pub use crate::audio::Audio;
+pub use crate::game::{Autoplay, Game, GameEvent, GameState, Stage};
+pub use crate::input::{InputID, VirtualInput};
+
+/// Fixed timestep of headless runs, matches the engine's target frame duration.
+pub const HEADLESS_DT: f32 = 1.0 / 60.0;
+const HEADLESS_ASPECT: f32 = 16.0 / 9.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScriptEvent {
+    time: f32,
+    key: InputID,
+    pressed: bool,
+}
+
+/// Key presses and releases at fixed times, applied at the first update at or
+/// after the event time.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    events: Vec<ScriptEvent>,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(mut self, key: InputID, time: f32) -> Self {
+        self.events.push(ScriptEvent { time, key, pressed: true });
+        self
+    }
+
+    pub fn release(mut self, key: InputID, time: f32) -> Self {
+        self.events.push(ScriptEvent { time, key, pressed: false });
+        self
+    }
+
+    /// Holds `key` from `start` until `end`.
+    pub fn hold(self, key: InputID, start: f32, end: f32) -> Self {
+        self.press(key, start).release(key, end)
+    }
+
+    fn sorted_events(&self) -> Vec<ScriptEvent> {
+        let mut events = self.events.clone();
+        // stable, so events at the same time keep their order
+        events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        events
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadlessResult {
+    pub steps: usize,
+    pub time: f32,
+    pub game_over: bool,
+    pub checksum: u32,
+}
+
+pub struct HeadlessRunner {
+    pub game: Game,
+    pub input: VirtualInput,
+    pub dt: f32,
+    pub time: f32,
+    pub steps: usize,
+    /// Silent unless set to record the sound of the run, it plays the music
+    /// either way since the music is the clock of the timeline.
+    pub audio: Audio,
+}
+
+impl HeadlessRunner {
+    /// Runner with a game that is already playing, skipping the title screen.
+    pub fn new(seed: u64) -> Self {
+        Self::playing_stage(seed, Stage::first())
+    }
+
+    /// Like `new`, on `stage` instead of the first one.
+    pub fn playing_stage(seed: u64, stage: &Stage) -> Self {
+        let mut runner = Self::with_stage(seed, stage);
+        runner.game.start();
+        runner
+    }
+
+    /// Runner with a game waiting on the title screen, like a freshly started engine.
+    pub fn on_title_screen(seed: u64) -> Self {
+        Self::with_stage(seed, Stage::first())
+    }
+
+    /// Like `on_title_screen`, with `stage` picked.
+    pub fn with_stage(seed: u64, stage: &Stage) -> Self {
+        Self {
+            game: Game::new(HEADLESS_ASPECT, seed, stage.clone()),
+            input: VirtualInput::new(),
+            dt: HEADLESS_DT,
+            time: 0.0,
+            steps: 0,
+            audio: Audio::silent(),
+        }
+    }
+
+    /// One game update with the currently pressed keys.
+    pub fn step(&mut self) {
+        self.game.update(self.dt, &self.input);
+        self.time += self.dt;
+        self.steps += 1;
+        self.update_audio(self.dt);
+    }
+
+    fn update_audio(&mut self, dt: f32) {
+        if let Err(err) = self.audio.update_game(&mut self.game, dt) {
+            eprintln!("Failed to write audio: {}", err);
+            self.audio = Audio::silent();
+        }
+    }
+
+    pub fn result(&self) -> HeadlessResult {
+        HeadlessResult {
+            steps: self.steps,
+            time: self.time,
+            game_over: self.game.is_game_over(),
+            checksum: self.game.state_checksum(),
+        }
+    }
+
+    /// Steps the game for `seconds`, or until game over, applying `script`.
+    /// Script times are relative to the start of this call.
+    pub fn run(&mut self, seconds: f32, script: &InputScript) -> HeadlessResult {
+        let events = script.sorted_events();
+        let mut next_event = 0;
+        let mut pressed = self.input.pressed_mask();
+        let mut time = 0.0;
+        while time < seconds && !self.game.is_game_over() {
+            while let Some(event) = events.get(next_event).filter(|e| e.time <= time) {
+                if event.pressed {
+                    pressed |= event.key.bit();
+                } else {
+                    pressed &= !event.key.bit();
+                }
+                next_event += 1;
+            }
+            self.input.set_pressed_mask(pressed);
+            self.step();
+            time += self.dt;
+        }
+        self.result()
+    }
+
+    /// Lets `Autoplay` play for `seconds`, or until game over.
+    pub fn run_autoplay(&mut self, seconds: f32) -> HeadlessResult {
+        let mut bot = Autoplay::new();
+        let mut time = 0.0;
+        while time < seconds && !self.game.is_game_over() {
+            bot.drive(&self.game, &mut self.input);
+            self.step();
+            time += self.dt;
+        }
+        self.result()
+    }
+
+    /// Plays back `replay` from a fresh game on the title screen, checking
+    /// the recorded checksums.
+    pub fn run_replay(replay: Replay) -> Result<HeadlessResult, Desync> {
+        Self::run_replay_with_audio(replay, Audio::silent())
+    }
+
+    /// Like `run_replay`, rendering the sound of the run to `audio`.
+    pub fn run_replay_with_audio(replay: Replay, audio: Audio) -> Result<HeadlessResult, Desync> {
+        let mut runner = Self::with_stage(replay.seed, Stage::find_or_first(&replay.stage));
+        runner.audio = audio;
+        runner.play_replay(replay)
+    }
+
+    /// Plays back `replay` on this runner's game, which has to be on the
+    /// title screen with the replay's seed and stage.
+    pub fn play_replay(mut self, replay: Replay) -> Result<HeadlessResult, Desync> {
+        let mut player = ReplayPlayer::new(replay);
+        while let Some(dt) = player.next_frame() {
+            self.game.update(dt, &player.input);
+            self.time += dt;
+            self.steps += 1;
+            self.update_audio(dt);
+            player.sync_music(&mut self.game);
+            player.verify(&self.game)?;
+        }
+        self.finish_audio();
+        Ok(self.result())
+    }
+
+    /// Flushes the audio output, call it after the last step.
+    pub fn finish_audio(&mut self) {
+        if let Err(err) = self.audio.finish() {
+            eprintln!("Failed to finish audio: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayRecorder;
+    use crate::time_scale::TimeScale;
+
+    #[test]
+    fn same_seed_and_script_give_same_run() {
+        let script = InputScript::new()
+            .hold(InputID::Left, 1.0, 2.5)
+            .hold(InputID::Right, 4.0, 4.3);
+        let a = HeadlessRunner::new(5).run(20.0, &script);
+        let b = HeadlessRunner::new(5).run(20.0, &script);
+        assert_eq!(a, b);
+        assert!(a.steps > 0);
+    }
+
+    #[test]
+    fn idle_player_eventually_collides() {
+        let result = HeadlessRunner::new(1).run(120.0, &InputScript::new());
+        assert!(result.game_over);
+        assert!(result.time < 120.0);
+    }
+
+    #[test]
+    fn autoplay_starts_a_run_and_outlives_an_idle_player() {
+        let idle = HeadlessRunner::new(4).run(60.0, &InputScript::new());
+        assert!(idle.game_over);
+        let mut runner = HeadlessRunner::on_title_screen(4);
+        let played = runner.run_autoplay(idle.time + 10.0);
+        assert!(!played.game_over, "died after {:.2} s", played.time);
+        assert_eq!(runner.game.state(), GameState::Playing);
+    }
+
+    #[test]
+    fn recorded_run_replays_without_desync() {
+        let mut runner = HeadlessRunner::on_title_screen(99);
+        let mut recorder = ReplayRecorder::new(99, Stage::first().id);
+        for step in 0..600 {
+            let keys: &[InputID] = match (step / 40) % 3 {
+                _ if step == 0 => &[InputID::Confirm],
+                0 => &[InputID::Left],
+                1 => &[],
+                _ => &[InputID::Right],
+            };
+            runner.input.set_pressed(keys);
+            recorder.record_input(runner.dt, &runner.input);
+            runner.step();
+            recorder.record_state(&runner.game);
+        }
+        let expected = runner.result();
+        let replayed = HeadlessRunner::run_replay(recorder.replay.clone()).unwrap();
+        assert_eq!(replayed.checksum, expected.checksum);
+
+        // a changed input must be noticed at the next checksum
+        let mut tampered = recorder.replay;
+        tampered.frames[10].keys[0] ^= InputID::Left.bit();
+        let desync = HeadlessRunner::run_replay(tampered).unwrap_err();
+        assert_eq!(desync.frame, 30);
+    }
+
+    #[test]
+    fn time_scaled_run_with_a_spawning_timeline_replays_without_desync() {
+        // like the engine: the game and the replay get the scaled time, the
+        // audio gets the wall clock, near misses stop the game for a moment
+        let timeline = "bpm 60\nloop 16\n8 spawn square_gaps\n12 speed 1.1\n";
+        let mut runner = HeadlessRunner::on_title_screen(7);
+        runner.game.set_timeline_source(timeline).unwrap();
+        let mut recorder = ReplayRecorder::new(7, Stage::first().id);
+        let mut time_scale = TimeScale::new();
+        let mut bot = Autoplay::new();
+        let (mut near_misses, mut hit_stops) = (0, 0);
+        for _ in 0..3600 {
+            let game_dt = time_scale.update(HEADLESS_DT);
+            hit_stops += (game_dt == 0.0) as usize;
+            bot.drive(&runner.game, &mut runner.input);
+            recorder.record_input(game_dt, &runner.input);
+            runner.game.update(game_dt, &runner.input);
+            runner.update_audio(HEADLESS_DT);
+            recorder.record_state(&runner.game);
+            near_misses += runner.game.events().iter().filter(|&&event| event == GameEvent::NearMiss).count();
+            time_scale.handle_events(runner.game.events());
+        }
+        assert!(near_misses > 0 && hit_stops > 0, "{} near misses, {} hit-stops", near_misses, hit_stops);
+        let expected = runner.game.state_checksum();
+
+        // the music of the replay plays in game time, the recorded music
+        // positions keep its timeline where the recorded one was
+        let mut replayer = HeadlessRunner::on_title_screen(7);
+        replayer.game.set_timeline_source(timeline).unwrap();
+        let replayed = replayer.play_replay(recorder.replay).unwrap();
+        assert_eq!(replayed.checksum, expected);
+    }
+
+    #[test]
+    fn state_transitions() {
+        let mut runner = HeadlessRunner::on_title_screen(3);
+        let press = |runner: &mut HeadlessRunner, keys: &[InputID]| {
+            runner.input.set_pressed(keys);
+            runner.step();
+            runner.input.set_pressed(&[]);
+            runner.step();
+        };
+        runner.run(2.0, &InputScript::new());
+        assert_eq!(runner.game.state(), GameState::Title);
+        assert_eq!(runner.game.survival_time, 0.0);
+
+        press(&mut runner, &[InputID::Confirm]);
+        assert_eq!(runner.game.state(), GameState::Playing);
+        press(&mut runner, &[InputID::Cancel]);
+        assert_eq!(runner.game.state(), GameState::Paused);
+        let (paused_time, paused_checksum) = (runner.game.time, runner.game.state_checksum());
+        runner.run(1.0, &InputScript::new());
+        assert_eq!(runner.game.time, paused_time);
+        assert_eq!(runner.game.state_checksum(), paused_checksum);
+        press(&mut runner, &[InputID::Cancel]);
+        assert_eq!(runner.game.state(), GameState::Playing);
+
+        runner.run(120.0, &InputScript::new());
+        assert_eq!(runner.game.state(), GameState::GameOver);
+        let run = runner.game.take_finished_run().unwrap();
+        assert_eq!(run.seed, 3);
+        assert!(runner.game.take_finished_run().is_none());
+
+        // the game over screen waits for input
+        runner.run(1.0, &InputScript::new());
+        assert_eq!(runner.game.state(), GameState::GameOver);
+        press(&mut runner, &[InputID::Confirm]);
+        assert_eq!(runner.game.state(), GameState::Playing);
+        assert!(runner.game.survival_time < 0.1);
+        assert_ne!(runner.game.seed(), 3);
+        assert!(runner.game.get_obstacles_all().iter().all(|o| o.start > Game::PLAYER_RADIUS));
+
+        runner.run(120.0, &InputScript::new());
+        press(&mut runner, &[InputID::Cancel]);
+        assert_eq!(runner.game.state(), GameState::Title);
+    }
+}
@@ -0,0 +1,378 @@
+// Persistent high-score tables, one per game mode.
+//
+// Scores are stored in a small text file in the user data directory:
+//
+//   highscores 2
+//   entry <mode> <time> <date> <seed> <pattern>
+//
+// `mode` is the id of the stage, `time` the survival time in seconds and `date`
+// a unix timestamp. Version 1 files are from before stages, their only mode
+// `normal` is renamed to the first stage when they're loaded and they are
+// written back as version 2 on the next save. Lines that can't be parsed are
+// skipped. A file that isn't a high-score file at all is
+// moved aside to `<file>.corrupt` before it gets overwritten, and a file written
+// by a newer version of the game is left alone.
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+pub const HIGHSCORE_VERSION: u32 = 2;
+/// The oldest version `HighScores::load` can still read.
+pub const MIN_HIGHSCORE_VERSION: u32 = 1;
+pub const HIGHSCORE_FILE_NAME: &str = "highscores.txt";
+const APP_DIR_NAME: &str = "raymarched-hexagon";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighScoreEntry {
+    /// Survival time in seconds.
+    pub time: f32,
+    /// Unix timestamp of the run.
+    pub date: u64,
+    pub seed: u64,
+    /// Name of the last pattern spawned before the run ended.
+    pub pattern: String,
+}
+
+#[derive(Debug)]
+pub enum HighScoreError {
+    Io(io::Error),
+    /// The file was written by a newer version and is not overwritten.
+    NewerVersion(u32),
+}
+
+impl fmt::Display for HighScoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HighScoreError::Io(err) => write!(f, "{}", err),
+            HighScoreError::NewerVersion(version) => write!(
+                f,
+                "high-score file has version {}, this game only knows version {}",
+                version, HIGHSCORE_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HighScoreError {}
+
+impl From<io::Error> for HighScoreError {
+    fn from(err: io::Error) -> Self {
+        HighScoreError::Io(err)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HighScores {
+    /// `None` keeps the scores in memory only.
+    path: Option<PathBuf>,
+    tables: BTreeMap<String, Vec<HighScoreEntry>>,
+    /// Set if the file on disk is from a newer version.
+    newer_version: Option<u32>,
+}
+
+/// `$XDG_DATA_HOME`, `~/.local/share`, `~/Library/Application Support` or `%APPDATA%`.
+pub fn user_data_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let base = if cfg!(target_os = "windows") {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".local/share")))
+    };
+    base.map(|dir| dir.join(APP_DIR_NAME))
+}
+
+impl HighScores {
+    pub const MAX_ENTRIES: usize = 10;
+
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Loads the table from the user data directory, or keeps it in memory if
+    /// there is no such directory (e.g. on the web).
+    pub fn load_default() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            return Self::in_memory();
+        }
+        match user_data_dir() {
+            Some(dir) => Self::load(&dir.join(HIGHSCORE_FILE_NAME)),
+            None => Self::in_memory(),
+        }
+    }
+
+    /// Loads the table from `path`. Problems with the file are reported but
+    /// never fatal, a missing file gives empty tables.
+    pub fn load(path: &Path) -> Self {
+        let mut scores = Self { path: Some(path.to_path_buf()), ..Self::default() };
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return scores,
+            Err(err) => {
+                eprintln!("Failed to read high scores {}: {}", path.display(), err);
+                scores.backup_corrupt_file();
+                return scores;
+            }
+        };
+
+        let mut lines = source.lines().enumerate();
+        let version = lines.next().and_then(|(_, header)| {
+            let mut tokens = header.split_whitespace();
+            match (tokens.next(), tokens.next(), tokens.next()) {
+                (Some("highscores"), Some(version), None) => version.parse::<u32>().ok(),
+                _ => None,
+            }
+        });
+        let version = match version {
+            Some(version) if version > HIGHSCORE_VERSION => {
+                eprintln!("{}", HighScoreError::NewerVersion(version));
+                scores.newer_version = Some(version);
+                return scores;
+            }
+            Some(version) if version >= MIN_HIGHSCORE_VERSION => version,
+            _ => {
+                eprintln!("{} is not a high-score file", path.display());
+                scores.backup_corrupt_file();
+                return scores;
+            }
+        };
+
+        for (i, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_entry(line) {
+                Some((mode, entry)) => scores.tables.entry(upgrade_mode(version, mode)).or_default().push(entry),
+                None => eprintln!("{}:{}: skipping invalid high-score entry", path.display(), i + 1),
+            }
+        }
+        for table in scores.tables.values_mut() {
+            sort_and_truncate(table);
+        }
+        scores
+    }
+
+    fn backup_corrupt_file(&self) {
+        if let Some(path) = &self.path {
+            let mut backup = path.clone().into_os_string();
+            backup.push(".corrupt");
+            if let Err(err) = fs::rename(path, &backup) {
+                eprintln!("Failed to move {} aside: {}", path.display(), err);
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), HighScoreError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(version) = self.newer_version {
+            return Err(HighScoreError::NewerVersion(version));
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = format!("highscores {}\n", HIGHSCORE_VERSION);
+        for (mode, table) in &self.tables {
+            for entry in table {
+                out.push_str(&format!(
+                    "entry {} {} {} {} {}\n",
+                    mode, entry.time, entry.date, entry.seed, entry.pattern
+                ));
+            }
+        }
+        // write a temporary file first, so a crash can't leave a half written table
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, out)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Adds an entry and returns its rank (0 is best), or `None` if it didn't
+    /// make it into the table.
+    pub fn submit(&mut self, mode: &str, entry: HighScoreEntry) -> Option<usize> {
+        let mode = sanitize_token(mode);
+        let entry = HighScoreEntry { pattern: sanitize_token(&entry.pattern), ..entry };
+        let table = self.tables.entry(mode).or_default();
+        let rank = table.iter().position(|e| entry.time > e.time).unwrap_or(table.len());
+        if rank >= Self::MAX_ENTRIES {
+            return None;
+        }
+        table.insert(rank, entry);
+        table.truncate(Self::MAX_ENTRIES);
+        Some(rank)
+    }
+
+    /// The best `n` entries of `mode`, best first.
+    pub fn top(&self, mode: &str, n: usize) -> &[HighScoreEntry] {
+        match self.tables.get(mode) {
+            Some(table) => &table[..n.min(table.len())],
+            None => &[],
+        }
+    }
+
+    pub fn personal_best(&self, mode: &str) -> Option<&HighScoreEntry> {
+        self.top(mode, 1).first()
+    }
+}
+
+fn parse_entry(line: &str) -> Option<(String, HighScoreEntry)> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next()? != "entry" {
+        return None;
+    }
+    let mode = tokens.next()?.to_string();
+    let time = tokens.next()?.parse::<f32>().ok().filter(|t| t.is_finite() && *t >= 0.0)?;
+    let date = tokens.next()?.parse::<u64>().ok()?;
+    let seed = tokens.next()?.parse::<u64>().ok()?;
+    let pattern = tokens.next()?.to_string();
+    if tokens.next().is_some() {
+        return None;
+    }
+    Some((mode, HighScoreEntry { time, date, seed, pattern }))
+}
+
+/// The mode of an entry read from a file of `version` as it is called now.
+fn upgrade_mode(version: u32, mode: String) -> String {
+    // before version 2 there was one mode, which became the first stage
+    if version < 2 && mode == "normal" {
+        return "hexagon".to_string();
+    }
+    mode
+}
+
+fn sort_and_truncate(table: &mut Vec<HighScoreEntry>) {
+    table.sort_by(|a, b| b.time.total_cmp(&a.time));
+    table.truncate(HighScores::MAX_ENTRIES);
+}
+
+/// Modes and pattern names are stored as single words.
+fn sanitize_token(text: &str) -> String {
+    let token: String = text.split_whitespace().collect::<Vec<_>>().join("_");
+    if token.is_empty() { "-".to_string() } else { token }
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD` (UTC).
+pub fn format_date(unix_seconds: u64) -> String {
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = (unix_seconds / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("highscore-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(HIGHSCORE_FILE_NAME)
+    }
+
+    fn entry(time: f32) -> HighScoreEntry {
+        HighScoreEntry { time, date: 1_700_000_000, seed: 7, pattern: "spiral".to_string() }
+    }
+
+    #[test]
+    fn ranks_and_truncates() {
+        let mut scores = HighScores::in_memory();
+        assert_eq!(scores.submit("normal", entry(10.0)), Some(0));
+        assert_eq!(scores.submit("normal", entry(20.0)), Some(0));
+        assert_eq!(scores.submit("normal", entry(15.0)), Some(1));
+        assert_eq!(scores.submit("other", entry(1.0)), Some(0));
+        for i in 0..20 {
+            scores.submit("normal", entry(30.0 + i as f32));
+        }
+        assert_eq!(scores.top("normal", 100).len(), HighScores::MAX_ENTRIES);
+        assert_eq!(scores.submit("normal", entry(5.0)), None);
+        assert_eq!(scores.personal_best("normal").unwrap().time, 49.0);
+        assert_eq!(scores.top("other", 3), &[entry(1.0)]);
+        assert!(scores.personal_best("missing").is_none());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = temp_file("round-trip");
+        let mut scores = HighScores::load(&path);
+        scores.submit("normal", entry(12.5));
+        scores.submit("normal", HighScoreEntry { pattern: "two words".to_string(), ..entry(3.25) });
+        scores.save().unwrap();
+
+        let loaded = HighScores::load(&path);
+        assert_eq!(loaded.top("normal", 10), &[
+            entry(12.5),
+            HighScoreEntry { pattern: "two_words".to_string(), ..entry(3.25) },
+        ]);
+    }
+
+    #[test]
+    fn skips_corrupt_lines() {
+        let path = temp_file("corrupt-lines");
+        fs::write(&path, "highscores 2\nentry normal 5 1 2 spiral\nentry normal nan 1 2 x\ngarbage\nentry normal 9 1\nentry normal 7 1 2 gaps\n").unwrap();
+        let scores = HighScores::load(&path);
+        let times: Vec<f32> = scores.top("normal", 10).iter().map(|e| e.time).collect();
+        assert_eq!(times, vec![7.0, 5.0]);
+    }
+
+    #[test]
+    fn upgrades_version_1_files() {
+        let path = temp_file("version-1");
+        fs::write(&path, "highscores 1\nentry normal 42.5 1700000000 7 spiral\nentry other 3 1700000000 7 spiral\n").unwrap();
+        let scores = HighScores::load(&path);
+        assert_eq!(scores.top("hexagon", 10), &[entry(42.5)]);
+        assert!(scores.top("normal", 10).is_empty());
+        assert_eq!(scores.top("other", 10), &[entry(3.0)]);
+
+        scores.save().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().starts_with("highscores 2\n"));
+        assert_eq!(HighScores::load(&path).top("hexagon", 10), &[entry(42.5)]);
+    }
+
+    #[test]
+    fn corrupt_file_is_moved_aside() {
+        let path = temp_file("corrupt-file");
+        fs::write(&path, [0xff, 0x00, 0x12]).unwrap();
+        let mut scores = HighScores::load(&path);
+        assert!(scores.top("normal", 10).is_empty());
+        let mut backup = path.clone().into_os_string();
+        backup.push(".corrupt");
+        assert!(Path::new(&backup).exists());
+
+        scores.submit("normal", entry(1.0));
+        scores.save().unwrap();
+        assert_eq!(HighScores::load(&path).top("normal", 10).len(), 1);
+    }
+
+    #[test]
+    fn newer_version_is_not_overwritten() {
+        let path = temp_file("newer-version");
+        let contents = "highscores 3\nsomething new\n";
+        fs::write(&path, contents).unwrap();
+        let mut scores = HighScores::load(&path);
+        scores.submit("normal", entry(1.0));
+        assert!(matches!(scores.save(), Err(HighScoreError::NewerVersion(3))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_700_000_000), "2023-11-14");
+    }
+}
@@ -1,9 +1,10 @@
 
 use std::collections::HashMap;
 
-use winit::{dpi::{PhysicalPosition, PhysicalSize}, event, window::Window};
-
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, event, keyboard::KeyCode, window::Window};
 
+/// Players that can play at once, each with their own keys.
+pub const MAX_PLAYERS: usize = 2;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum InputID {
@@ -15,6 +16,23 @@ pub enum InputID {
     Right,
 }
 
+impl InputID {
+    pub const ALL: [InputID; 6] = [
+        InputID::Confirm,
+        InputID::Cancel,
+        InputID::Up,
+        InputID::Down,
+        InputID::Left,
+        InputID::Right,
+    ];
+
+    /// Bit of this input in a pressed key mask, see `VirtualInput`.
+    pub fn bit(self) -> u8 {
+        let index = InputID::ALL.iter().position(|&k| k == self).unwrap();
+        1 << index
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct KeyState {
     pub pressed: bool,
@@ -23,7 +41,7 @@ pub struct KeyState {
 }
 
 impl KeyState {
-    fn new() -> KeyState {
+    pub fn new() -> KeyState {
         KeyState {
             pressed: false,
             just_pressed: false,
@@ -60,13 +78,14 @@ pub struct InputHandler<'a> {
     mouse_sensitivity: f32,
 
     inputs_received: Vec<event::KeyEvent>,
-    inputs: HashMap<InputID, Input>,
+    /// Action map of each player, see `InputGetInterface::get_player_key_state`.
+    players: Vec<HashMap<InputID, Input>>,
+    /// Keys of any player, for the menus and single player runs.
+    combined: HashMap<InputID, KeyState>,
 }
 
 pub trait InputDriveInterface {
-    fn new(window: &Window) -> InputHandler;
     fn activate(&mut self);
-    fn deactivate(&mut self);
     fn cursor_moved(&mut self, position: &PhysicalPosition<f64>);
     fn window_resized(&mut self, size: PhysicalSize<u32>);
     fn update(&mut self, dt: f32);
@@ -76,7 +95,10 @@ pub trait InputDriveInterface {
 pub trait InputGetInterface {
     fn get_mouse_x(&self) -> f32;
     fn get_mouse_y(&self) -> f32;
+    /// State of `key` on any player's keys.
     fn get_key_state(&self, key: InputID) -> &KeyState;
+    /// State of `key` on the keys of `player` alone.
+    fn get_player_key_state(&self, player: usize, key: InputID) -> &KeyState;
 }
 
 impl InputGetInterface for InputHandler<'_> {
@@ -89,48 +111,131 @@ impl InputGetInterface for InputHandler<'_> {
     }
 
     fn get_key_state(&self, key: InputID) -> &KeyState {
-        &self.inputs[&key].key_state
+        &self.combined[&key]
+    }
+
+    fn get_player_key_state(&self, player: usize, key: InputID) -> &KeyState {
+        &self.players[player][&key].key_state
     }
 }
 
-impl InputHandler<'_> {
-    pub fn debug_print_keys(&self) {
-        for (key, input) in &self.inputs {
-            println!("{:?} {:?}", key, input.key_state);
+/// Input source that isn't backed by a window, used for replays and headless runs.
+/// The pressed keys are set once per update, just pressed / released states are
+/// derived from the previous update. The plain setters are for the first player.
+pub struct VirtualInput {
+    key_states: [[KeyState; InputID::ALL.len()]; MAX_PLAYERS],
+    pressed_masks: [u8; MAX_PLAYERS],
+    /// Keys of any player, see `InputGetInterface::get_key_state`.
+    combined: [KeyState; InputID::ALL.len()],
+}
+
+impl VirtualInput {
+    pub fn new() -> Self {
+        Self {
+            key_states: [[KeyState::new(); InputID::ALL.len()]; MAX_PLAYERS],
+            pressed_masks: [0; MAX_PLAYERS],
+            combined: [KeyState::new(); InputID::ALL.len()],
+        }
+    }
+
+    /// Keys of the first player.
+    pub fn pressed_mask(&self) -> u8 {
+        self.pressed_masks[0]
+    }
+
+    /// Bit `i` of `mask` is set if `InputID::ALL[i]` is pressed, by the
+    /// first player. The other players press nothing.
+    pub fn set_pressed_mask(&mut self, mask: u8) {
+        let mut masks = [0; MAX_PLAYERS];
+        masks[0] = mask;
+        self.set_pressed_masks(masks);
+    }
+
+    pub fn set_pressed(&mut self, keys: &[InputID]) {
+        self.set_pressed_mask(Self::mask(keys));
+    }
+
+    pub fn pressed_masks(&self) -> [u8; MAX_PLAYERS] {
+        self.pressed_masks
+    }
+
+    /// The keys of every player, like `set_pressed_mask` for each one.
+    pub fn set_pressed_masks(&mut self, masks: [u8; MAX_PLAYERS]) {
+        let all = |masks: &[u8; MAX_PLAYERS]| masks.iter().fold(0, |all, mask| all | mask);
+        Self::apply_mask(&mut self.combined, all(&self.pressed_masks), all(&masks));
+        for (player, &mask) in masks.iter().enumerate() {
+            Self::apply_mask(&mut self.key_states[player], self.pressed_masks[player], mask);
+        }
+        self.pressed_masks = masks;
+    }
+
+    pub fn set_players_pressed(&mut self, keys: [&[InputID]; MAX_PLAYERS]) {
+        self.set_pressed_masks(keys.map(Self::mask));
+    }
+
+    fn mask(keys: &[InputID]) -> u8 {
+        keys.iter().fold(0, |mask, key| mask | key.bit())
+    }
+
+    fn apply_mask(states: &mut [KeyState; InputID::ALL.len()], previous: u8, mask: u8) {
+        for (i, state) in states.iter_mut().enumerate() {
+            let was_pressed = previous & (1 << i) != 0;
+            let pressed = mask & (1 << i) != 0;
+            state.pressed = pressed;
+            state.just_pressed = pressed && !was_pressed;
+            state.just_released = !pressed && was_pressed;
         }
     }
 }
 
-impl<'a> InputDriveInterface for InputHandler<'a> {
-    fn new(window: &Window) -> InputHandler {
-
-        let mut inputs = HashMap::new();
-        inputs.insert(InputID::Confirm, Input::new(InputID::Confirm, vec![
-            winit::keyboard::KeyCode::Space,
-            winit::keyboard::KeyCode::Enter,
-            winit::keyboard::KeyCode::KeyE,
-        ]));
-        inputs.insert(InputID::Cancel, Input::new(InputID::Cancel, vec![
-            winit::keyboard::KeyCode::Escape,
-            winit::keyboard::KeyCode::Backspace,
-            winit::keyboard::KeyCode::KeyQ,
-        ]));
-        inputs.insert(InputID::Up, Input::new(InputID::Up, vec![
-            winit::keyboard::KeyCode::KeyW,
-            winit::keyboard::KeyCode::ArrowUp,
-        ]));
-        inputs.insert(InputID::Down, Input::new(InputID::Down, vec![
-            winit::keyboard::KeyCode::KeyS,
-            winit::keyboard::KeyCode::ArrowDown,
-        ]));
-        inputs.insert(InputID::Left, Input::new(InputID::Left, vec![
-            winit::keyboard::KeyCode::KeyA,
-            winit::keyboard::KeyCode::ArrowLeft,
-        ]));
-        inputs.insert(InputID::Right, Input::new(InputID::Right, vec![
-            winit::keyboard::KeyCode::KeyD,
-            winit::keyboard::KeyCode::ArrowRight,
-        ]));
+impl Default for VirtualInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputGetInterface for VirtualInput {
+    fn get_mouse_x(&self) -> f32 {
+        0.0
+    }
+
+    fn get_mouse_y(&self) -> f32 {
+        0.0
+    }
+
+    fn get_key_state(&self, key: InputID) -> &KeyState {
+        let index = InputID::ALL.iter().position(|&k| k == key).unwrap();
+        &self.combined[index]
+    }
+
+    fn get_player_key_state(&self, player: usize, key: InputID) -> &KeyState {
+        let index = InputID::ALL.iter().position(|&k| k == key).unwrap();
+        &self.key_states[player][index]
+    }
+}
+
+impl<'a> InputHandler<'a> {
+    pub fn new(window: &'a Window) -> Self {
+        // the first player is on the left of the keyboard, the second one on the arrows
+        let players = vec![
+            Self::action_map([
+                (InputID::Confirm, vec![KeyCode::Space, KeyCode::KeyE]),
+                (InputID::Cancel, vec![KeyCode::Escape, KeyCode::KeyQ]),
+                (InputID::Up, vec![KeyCode::KeyW]),
+                (InputID::Down, vec![KeyCode::KeyS]),
+                (InputID::Left, vec![KeyCode::KeyA]),
+                (InputID::Right, vec![KeyCode::KeyD]),
+            ]),
+            Self::action_map([
+                (InputID::Confirm, vec![KeyCode::Enter]),
+                (InputID::Cancel, vec![KeyCode::Backspace]),
+                (InputID::Up, vec![KeyCode::ArrowUp]),
+                (InputID::Down, vec![KeyCode::ArrowDown]),
+                (InputID::Left, vec![KeyCode::ArrowLeft]),
+                (InputID::Right, vec![KeyCode::ArrowRight]),
+            ]),
+        ];
+        let combined = InputID::ALL.iter().map(|&key| (key, KeyState::new())).collect();
 
         InputHandler {
             window,
@@ -139,21 +244,31 @@ impl<'a> InputDriveInterface for InputHandler<'a> {
             screen_width: window.inner_size().width,
             screen_height: window.inner_size().height,
             mouse_sensitivity: 40.0,
-            inputs,
+            players,
+            combined,
             inputs_received: vec![],
         }
     }
 
+    fn action_map(keys: [(InputID, Vec<KeyCode>); InputID::ALL.len()]) -> HashMap<InputID, Input> {
+        keys.into_iter().map(|(id, keys)| (id, Input::new(id, keys))).collect()
+    }
+
+    pub fn debug_print_keys(&self) {
+        for (player, inputs) in self.players.iter().enumerate() {
+            for (key, input) in inputs {
+                println!("{} {:?} {:?}", player + 1, key, input.key_state);
+            }
+        }
+    }
+}
+
+impl InputDriveInterface for InputHandler<'_> {
     fn activate(&mut self) {
         self.window.set_cursor_grab(winit::window::CursorGrabMode::Confined).expect("Could not grab cursor");
         self.window.set_cursor_visible(false);
     }
 
-    fn deactivate(&mut self) {
-        self.window.set_cursor_grab(winit::window::CursorGrabMode::None).expect("Could not ungrab cursor");
-        self.window.set_cursor_visible(true);
-    }
-
     fn window_resized(&mut self, PhysicalSize { width, height }: PhysicalSize<u32>) {
         self.screen_width = width;
         self.screen_height = height;
@@ -180,10 +295,15 @@ impl<'a> InputDriveInterface for InputHandler<'a> {
         self.inputs_received.push(event.clone());
     }
 
-    fn update(&mut self, dt: f32) {
+    fn update(&mut self, _dt: f32) {
+        // just_pressed / just_released only hold for the update in which the key changed
+        for input in self.players.iter_mut().flat_map(|inputs| inputs.values_mut()) {
+            input.key_state.just_pressed = false;
+            input.key_state.just_released = false;
+        }
         // Note: we could tag the input events with a timestamp and only process those within dt range
         for event in self.inputs_received.drain(..) {
-            for input in self.inputs.values_mut() {
+            for input in self.players.iter_mut().flat_map(|inputs| inputs.values_mut()) {
                 let pressed = event.state == winit::event::ElementState::Pressed;
                 let physical_key = event.physical_key;
                 let key_code = match physical_key {
@@ -197,6 +317,12 @@ impl<'a> InputDriveInterface for InputHandler<'a> {
                 }
             }
         }
+        // a key held by one player stays pressed when the other one lets go of theirs
+        for (key, state) in &mut self.combined {
+            let pressed = self.players.iter().any(|inputs| inputs[key].key_state.pressed);
+            state.just_pressed = pressed && !state.pressed;
+            state.just_released = !pressed && state.pressed;
+            state.pressed = pressed;
+        }
     }
-    
 }
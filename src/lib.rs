@@ -3,11 +3,33 @@ use winit::{event::Event, event_loop::EventLoopWindowTarget};
 mod engine;
 mod renderer;
 mod time;
+mod time_scale;
 mod input;
 mod game;
 mod common;
+mod replay;
+mod highscore;
+mod audio;
+pub mod headless;
+pub mod pattern_check;
+pub mod pattern_view;
 
 pub async fn run() {
+    let options = crate::engine::EngineOptions::from_args(std::env::args().skip(1));
+    if options.check_patterns {
+        let params = crate::pattern_check::AnalysisParams::default();
+        let ok = crate::pattern_check::check_pattern_files(std::path::Path::new(crate::pattern_check::PATTERN_DIR), &params);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+    if let Some(name) = &options.view_pattern {
+        let ok = crate::pattern_view::view_pattern(name, options.export.as_deref());
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+    if let Some(seconds) = options.headless {
+        run_headless(seconds, options);
+        return;
+    }
+
     // Initialize logging, window, etc., the same as before.
     let event_loop = winit::event_loop::EventLoop::new().expect("Failed to create event loop");
     let window = winit::window::WindowBuilder::new()
@@ -17,7 +39,7 @@ pub async fn run() {
     // On WASM, insert the canvas, etc. (omitted here for brevity)
 
     // Create our Engine
-    let mut engine = crate::engine::Engine::new(&window).await;
+    let mut engine = crate::engine::Engine::new(&window, options).await;
 
     #[cfg(not(target_arch = "wasm32"))]
     event_loop
@@ -40,16 +62,58 @@ pub async fn run() {
         .expect("Event loop failed");
 }
 
+/// Runs the game without a window, idle, played by `Autoplay` or playing back a replay.
+fn run_headless(seconds: f32, options: crate::engine::EngineOptions) {
+    use crate::audio::Audio;
+    use crate::headless::{HeadlessRunner, InputScript, Stage};
+
+    let audio = match options.record_audio.as_deref() {
+        Some(path) => Audio::open(Some(path)),
+        None => Audio::silent(),
+    };
+    let result = match &options.play_replay {
+        Some(path) => {
+            let replay = match crate::replay::Replay::load(path) {
+                Ok(replay) => replay,
+                Err(err) => {
+                    eprintln!("Failed to load replay {}: {}", path.display(), err);
+                    std::process::exit(1);
+                }
+            };
+            match HeadlessRunner::run_replay_with_audio(replay, audio) {
+                Ok(result) => result,
+                Err(desync) => {
+                    eprintln!("{}", desync);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            let stage = options.stage.as_deref().map_or(Stage::first(), Stage::find_or_first);
+            let mut runner = HeadlessRunner::playing_stage(crate::time::time_seed(), stage);
+            runner.audio = audio;
+            let result = match options.autoplay {
+                true => runner.run_autoplay(seconds),
+                false => runner.run(seconds, &InputScript::new()),
+            };
+            runner.finish_audio();
+            result
+        }
+    };
+    println!(
+        "Headless run: {} steps, {:.2} s, game over: {}, checksum {:08x}",
+        result.steps, result.time, result.game_over, result.checksum
+    );
+}
+
 pub async fn event_loop_handler(
     event: Event<()>,
     event_loop_window_target: &EventLoopWindowTarget<()>,
     engine: &mut crate::engine::Engine<'_>,
 ) {
     match event {
-        Event::WindowEvent { event, window_id } if window_id == engine.window.id() => {
-            if !engine.input(&event) {
-                engine.handle_window_event(&event, event_loop_window_target).await;
-            }
+        Event::WindowEvent { event, window_id } if window_id == engine.window.id() && !engine.input(&event) => {
+            engine.handle_window_event(&event, event_loop_window_target).await;
         }
         _ => {}
     }
@@ -0,0 +1,64 @@
+// Checks the obstacle pattern files without running the game, for
+// `--check-patterns` and for tools that rate patterns.
+use std::{fs, path::Path};
+
+use crate::game::{parse_patterns, pattern_files};
+
+pub use crate::game::{analyze_pattern, AnalysisParams, Dodge, Pattern, PatternAnalysis, PATTERN_DIR};
+
+/// Parses every pattern file in `dir` and analyzes its patterns with
+/// `params`, printing a line per pattern. Returns whether all files parse and
+/// all their patterns are solvable.
+pub fn check_pattern_files(dir: &Path, params: &AnalysisParams) -> bool {
+    let files = match pattern_files(dir) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+    let mut ok = true;
+    for file in files {
+        let patterns = fs::read_to_string(&file)
+            .map_err(|err| format!("{}: {}", file.display(), err))
+            .and_then(|source| parse_patterns(&source, Some(&file)).map_err(|err| err.to_string()));
+        let patterns = match patterns {
+            Ok(patterns) => patterns,
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+                continue;
+            }
+        };
+        println!("{}", file.display());
+        for pattern in &patterns {
+            let analysis = analyze_pattern(pattern, params);
+            if analysis.solvable() {
+                println!("  {}", analysis);
+            } else {
+                eprintln!("  {}", analysis);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_patterns_pass_and_broken_files_fail() {
+        assert!(check_pattern_files(Path::new(PATTERN_DIR), &AnalysisParams::default()));
+
+        let dir = std::env::temp_dir().join(format!("pattern-check-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("closed.pattern"), "pattern closed\nrepeat 6 lane 1\nwall 0 1 0\nend\n").unwrap();
+        assert!(!check_pattern_files(&dir, &AnalysisParams::default()));
+        fs::write(dir.join("closed.pattern"), "pattern broken\nwall 0 one 0\n").unwrap();
+        assert!(!check_pattern_files(&dir, &AnalysisParams::default()));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!check_pattern_files(&dir, &AnalysisParams::default()));
+    }
+}
@@ -0,0 +1,94 @@
+// Shows obstacle patterns as text on the terminal or in a file, for
+// `--view-pattern`, see `AsciiView`.
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    path::Path,
+};
+
+use crate::game::{load_patterns_dir, PATTERN_DIR};
+
+pub use crate::game::{AsciiView, Pattern};
+
+/// Finds the pattern `name` in the pattern files and writes all of it to
+/// `export`, or pages through it on the terminal. Returns whether the
+/// pattern was found and shown.
+pub fn view_pattern(name: &str, export: Option<&Path>) -> bool {
+    let patterns = match load_patterns_dir(Path::new(PATTERN_DIR)) {
+        Ok(patterns) => patterns,
+        Err(err) => {
+            eprintln!("{}", err);
+            return false;
+        }
+    };
+    let Some(pattern) = patterns.iter().find(|pattern| pattern.name == name) else {
+        let names: Vec<&str> = patterns.iter().map(|pattern| pattern.name.as_str()).collect();
+        eprintln!("No pattern {}, the patterns are: {}", name, names.join(", "));
+        return false;
+    };
+    let view = AsciiView { color: io::stdout().is_terminal(), ..AsciiView::default() };
+    match export {
+        Some(path) => match view.export_pattern(pattern, path) {
+            Ok(()) => {
+                println!("Wrote {} to {}", pattern.name, path.display());
+                true
+            }
+            Err(err) => {
+                eprintln!("Failed to write {}: {}", path.display(), err);
+                false
+            }
+        },
+        None => {
+            page(&view, pattern, io::stdin().lock());
+            true
+        }
+    }
+}
+
+/// Prints `pattern` a page at a time. Enter shows the next page, `b` the one
+/// before, a number of seconds jumps there and `q` or the end of the input quits.
+fn page(view: &AsciiView, pattern: &Pattern, mut input: impl BufRead) {
+    let rows = view.pattern_rows(pattern);
+    let mut first_row = 0;
+    loop {
+        print!("{}", view.render_pattern(pattern, first_row));
+        print!("rows {}-{} of {}, Enter: next, b: back, <seconds>: jump, q: quit ", first_row, first_row + view.rows, rows);
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if input.read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            return;
+        }
+        first_row = match line.trim() {
+            "q" => return,
+            "b" => first_row.saturating_sub(view.rows),
+            "" if first_row + view.rows >= rows => return,
+            "" => first_row + view.rows,
+            seconds => match seconds.parse::<f32>() {
+                Ok(seconds) => ((seconds.max(0.0) / view.row_time) as usize).min(rows.saturating_sub(1)),
+                Err(_) => first_row,
+            },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_a_bundled_pattern_and_pages_to_the_end() {
+        let path = std::env::temp_dir().join(format!("pattern-view-test-{}.txt", std::process::id()));
+        assert!(view_pattern("spiral", Some(&path)));
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(text.starts_with("spiral (6 lanes"));
+        assert!(text.contains('X'));
+        assert!(!view_pattern("no_such_pattern", Some(&path)));
+        assert!(!path.exists());
+
+        // jumping, going back and running out of input all end the pager
+        let patterns = load_patterns_dir(Path::new(PATTERN_DIR)).unwrap();
+        page(&AsciiView::default(), &patterns[0], "\n2.5\nb\n".as_bytes());
+        page(&AsciiView::default(), &patterns[0], "\n".repeat(100).as_bytes());
+    }
+}
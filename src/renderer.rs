@@ -4,10 +4,12 @@ use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+use crate::audio::BandLevels;
 use crate::common::camera;
 use crate::game::{self, Game};
-use crate::input::{InputHandler, InputGetInterface};
+use crate::input::{InputHandler, InputGetInterface, MAX_PLAYERS};
 use crate::time::get_time_since_start;
+use crate::time_scale::TimeScale;
 
 const MAX_WIDTH_WEB: u32 = 480;
 const MAX_HEIGHT_WEB: u32 = 270;
@@ -54,6 +56,16 @@ pub struct EngineUniforms {
     pub mouse_x: f32,
     pub mouse_y: f32,
     //
+    /// Band levels of the music from 0 to 1, see `audio::BandEnvelopes`.
+    pub bass: f32,
+    pub mid: f32,
+    pub high: f32,
+    /// 0 on a beat, rising to 1 right before the next one.
+    pub beat_phase: f32,
+    //
+    /// Game seconds per wall-clock second, `time` runs at this speed, see `TimeScale`.
+    pub time_scale: f32,
+    pub _padding: [f32; 3],
 }
 
 #[repr(C)]
@@ -83,11 +95,46 @@ impl CameraUniforms {
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct GameUniforms {
-    player_angle: f32,
-    player_width: f32,
-    _padding: [f32; 2],
-    player_position: [f32; 4],
-    player_tangent: [f32; 4],
+    /// Number of polygon sides, fractional while the lane count morphs, see `LaneShape`.
+    lane_count: f32,
+    /// World angle of the arena, see `Game::arena_rotation`.
+    arena_rotation: f32,
+    /// Rings of the stage, see `Stage::rings`.
+    ring_count: u32,
+    /// Seconds of slow time left, of the player with the most.
+    slow_time: f32,
+    /// Color palettes to blend, see `Game::palette`.
+    palette_from: u32,
+    palette_to: u32,
+    palette_blend: f32,
+    /// 1 right after a near miss, fading to 0, see `Game::near_miss_glow`.
+    near_miss_glow: f32,
+    players: [PlayerUniform; MAX_PLAYERS],
+    /// The nearest power-ups on their way: world x and z, `PowerUpKind::material`
+    /// and 0, all zeros for an empty slot.
+    power_ups: [[f32; 4]; MAX_POWER_UPS],
+}
+
+/// Power-ups drawn at once, as in game_uniforms.wgsl.
+const MAX_POWER_UPS: usize = 8;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PlayerUniform {
+    position: [f32; 4],
+    tangent: [f32; 4],
+    /// Half the width of the player on the ring.
+    width: f32,
+    /// `Player::MATERIALS`, 0 for a player who is out or not playing.
+    material: u32,
+    /// 1 while the player's shield is up.
+    shield: f32,
+    /// Seconds left of the player's wide gaps.
+    wide_gap: f32,
+}
+
+impl PlayerUniform {
+    const EMPTY: Self = Self { position: [0.0; 4], tangent: [0.0; 4], width: 0.0, material: 0, shield: 0.0, wide_gap: 0.0 };
 }
 
 #[repr(C)]
@@ -103,12 +150,10 @@ pub struct ObstacleUniform {
     start: f32,
     end: f32,
     lane: u32,
-    _padding: [f32; 1],
-}
-
-fn smoothstep(edge0: f32, edge1: f32, t: f32) -> f32 {
-    let t = ((t - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
-    t * t * (3.0 - 2.0 * t)
+    motion: u32,
+    /// `ObstacleHeight` as 0 (full), 1 (low) or 2 (high).
+    height: u32,
+    _padding: u32,
 }
 
 fn vector3_to_array4(v: Vector3<f32>) -> [f32; 4] {
@@ -120,15 +165,23 @@ fn mat2x2_to_array4(m: cgmath::Matrix2<f32>) -> [f32; 4] {
 }
 
 impl ObstacleUniform {
-    pub fn new(lane: i32, start: f32, end: f32) -> Self {
-        let angle = lane as f32 / (6 as f32) * std::f32::consts::PI * 2.0;
+    pub fn new(lane: i32, start: f32, end: f32, lane_width: f32) -> Self {
+        Self::with_offset(lane, 0.0, start, end, lane_width, 0, 0.0)
+    }
+
+    /// Obstacle turned `lane_offset` lanes away from `lane`, see `Obstacle::lane_offset`,
+    /// in an arena turned by `arena_rotation`.
+    pub fn with_offset(lane: i32, lane_offset: f32, start: f32, end: f32, lane_width: f32, motion: u32, arena_rotation: f32) -> Self {
+        let angle = (lane as f32 + lane_offset) * lane_width + arena_rotation;
         let rotation = cgmath::Matrix2::from_angle(cgmath::Rad(-angle));
         Self {
             lane:lane.try_into().unwrap(),
             start,
             end,
             rotation: mat2x2_to_array4(-rotation),
-            _padding: [0.0],
+            motion,
+            height: 0,
+            _padding: 0,
         }
     }
 }
@@ -244,7 +297,7 @@ impl<'a> Renderer<'a> {
                 let u = cgmath::Vector3::new(p3.y + 33.33, p3.z + 33.33, p3.x + 33.33);
                 let v = cgmath::dot(p3, u);
                 p3 += cgmath::Vector3::new(v, v, v);
-                let value = ((p3.x + p3.y) * p3.z).fract();
+                let _value = ((p3.x + p3.y) * p3.z).fract();
                 // fn hash12(p: vec2f) -> f32 {
                 //     var p3 = fract(vec3f(p.xyx) * 0.1031);
                 //     p3 += dot(p3, p3.yzx + 33.33);
@@ -273,7 +326,7 @@ impl<'a> Renderer<'a> {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: format,
+            format,
             usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[format],
         });
@@ -341,7 +394,7 @@ impl<'a> Renderer<'a> {
             device,
             queue,
             config,
-            noise_texture_bind_group: noise_texture_bind_group,
+            noise_texture_bind_group,
             render_frame: 0,
             render_pipeline,
             uniforms,
@@ -361,14 +414,13 @@ impl<'a> Renderer<'a> {
             include_str!("shaders/shaderbuild/main_scene.wgsl").into()
         } else {
             let current_dir = std::env::current_dir().unwrap();
-            std::fs::read_to_string("src/shaders/shaderbuild/main_scene.wgsl").expect(
-                format!(
+            std::fs::read_to_string("src/shaders/shaderbuild/main_scene.wgsl").unwrap_or_else(|_| {
+                panic!(
                     "Failed to read shader file {}{}",
                     current_dir.display(),
                     "shaders/main_scene.wgsl"
                 )
-                .as_str(),
-            )
+            })
         };
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -379,7 +431,7 @@ impl<'a> Renderer<'a> {
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: bind_group_layouts,
+                bind_group_layouts,
                 push_constant_ranges: &[],
             });
 
@@ -433,6 +485,12 @@ impl<'a> Renderer<'a> {
             global_time: 0.0,
             mouse_x: 80.0,
             mouse_y: 80.0,
+            bass: 0.0,
+            mid: 0.0,
+            high: 0.0,
+            beat_phase: 0.0,
+            time_scale: 1.0,
+            _padding: [0.0; 3],
         };
 
         let engine_uniforms_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -461,11 +519,16 @@ impl<'a> Renderer<'a> {
 
         // Game uniform data
         let game_uniforms = GameUniforms {
-            player_angle: 0.0,
-            player_width: 0.5,
-            _padding: [0.0; 2],
-            player_position: [0.0, 0.0, 0.0, 0.0],
-            player_tangent: [0.0, 0.0, 0.0, 0.0],
+            lane_count: Game::DEFAULT_LANE_COUNT as f32,
+            arena_rotation: 0.0,
+            ring_count: 1,
+            slow_time: 0.0,
+            palette_from: 0,
+            palette_to: 0,
+            palette_blend: 0.0,
+            near_miss_glow: 0.0,
+            players: [PlayerUniform::EMPTY; MAX_PLAYERS],
+            power_ups: [[0.0; 4]; MAX_POWER_UPS],
         };
         let game_uniforms_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Game Uniforms Buffer"),
@@ -473,7 +536,7 @@ impl<'a> Renderer<'a> {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
-        let obstacle_uniforms = vec![ObstacleUniform::new(0, 10.0, 20.0); 24];
+        let obstacle_uniforms = vec![ObstacleUniform::new(0, 10.0, 20.0, game::LaneShape::regular(Game::DEFAULT_LANE_COUNT).lane_width()); 24];
         let obstacle_globals = ObstactleGlobalUniform { count: 24 };
 
         let obstacle_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -584,41 +647,83 @@ impl<'a> Renderer<'a> {
         }
     }
 
-    fn update_engine_uniforms(&mut self, mouse_x: f32, mouse_y: f32) -> () {
+    fn update_engine_uniforms(&mut self, mouse_x: f32, mouse_y: f32, music: BandLevels, beat_phase: f32, time_scale: &TimeScale) {
         let engine_uniforms = &mut self.uniforms.engine_uniforms;
-        engine_uniforms.time = self.render_frame as f32 / 60.0; //TODO use actual time instead
+        engine_uniforms.time = time_scale.time();
+        engine_uniforms.time_scale = time_scale.scale();
         engine_uniforms.frame = self.render_frame;
         engine_uniforms.global_time = get_time_since_start() as f32;
         engine_uniforms.mouse_x = mouse_x;
         engine_uniforms.mouse_y = mouse_y;
+        engine_uniforms.bass = music.bass;
+        engine_uniforms.mid = music.mid;
+        engine_uniforms.high = music.high;
+        engine_uniforms.beat_phase = beat_phase;
     }
 
-    fn update_game_uniforms(&mut self, game: &game::Game) -> () {
+    fn update_game_uniforms(&mut self, game: &game::Game) {
         let game_uniforms = &mut self.uniforms.game_uniforms;
-        game_uniforms.player_angle = game.player_angle;
-        game_uniforms.player_width = game.player_width * Game::PLAYER_RADIUS / 2.0;
-        let player_position = game.player_position();
-        game_uniforms.player_position = vector3_to_array4(player_position);
-        game_uniforms.player_tangent = vector3_to_array4(player_position.normalize().cross(Vector3::unit_y()));
+        game_uniforms.lane_count = game.lane_shape().sides;
+        game_uniforms.arena_rotation = game.arena_rotation();
+        (game_uniforms.palette_from, game_uniforms.palette_to, game_uniforms.palette_blend) = game.palette();
+        game_uniforms.near_miss_glow = game.near_miss_glow();
+        for (i, slot) in game_uniforms.players.iter_mut().enumerate() {
+            *slot = match game.players.get(i).filter(|player| player.alive) {
+                Some(player) => {
+                    let position = game.player_position(i);
+                    PlayerUniform {
+                        position: vector3_to_array4(position),
+                        tangent: vector3_to_array4(position.normalize().cross(Vector3::unit_y())),
+                        width: player.width * player.radius() / 2.0,
+                        material: game::Player::MATERIALS[i],
+                        shield: if player.power_ups.shield { 1.0 } else { 0.0 },
+                        wide_gap: player.power_ups.wide_gap,
+                    }
+                }
+                None => PlayerUniform::EMPTY,
+            };
+        }
+        game_uniforms.slow_time = game.players.iter().map(|player| player.power_ups.slow_time).fold(0.0, f32::max);
+        game_uniforms.ring_count = game.stage().rings as u32;
+        let shape = game.lane_shape();
+        let power_ups = game.get_power_ups_all();
+        for (i, slot) in game_uniforms.power_ups.iter_mut().enumerate() {
+            *slot = match power_ups.get(i) {
+                Some(power_up) => {
+                    let angle = shape.lane_center(power_up.lane) + game.arena_rotation();
+                    let (x, z) = (angle.cos() * power_up.distance, angle.sin() * power_up.distance);
+                    [x, z, power_up.kind.material() as f32, 0.0]
+                }
+                None => [0.0; 4],
+            };
+        }
     }
 
-    fn update_camera_uniforms(&mut self, camera: &camera::Camera) -> () {
+    fn update_camera_uniforms(&mut self, camera: &camera::Camera) {
         self.uniforms.camera_uniforms = CameraUniforms::new(camera.eye.to_vec(), camera.direction(), camera.up);
     }
 
-    fn update_obstacles(&mut self, obstacles: Vec<game::Obstacle>) -> () {
+    fn update_obstacles(&mut self, obstacles: Vec<game::Obstacle>, lane_width: f32, arena_rotation: f32) {
         let obstacle_data = &mut self.uniforms.obstacle_uniforms;
         let length = obstacles.len().min(24);
         for i in 0..24 {
             if i >= length {
-                obstacle_data[i] = ObstacleUniform::new(0, 0.0, 0.0);
+                obstacle_data[i] = ObstacleUniform::new(0, 0.0, 0.0, lane_width);
                 continue;
             }
             let obs = &obstacles[i];
             let lane = obs.lane;
-            let start = obs.start;
-            let end = obs.end;
-            obstacle_data[i] = ObstacleUniform::new(lane as i32, start, end);
+            let start = obs.current_start();
+            let end = obs.current_end();
+            let height = match obs.height {
+                game::ObstacleHeight::Full => 0,
+                game::ObstacleHeight::Low => 1,
+                game::ObstacleHeight::High => 2,
+            };
+            obstacle_data[i] = ObstacleUniform {
+                height,
+                ..ObstacleUniform::with_offset(lane as i32, obs.lane_offset(), start, end, lane_width, obs.motion.flags(), arena_rotation)
+            };
         }
 
         self.uniforms.obstacle_globals.count = obstacles.len() as i32;
@@ -659,11 +764,11 @@ impl<'a> Renderer<'a> {
 
     }
 
-    pub fn render(&mut self, game: &Game, input: &InputHandler) -> Result<(), wgpu::SurfaceError> {
-        self.update_engine_uniforms(input.get_mouse_x(), input.get_mouse_y());
+    pub fn render(&mut self, game: &Game, input: &InputHandler, music: BandLevels, time_scale: &TimeScale) -> Result<(), wgpu::SurfaceError> {
+        self.update_engine_uniforms(input.get_mouse_x(), input.get_mouse_y(), music, game.beat_phase(), time_scale);
         self.update_camera_uniforms(&game.camera);
         self.update_game_uniforms(game);
-        self.update_obstacles(game.get_obstacles_all());
+        self.update_obstacles(game.get_obstacles_all(), game.lane_shape().lane_width(), game.arena_rotation());
 
         self.write_uniform_buffers();
 
@@ -0,0 +1,400 @@
+// Recording and deterministic playback of runs.
+//
+// A replay stores the stage, the spawner seed and, for every game update, the dt, the
+// pressed state of each `InputID` for each player and the music position the game was synced
+// to. Feeding the same dt, key states and music positions into a `Game` built from the same
+// stage and seed reproduces the run, starting from the title screen. Every
+// `CHECKSUM_INTERVAL` updates the recorder also stores `Game::state_checksum`,
+// which playback compares against to detect desyncs.
+//
+// File layout, all values little endian:
+//   magic        4 bytes  "HXRP"
+//   version      u16
+//   seed         u64
+//   stage        u8 length, then the UTF-8 id of the `Stage`
+//   frame count  u32
+//   frames       frame count * (dt: f32, MAX_PLAYERS * pressed keys: u8 bitmask over InputID::ALL,
+//                music elapsed: f64, negative without music, music position: f32)
+//   check count  u32
+//   checksums    check count * (frame index: u32, checksum: u32)
+//
+// Older versions are still read, from `MIN_REPLAY_VERSION` on. They have no music
+// positions, so their timeline runs on game time:
+//   2  no stage, the run was played on `Stage::first`, and one byte of keys per frame
+//   3  one byte of keys per frame, for the only player
+//   4  no music positions
+// Version 1 runs started without the title screen, which every run starts
+// from now, so they can't be played back.
+use std::{
+    fmt,
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::game::{Game, MusicClock, Stage};
+use crate::input::{InputGetInterface, InputID, VirtualInput, MAX_PLAYERS};
+
+const MAGIC: &[u8; 4] = b"HXRP";
+pub const REPLAY_VERSION: u16 = 5;
+/// Oldest version `Replay::from_bytes` reads.
+pub const MIN_REPLAY_VERSION: u16 = 2;
+pub const CHECKSUM_INTERVAL: usize = 30;
+/// Bytes of a `ReplayFrame`.
+const FRAME_SIZE: usize = 4 + MAX_PLAYERS + 12;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    NotAReplay,
+    UnsupportedVersion(u16),
+    Truncated,
+    /// The stage id is not valid UTF-8.
+    InvalidStage,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "{}", err),
+            ReplayError::NotAReplay => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported replay version {} (expected {} to {})", version, MIN_REPLAY_VERSION, REPLAY_VERSION)
+            }
+            ReplayError::Truncated => write!(f, "replay file is truncated"),
+            ReplayError::InvalidStage => write!(f, "replay has an invalid stage id"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(err: io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayFrame {
+    pub dt: f32,
+    /// Keys of each player, bit `i` is set if `InputID::ALL[i]` is pressed.
+    pub keys: [u8; MAX_PLAYERS],
+    /// Music position after the update, see `Game::sync_music`.
+    pub music: Option<MusicClock>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    /// Id of the `Stage` the run was played on.
+    pub stage: String,
+    pub frames: Vec<ReplayFrame>,
+    pub checksums: Vec<(u32, u32)>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, stage: &str) -> Self {
+        Self { seed, stage: stage.to_string(), frames: vec![], checksums: vec![] }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let stage = &self.stage.as_bytes()[..self.stage.len().min(u8::MAX as usize)];
+        let mut out = Vec::with_capacity(19 + stage.len() + 4 + self.frames.len() * FRAME_SIZE + self.checksums.len() * 8);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.push(stage.len() as u8);
+        out.extend_from_slice(stage);
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            out.extend_from_slice(&frame.dt.to_le_bytes());
+            out.extend_from_slice(&frame.keys);
+            let (elapsed, position) = frame.music.map_or((-1.0, 0.0), |clock| (clock.elapsed, clock.position));
+            out.extend_from_slice(&elapsed.to_le_bytes());
+            out.extend_from_slice(&position.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.checksums.len() as u32).to_le_bytes());
+        for (frame, checksum) in &self.checksums {
+            out.extend_from_slice(&frame.to_le_bytes());
+            out.extend_from_slice(&checksum.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = ByteReader { bytes, position: 0 };
+        if reader.take(4).map_err(|_| ReplayError::NotAReplay)? != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = reader.u16()?;
+        if !(MIN_REPLAY_VERSION..=REPLAY_VERSION).contains(&version) {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = reader.u64()?;
+        let stage = match version {
+            2 => Stage::first().id.to_string(),
+            _ => {
+                let stage_len = reader.take(1)?[0] as usize;
+                std::str::from_utf8(reader.take(stage_len)?)
+                    .map_err(|_| ReplayError::InvalidStage)?
+                    .to_string()
+            }
+        };
+
+        // versions before 4 have the keys of the first player only
+        let players = if version < 4 { 1 } else { MAX_PLAYERS };
+        let frame_size = if version < 5 { 4 + players } else { FRAME_SIZE };
+        let frame_count = reader.u32()? as usize;
+        let mut frames = Vec::with_capacity(frame_count.min(bytes.len() / frame_size));
+        for _ in 0..frame_count {
+            let dt = f32::from_bits(reader.u32()?);
+            let mut keys = [0; MAX_PLAYERS];
+            keys[..players].copy_from_slice(reader.take(players)?);
+            let mut music = None;
+            if version >= 5 {
+                let elapsed = f64::from_bits(reader.u64()?);
+                let position = f32::from_bits(reader.u32()?);
+                music = (elapsed >= 0.0).then_some(MusicClock { elapsed, position });
+            }
+            frames.push(ReplayFrame { dt, keys, music });
+        }
+
+        let checksum_count = reader.u32()? as usize;
+        let mut checksums = Vec::with_capacity(checksum_count.min(bytes.len() / 8));
+        for _ in 0..checksum_count {
+            checksums.push((reader.u32()?, reader.u32()?));
+        }
+        Ok(Self { seed, stage, frames, checksums })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ReplayError> {
+        let end = self.position + count;
+        if end > self.bytes.len() {
+            return Err(ReplayError::Truncated);
+        }
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, ReplayError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ReplayError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ReplayError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+pub struct ReplayRecorder {
+    pub replay: Replay,
+}
+
+impl ReplayRecorder {
+    pub fn new(seed: u64, stage: &str) -> Self {
+        Self { replay: Replay::new(seed, stage) }
+    }
+
+    /// Call before `Game::update` with the dt and input passed to it.
+    pub fn record_input(&mut self, dt: f32, input: &dyn InputGetInterface) {
+        let keys = std::array::from_fn(|player| {
+            InputID::ALL
+                .iter()
+                .filter(|&&key| input.get_player_key_state(player, key).pressed)
+                .fold(0, |keys, &key| keys | key.bit())
+        });
+        self.replay.frames.push(ReplayFrame { dt, keys, music: None });
+    }
+
+    /// Call after `Game::update` and `Game::sync_music`.
+    pub fn record_state(&mut self, game: &Game) {
+        let frame = self.replay.frames.len() - 1;
+        self.replay.frames[frame].music = game.music_clock();
+        if frame.is_multiple_of(CHECKSUM_INTERVAL) {
+            self.replay.checksums.push((frame as u32, game.state_checksum()));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: u32,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "replay desync at frame {}: expected checksum {:08x}, got {:08x}",
+            self.frame, self.expected, self.actual
+        )
+    }
+}
+
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    pub input: VirtualInput,
+    frame: usize,
+    next_checksum: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            input: VirtualInput::new(),
+            frame: 0,
+            next_checksum: 0,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.replay.frames.len()
+    }
+
+    /// Advances to the next frame and returns its dt, the key states are
+    /// available from `self.input`. Returns `None` at the end of the replay.
+    pub fn next_frame(&mut self) -> Option<f32> {
+        let frame = *self.replay.frames.get(self.frame)?;
+        self.input.set_pressed_masks(frame.keys);
+        self.frame += 1;
+        Some(frame.dt)
+    }
+
+    /// Call after the audio of the frame is updated: syncs the game to the
+    /// recorded music position, which the music playing now needn't match.
+    pub fn sync_music(&self, game: &mut Game) {
+        if let Some(frame) = self.frame.checked_sub(1).and_then(|frame| self.replay.frames.get(frame)) {
+            game.sync_music(frame.music);
+        }
+    }
+
+    /// Call after `Game::update`, compares the game state against the
+    /// recorded checksum for the frame just played, if there is one.
+    pub fn verify(&mut self, game: &Game) -> Result<(), Desync> {
+        let frame = (self.frame - 1) as u32;
+        let Some(&(checksum_frame, expected)) = self.replay.checksums.get(self.next_checksum) else {
+            return Ok(());
+        };
+        if checksum_frame != frame {
+            return Ok(());
+        }
+        self.next_checksum += 1;
+        let actual = game.state_checksum();
+        if actual != expected {
+            return Err(Desync { frame, expected, actual });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_replay() -> Replay {
+        let mut replay = Replay::new(0xDEAD_BEEF_1234, "hexagoner");
+        for i in 0..100 {
+            let music = (i >= 10).then(|| MusicClock { elapsed: i as f64 / 60.0, position: (i % 60) as f32 / 60.0 });
+            replay.frames.push(ReplayFrame {
+                dt: 1.0 / 60.0 + i as f32 * 1e-5,
+                keys: [(i % 64) as u8, (i * 7 % 64) as u8],
+                music,
+            });
+        }
+        replay.checksums = vec![(0, 1), (30, 0xFFFF_FFFF), (60, 12345)];
+        replay
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let replay = sample_replay();
+        let bytes = replay.to_bytes();
+        assert_eq!(bytes.len(), 18 + 1 + 9 + 100 * 18 + 4 + 3 * 8);
+        assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+    }
+
+    /// `replay` in the layout of `version`, without music and, before version
+    /// 4, with the first player only.
+    fn older_bytes(replay: &Replay, version: u16) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&version.to_le_bytes());
+        out.extend_from_slice(&replay.seed.to_le_bytes());
+        if version >= 3 {
+            out.push(replay.stage.len() as u8);
+            out.extend_from_slice(replay.stage.as_bytes());
+        }
+        out.extend_from_slice(&(replay.frames.len() as u32).to_le_bytes());
+        for frame in &replay.frames {
+            out.extend_from_slice(&frame.dt.to_le_bytes());
+            match version {
+                4 => out.extend_from_slice(&frame.keys),
+                _ => out.push(frame.keys[0]),
+            }
+        }
+        out.extend_from_slice(&(replay.checksums.len() as u32).to_le_bytes());
+        for (frame, checksum) in &replay.checksums {
+            out.extend_from_slice(&frame.to_le_bytes());
+            out.extend_from_slice(&checksum.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn reads_older_versions() {
+        let mut replay = sample_replay();
+        for frame in &mut replay.frames {
+            frame.music = None;
+        }
+        assert_eq!(Replay::from_bytes(&older_bytes(&replay, 4)).unwrap(), replay);
+
+        for frame in &mut replay.frames {
+            frame.keys[1] = 0;
+        }
+        assert_eq!(Replay::from_bytes(&older_bytes(&replay, 3)).unwrap(), replay);
+
+        let loaded = Replay::from_bytes(&older_bytes(&replay, 2)).unwrap();
+        assert_eq!(loaded.stage, Stage::first().id);
+        assert_eq!(Replay { stage: replay.stage.clone(), ..loaded }, replay);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let bytes = sample_replay().to_bytes();
+        assert!(matches!(Replay::from_bytes(b"nope"), Err(ReplayError::NotAReplay)));
+        assert!(matches!(Replay::from_bytes(&bytes[..bytes.len() - 1]), Err(ReplayError::Truncated)));
+        let mut newer = bytes.clone();
+        newer[4] = REPLAY_VERSION as u8 + 1;
+        assert!(matches!(Replay::from_bytes(&newer), Err(ReplayError::UnsupportedVersion(v)) if v == REPLAY_VERSION + 1));
+        let mut first = bytes.clone();
+        first[4] = 1;
+        assert!(matches!(Replay::from_bytes(&first), Err(ReplayError::UnsupportedVersion(1))));
+        let mut bad_stage = bytes.clone();
+        bad_stage[15] = 0xFF;
+        assert!(matches!(Replay::from_bytes(&bad_stage), Err(ReplayError::InvalidStage)));
+    }
+}
@@ -1,2 +1,4 @@
 const DEG_TO_RAD = 0.01745329252;
 const UP = vec3f(0.0, 1.0, 0.0);
+const PI = 3.14159265;
+const TAU = 6.28318531;
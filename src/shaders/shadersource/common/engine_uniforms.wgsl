@@ -9,4 +9,15 @@ struct EngineUniforms {
     global_time: f32,
     mouse_x: f32,
     mouse_y: f32,
+
+    // music band levels, 0 to 1
+    bass: f32,
+    mid: f32,
+    high: f32,
+    // 0 on a beat, rising to 1 right before the next one
+    beat_phase: f32,
+
+    // game seconds per wall-clock second, time runs at this speed
+    time_scale: f32,
+    _padding: array<f32, 3>,
 };
@@ -1,8 +1,39 @@
+const MAX_POWER_UPS = 8;
+const MAX_PLAYERS = 2;
+// distance between the rings the player can be on, Game::RING_SPACING
+const RING_SPACING = 1.5;
+
+
+struct Player {
+    position: vec4<f32>,
+    // direction along the ring
+    tangent: vec4<f32>,
+    // half the width on the ring
+    width: f32,
+    // material id, 0 for a player who is out or not playing
+    material: u32,
+    // 1 while the shield is up
+    shield: f32,
+    // seconds left of the wide gaps
+    wide_gap: f32,
+}
 
 struct GameUniforms {
-    player_angle: f32,
-    player_width: f32,
-    _padding: vec2<f32>,
-    player_position: vec4<f32>,
-    player_tangent: vec4<f32>,
+    // number of polygon sides, fractional while the lane count morphs
+    lane_count: f32,
+    // world angle of the arena, lanes and obstacles turn with it
+    arena_rotation: f32,
+    // rings the players hop between, an outline is drawn inside each
+    ring_count: u32,
+    // seconds left of slow time
+    slow_time: f32,
+    // color palettes to blend, see palette_color in main_scene.wgsl
+    palette_from: u32,
+    palette_to: u32,
+    palette_blend: f32,
+    // 1 right after a near miss, fading to 0
+    near_miss_glow: f32,
+    players: array<Player, MAX_PLAYERS>,
+    // pickups on the way: world x and z, material id (0 for an empty slot), unused
+    power_ups: array<vec4<f32>, MAX_POWER_UPS>,
 }
\ No newline at end of file
@@ -3,7 +3,11 @@ struct Obstacle {
     start: f32,
     end: f32,
     lane: u32,
-    _padding: f32,
+    // ObstacleMotion flags, 0 for a static wall
+    motion: u32,
+    // ObstacleHeight: 0 full, 1 low (jumped over), 2 high (ducked under)
+    height: u32,
+    _padding: u32,
 }
 
 struct ObstacleGlobal {
@@ -127,16 +127,44 @@ struct Material {
     _padding: f32,
 }
 
+// Palette slots
+const PALETTE_GROUND = 0u;
+const PALETTE_OBSTACLE = 1u;
+const PALETTE_OUTLINE = 2u;
+const PALETTE_MOVING_OBSTACLE = 3u;
+
+// Color of `slot` in `palette`, Game::PALETTE_COUNT palettes of four slots
+fn palette_entry(palette: u32, slot: u32) -> vec3f {
+    var colors = array<vec3f, 16>(
+        // 0: red
+        vec3f(0.1, 0.02, 0.02), vec3f(0.7, 0.2, 0.2), vec3f(0.9, 0.7, 0.5), vec3f(0.8, 0.35, 0.1),
+        // 1: blue
+        vec3f(0.02, 0.03, 0.1), vec3f(0.2, 0.35, 0.8), vec3f(0.6, 0.8, 1.0), vec3f(0.1, 0.6, 0.9),
+        // 2: green
+        vec3f(0.02, 0.08, 0.03), vec3f(0.25, 0.7, 0.3), vec3f(0.8, 1.0, 0.6), vec3f(0.6, 0.8, 0.1),
+        // 3: purple
+        vec3f(0.06, 0.02, 0.1), vec3f(0.6, 0.2, 0.7), vec3f(1.0, 0.6, 0.9), vec3f(0.9, 0.3, 0.5),
+    );
+    return colors[min(palette, 3u) * 4u + slot];
+}
+
+fn palette_color(slot: u32) -> vec3f {
+    let from_color = palette_entry(g_game.palette_from, slot);
+    let to_color = palette_entry(g_game.palette_to, slot);
+    return mix(from_color, to_color, g_game.palette_blend);
+}
+
 fn material_from_id(id: i32) -> Material {
     var material = Material();
     // Ground
     if (id == 0) {
-        material.color = vec3f(0.1, 0.02, 0.02);
+        // pulses with the mids of the music
+        material.color = palette_color(PALETTE_GROUND) * (1.0 + 2.0 * g_engine.mid);
         material.reflectivity = 0.9;
         // material.roughness = 0.1;
     }
 
-    // Player
+    // Players, see Player::MATERIALS: the first one, the second one after the power-ups
     if (id == 1) {
         material.color = vec3f(0.8, 0.5, 0.3);
         material.reflectivity = 0.8;
@@ -145,10 +173,44 @@ fn material_from_id(id: i32) -> Material {
 
     // Obstacle
     if (id == 2) {
-        material.color = vec3f(0.7, 0.2, 0.2);
+        material.color = palette_color(PALETTE_OBSTACLE);
         material.reflectivity = 0.0;
         // material.roughness = 0.1;
     }
+
+    // Lane outline
+    if (id == 3) {
+        // flashes white with the highs
+        material.color = mix(palette_color(PALETTE_OUTLINE), vec3f(1.0), 0.5 * g_engine.high);
+        material.reflectivity = 0.0;
+    }
+
+    // Moving obstacle
+    if (id == 4) {
+        material.color = palette_color(PALETTE_MOVING_OBSTACLE);
+        material.reflectivity = 0.0;
+    }
+
+    // Power-ups, see PowerUpKind::material: shield, slow time, wide gap.
+    // They keep their colors in every palette so they can be told apart.
+    if (id == 5) {
+        material.color = vec3f(0.3, 0.9, 1.0);
+        material.reflectivity = 0.3;
+    }
+    if (id == 6) {
+        material.color = vec3f(0.7, 0.4, 1.0);
+        material.reflectivity = 0.3;
+    }
+    if (id == 7) {
+        material.color = vec3f(0.5, 1.0, 0.3);
+        material.reflectivity = 0.3;
+    }
+
+    // Second player
+    if (id == 8) {
+        material.color = vec3f(0.35, 0.45, 0.9);
+        material.reflectivity = 0.8;
+    }
     return material;
 }
 
@@ -181,21 +243,9 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     var t = 0.0;
     var p = camera_origin;
     
-    // Vary scene scale
-    // let t_frac = (2.0 * g_engine.global_time) - floor(2.0 * g_engine.global_time);
-    // let t_mod = floor(g_engine.global_time * 2.0) % 4.0;
-    // let foo = (1.0 - t_frac);
-    // var factor = 0.0;
-    // if t_mod == 0.0 {
-    //     factor = foo * 0.07;
-    // } else if t_mod == 1.0 {
-    //     factor = foo * 0.03;
-    // } else if t_mod == 2.0 {
-    //     factor = foo * 0.04;
-    // } else if t_mod == 3.0 {
-    //     factor = foo * 0.03;
-    // }
-    // p *= (1.0 - factor);
+    // Zoom in on the beat, as far as the bass is loud, easing out until the next one
+    let beat_fade = 1.0 - g_engine.beat_phase;
+    p *= 1.0 - 0.06 * g_engine.bass * beat_fade * beat_fade;
     
     let background_color = vec3f(0.2);
     var color = background_color;
@@ -205,6 +255,8 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     var hit_infty = false;
 
     var min_player_distance = 1e20;
+    // shield of the player the ray came closest to
+    var glow_shield = 0.0;
 
     for (bounce = 0; bounce < max_bounces; bounce = bounce + 1) {
         var hit = false;
@@ -228,7 +280,13 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
             }
 
 
-            min_player_distance = min(min_player_distance, player_distance(p));
+            for (var i = 0; i < MAX_PLAYERS; i = i + 1) {
+                let player_dist = player_distance(p, i);
+                if (player_dist < min_player_distance) {
+                    min_player_distance = player_dist;
+                    glow_shield = g_game.players[i].shield;
+                }
+            }
 
             var rnd = modulo(t * 1000.0, 1.0); 
             p += abs(d) * (0.99) * ray_direction;
@@ -277,7 +335,11 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     //     color = mix(color, background_color, 1.0 - transmittance);
     // }
 
-    color += 0.1 * vec3f(1.0, 0.8, 0.6) * clamp(0.2 / (min_player_distance + 0.3), 0.0, 0.3);
+    // the glow around the players flares up on a near miss, and turns the
+    // color of the shield while it is up
+    let player_glow = 0.1 * (1.0 + 4.0 * g_game.near_miss_glow);
+    let glow_color = mix(vec3f(1.0, 0.8, 0.6), vec3f(0.3, 0.9, 1.0), glow_shield);
+    color += player_glow * glow_color * clamp(0.2 / (min_player_distance + 0.3), 0.0, 0.3);
 
     //color = vec3(f32(i) / 10);
     return vec4f(color, 1.0);
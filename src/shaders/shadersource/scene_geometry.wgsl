@@ -189,8 +189,10 @@ fn obstacleSdfPlanes(p: vec3f, i: i32) -> f32 {
     // start = 4.0 - g_engine.global_time * 0.5;
     // end = 4.0;
 
-    var r1 = end * 0.57735;
-    var r2 = start * 0.57735;
+    // half width of the lane per unit of distance from the center
+    var half_width = tan(PI / g_game.lane_count);
+    var r1 = end * half_width;
+    var r2 = start * half_width;
     var height = end - start;
 
     var rot_mat = o.rotation;
@@ -202,11 +204,48 @@ fn obstacleSdfPlanes(p: vec3f, i: i32) -> f32 {
     // var p_2d_rotated = o.rotation * vec2f(p.x, p.z);
     var dist_2d = trapezoidSdf(p2, r1, r2, height * 0.5);
 
-    var OBSTACLE_HEIGHT = 0.5;
+    // vertical extent by height class: a full wall stands from 1 to 2, a low
+    // one stays below a jumping player and a high one leaves room to duck
+    var bottom = 1.0;
+    var top = 2.0;
+    if (o.height == 1u) {
+        top = 1.4;
+    } else if (o.height == 2u) {
+        bottom = 1.2;
+    }
 
     var dist_2d2 = length(p.xz - vec2f(1.0, 0.0)) - 0.5;
 
-    return extrudeSdf(p.y - 1.5, dist_2d, OBSTACLE_HEIGHT);
+    return extrudeSdf(p.y - (bottom + top) * 0.5, dist_2d, (top - bottom) * 0.5);
+}
+
+// Polygon with one side per lane, laid out like LaneShape in src/game/shape.rs:
+// lane 0 is centered at 90 degrees and the last lane takes what is left of the
+// circle, so it grows from / shrinks to a point while the lane count morphs.
+fn lanePolygonSdf(p: vec2f, radius: f32) -> f32 {
+    let sides = g_game.lane_count;
+    let lane_count = i32(ceil(sides - 0.001));
+    let lane_width = TAU / sides;
+    let first_start = PI * 0.5 - lane_width * 0.5 + g_game.arena_rotation;
+    var d = -1e20;
+    for (var k = 0; k < lane_count; k = k + 1) {
+        let start = first_start + f32(k) * lane_width;
+        let end = select(start + lane_width, first_start + TAU, k == lane_count - 1);
+        let center = (start + end) * 0.5;
+        d = max(d, dot(p, vec2f(cos(center), sin(center))) - radius * cos((end - start) * 0.5));
+    }
+    return d;
+}
+
+// Thin outline of the lane polygon inside each of the player's rings
+fn lane_outline_distance(p: vec3f) -> f32 {
+    let polygon = lanePolygonSdf(p.xz, 2.3);
+    var outline = abs(polygon) - 0.06;
+    // the polygon distance is exact enough near its sides to offset it outwards
+    for (var ring = 1u; ring < g_game.ring_count; ring = ring + 1u) {
+        outline = min(outline, abs(polygon - f32(ring) * RING_SPACING) - 0.04);
+    }
+    return extrudeSdf(p.y - 1.1, outline, 0.1);
 }
 
 // fn obstacleSdf(p: vec3f, i: i32) -> f32 {
@@ -233,14 +272,37 @@ fn obstacle_distance(p: vec3f, i: i32) -> f32 {
     return obstacleSdfPlanes(p, i);
 }
 
-fn player_distance(p: vec3f) -> f32 {
-    var position = g_game.player_position.xyz;
-    var width = g_game.player_width;
-    var tangent = g_game.player_tangent.xyz;
+// 1e20 for a player who is out or not playing
+fn player_distance(p: vec3f, i: i32) -> f32 {
+    let player = g_game.players[i];
+    if (player.material == 0u) {
+        return 1e20;
+    }
+    var position = player.position.xyz;
+    var width = player.width;
+    var tangent = player.tangent.xyz;
 
     return vesicaSdf(p, position + tangent * width, position - tangent * width, width + 0.2);
 }
 
+fn players_distance(p: vec3f) -> f32 {
+    var d = 1e20;
+    for (var i = 0; i < MAX_PLAYERS; i = i + 1) {
+        d = min(d, player_distance(p, i));
+    }
+    return d;
+}
+
+// Octahedron spinning at the height of the walls, 1e20 for an empty slot
+fn power_up_distance(p: vec3f, i: i32) -> f32 {
+    let power_up = g_game.power_ups[i];
+    if (power_up.z == 0.0) {
+        return 1e20;
+    }
+    let q = abs(rot(p - vec3f(power_up.x, 1.5, power_up.y), UP, g_engine.time * 2.0));
+    return (q.x + q.y + q.z - 0.5) * sqrt3inv;
+}
+
 struct GroundDistanceMapRval {
     d: f32,
     hex_center: vec3f,
@@ -264,7 +326,8 @@ fn ground_distance(p: vec3f) -> GroundDistanceMapRval {
             // var hash2d = textureSample(t_noise2d, s_noise2d, center_xy).r;
             // var hash2d = -1.0;
             var hash2d = hash12(center_xy);
-            let height_offset = hash2d;
+            // tiles bounce with the bass, each by its own amount
+            let height_offset = hash2d * (1.0 + 0.6 * g_engine.bass);
             var center_original_space = vec3f(center_xy.x, height_offset, center_xy.y * l + l2 * center_xy.x);
             var l3 = length(center_original_space.xz);
             center_original_space += UP * clamp(10.0 / (l3 * l3 * l3 * l3) - 1.0, 0.0, 2.1);
@@ -311,8 +374,13 @@ fn map_dir(p: vec3f, ray_direction: vec3f) -> f32 {
         }
     }
 
+    for (var i = 0; i < MAX_POWER_UPS; i = i + 1) {
+        d = min(d, power_up_distance(p, i));
+    }
+
     d = min(d, ground_distance_dir(p, ray_direction).d);
-    d = min(d, player_distance(p));
+    d = min(d, players_distance(p));
+    d = min(d, lane_outline_distance(p));
     return d;
 }
 
@@ -321,11 +389,15 @@ fn map(p: vec3f) -> f32 {
     var d = 1e20;
     
     d = min(d, ground_distance(p).d);
-    d = min(d, player_distance(p));
+    d = min(d, players_distance(p));
+    d = min(d, lane_outline_distance(p));
 
     for (var i = 0; i < g_obstacle_globals.count; i = i + 1) {
         d = min(d, obstacle_distance(p, i));
     }
+    for (var i = 0; i < MAX_POWER_UPS; i = i + 1) {
+        d = min(d, power_up_distance(p, i));
+    }
     return d;
 }
 
@@ -343,17 +415,31 @@ fn map_color(p: vec3f) -> i32 {
             rval = 0;
         }
     }
-    var player_dist = player_distance(p);
-    if d > player_dist {
-        d = player_dist;
-        rval = 1;
+    for (var i = 0; i < MAX_PLAYERS; i = i + 1) {
+        var player_dist = player_distance(p, i);
+        if d > player_dist {
+            d = player_dist;
+            rval = i32(g_game.players[i].material);
+        }
+    }
+    var outline_dist = lane_outline_distance(p);
+    if d > outline_dist {
+        d = outline_dist;
+        rval = 3;
     }
 
     for (var i = 0; i < g_obstacle_globals.count; i = i + 1) {
         var od = obstacle_distance(p, i);
         if d > od {
             d = od;
-            rval = 2;
+            rval = select(2, 4, g_obstacles[i].motion != 0u);
+        }
+    }
+    for (var i = 0; i < MAX_POWER_UPS; i = i + 1) {
+        var pd = power_up_distance(p, i);
+        if d > pd {
+            d = pd;
+            rval = i32(g_game.power_ups[i].z);
         }
     }
 
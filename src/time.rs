@@ -19,9 +19,41 @@ pub fn get_time_since_start() -> f64 {
 pub fn get_time_since_start() -> f64 {
     use std::time::Instant;
     use once_cell::sync::Lazy;
-    static START: Lazy<Instant> = Lazy::new(|| std::time::Instant::now());
+    static START: Lazy<Instant> = Lazy::new(Instant::now);
     START.elapsed().as_secs_f64()
 }
+#[cfg(target_arch = "wasm32")]
+// seed for random number generators, taken from the wall clock
+pub fn time_seed() -> u64 {
+    js_sys::Date::now() as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+// seed for random number generators, taken from the wall clock
+pub fn time_seed() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(target_arch = "wasm32")]
+// wall clock time as a unix timestamp in seconds
+pub fn unix_time() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+// wall clock time as a unix timestamp in seconds
+pub fn unix_time() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 const MAX_SAMPLES: usize = 100;
 
 /// Stores a ring buffer of frame/update times for FPS/UPS computations.
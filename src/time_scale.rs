@@ -0,0 +1,170 @@
+use crate::game::GameEvent;
+
+/// A change of the game speed, started by a `GameEvent`, see `TimeScale::effect`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeEffect {
+    /// Game seconds per wall-clock second to go to.
+    pub scale: f32,
+    /// Wall-clock seconds to ease to `scale`, 0 switches at once.
+    pub ease: f32,
+    /// Wall-clock seconds to hold `scale` before going back to the speed
+    /// before the effect, `None` keeps it until the next effect.
+    pub hold: Option<f32>,
+}
+
+/// Speed of game time relative to the wall clock, between `Engine::update`
+/// and `Game::update`.
+///
+/// The engine steps the game with the scaled frame duration, and the
+/// renderer animates with the scaled time so the shaders slow down with the
+/// game. Effects are started by the events of each game update: dying eases
+/// to slow motion, a near miss or a broken shield stops the game for a moment and going through
+/// a menu is back to full speed. Sound and music stay on the wall clock, and
+/// so does the level timeline while the music is its clock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeScale {
+    /// Eased part, the scale once `ease_time` reaches `ease`.
+    from: f32,
+    to: f32,
+    ease: f32,
+    ease_time: f32,
+    /// Short override on top of the eased scale, and the seconds it has left.
+    hold: Option<(f32, f32)>,
+    /// Game seconds since the start, for shader animation.
+    time: f32,
+}
+
+impl TimeScale {
+    /// Scale of the slow motion after a collision.
+    pub const DEATH_SCALE: f32 = 0.1;
+    /// Wall-clock seconds it takes to slow down to `DEATH_SCALE`.
+    pub const DEATH_EASE: f32 = 0.6;
+    /// Wall-clock seconds the game stops for on a near miss.
+    pub const HIT_STOP: f32 = 0.05;
+    const FULL_SPEED: TimeEffect = TimeEffect { scale: 1.0, ease: 0.0, hold: None };
+
+    pub fn new() -> Self {
+        Self { from: 1.0, to: 1.0, ease: 0.0, ease_time: 0.0, hold: None, time: 0.0 }
+    }
+
+    /// The effect `event` has on the game speed, if any.
+    pub fn effect(event: GameEvent) -> Option<TimeEffect> {
+        match event {
+            GameEvent::Collision => Some(TimeEffect { scale: Self::DEATH_SCALE, ease: Self::DEATH_EASE, hold: None }),
+            GameEvent::MenuSelect => Some(Self::FULL_SPEED),
+            GameEvent::NearMiss | GameEvent::ShieldBroken => {
+                Some(TimeEffect { scale: 0.0, ease: 0.0, hold: Some(Self::HIT_STOP) })
+            }
+            GameEvent::PatternStarted | GameEvent::PowerUpCollected(_) => None,
+        }
+    }
+
+    /// Starts the effects of the events of the last game update, they apply
+    /// from the next one.
+    pub fn handle_events(&mut self, events: &[GameEvent]) {
+        for effect in events.iter().filter_map(|&event| Self::effect(event)) {
+            self.start(effect);
+        }
+    }
+
+    pub fn start(&mut self, effect: TimeEffect) {
+        match effect.hold {
+            Some(seconds) => self.hold = Some((effect.scale, seconds)),
+            None => {
+                self.from = self.eased_scale();
+                self.to = effect.scale;
+                self.ease = effect.ease;
+                self.ease_time = 0.0;
+                self.hold = None;
+            }
+        }
+    }
+
+    /// Back to full speed at once, for a new game.
+    pub fn reset(&mut self) {
+        self.start(Self::FULL_SPEED);
+    }
+
+    /// Game seconds per wall-clock second right now.
+    pub fn scale(&self) -> f32 {
+        self.hold.map_or_else(|| self.eased_scale(), |(scale, _)| scale)
+    }
+
+    /// Game seconds since the start.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Advances the effects by `dt` wall-clock seconds and returns the game
+    /// seconds that pass meanwhile.
+    pub fn update(&mut self, dt: f32) -> f32 {
+        let game_dt = dt * self.scale();
+        self.time += game_dt;
+        self.ease_time = (self.ease_time + dt).min(self.ease);
+        if let Some((_, seconds)) = &mut self.hold {
+            *seconds -= dt;
+            if *seconds <= 0.0 {
+                self.hold = None;
+            }
+        }
+        game_dt
+    }
+
+    fn eased_scale(&self) -> f32 {
+        if self.ease_time >= self.ease {
+            return self.to;
+        }
+        let t = self.ease_time / self.ease;
+        self.from + (self.to - self.from) * t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    #[test]
+    fn collision_eases_to_slow_motion_until_a_menu_key() {
+        let mut time_scale = TimeScale::new();
+        assert_eq!(time_scale.update(DT), DT);
+        time_scale.handle_events(&[GameEvent::PatternStarted]);
+        assert_eq!(time_scale.scale(), 1.0);
+
+        time_scale.handle_events(&[GameEvent::Collision]);
+        let mut previous = time_scale.scale();
+        for _ in 0..(TimeScale::DEATH_EASE / DT) as usize + 2 {
+            let game_dt = time_scale.update(DT);
+            assert!(game_dt <= previous * DT && game_dt >= TimeScale::DEATH_SCALE * DT);
+            previous = time_scale.scale();
+        }
+        assert_eq!(time_scale.scale(), TimeScale::DEATH_SCALE);
+        assert!((time_scale.update(DT) - TimeScale::DEATH_SCALE * DT).abs() < 1e-9);
+
+        time_scale.handle_events(&[GameEvent::MenuSelect]);
+        assert_eq!(time_scale.update(DT), DT);
+
+        // a near miss stops the game for a few frames
+        time_scale.handle_events(&[GameEvent::NearMiss]);
+        assert_eq!(time_scale.update(DT), 0.0);
+        assert!((0..10).map(|_| time_scale.update(DT)).any(|game_dt| game_dt == DT));
+    }
+
+    #[test]
+    fn hold_overrides_the_scale_and_goes_back() {
+        let mut time_scale = TimeScale::new();
+        time_scale.start(TimeEffect { scale: 0.0, ease: 0.0, hold: Some(2.5 * DT) });
+        for _ in 0..3 {
+            assert_eq!(time_scale.update(DT), 0.0);
+        }
+        assert_eq!(time_scale.update(DT), DT);
+        assert!((time_scale.time() - DT).abs() < 1e-9);
+    }
+}